@@ -3,7 +3,7 @@ use cgmath::{EuclideanSpace, Matrix3, Matrix4, Point3, Rad, Vector3};
 use crate::shaders;
 use crate::Graph;
 
-use crate::components::{CameraBundle, CameraId, Position};
+use crate::components::{CameraBundle, CameraId, Position, WorldOrigin};
 use crate::ecs::Ecs;
 use bevy_ecs::entity::Entity;
 use bevy_ecs::world::World;
@@ -14,6 +14,10 @@ pub struct Camera {
 }
 
 impl Camera {
+  // near and far planes of proj, the shadow cascades split the range between them
+  pub const NEAR: f32 = 0.1;
+  pub const FAR: f32 = 200.0;
+
   pub fn new(ecs: &mut Ecs) -> Self {
     let camera_entity = ecs
       .world
@@ -51,6 +55,15 @@ impl Camera {
       .point3
   }
 
+  pub fn set_pos(&self, world: &mut World, point3: Point3<f32>) {
+    world
+      .get_entity_mut(self.camera_entity)
+      .unwrap()
+      .get_mut::<Position>()
+      .unwrap()
+      .point3 = point3;
+  }
+
   pub fn proj(&self, graph: &Graph, world: &World) -> shaders::main::vs::ty::Data {
     let pos = world
       .get_entity(self.camera_entity)
@@ -72,7 +85,8 @@ impl Camera {
     // note: this teapot was meant for OpenGL where the origin is at the lower left
     //       instead the origin is at the upper left in, Vulkan, so we reverse the Y axis
     let aspect_ratio = graph.dimensions[0] as f32 / graph.dimensions[1] as f32;
-    let mut proj = cgmath::perspective(Rad(std::f32::consts::FRAC_PI_2), aspect_ratio, 0.1, 200.0);
+    let mut proj =
+      cgmath::perspective(Rad(std::f32::consts::FRAC_PI_2), aspect_ratio, Camera::NEAR, Camera::FAR);
 
     // flipping the "horizontal" projection bit
     proj[0][0] = -proj[0][0];
@@ -96,6 +110,15 @@ impl Camera {
     }
   }
 
+  // for things placed in absolute coordinates, which do not follow the origin shifts
+  pub fn proj_absolute(&self, graph: &Graph, world: &World) -> shaders::main::vs::ty::Data {
+    let mut data = self.proj(graph, world);
+    let origin = world.get_resource::<WorldOrigin>().unwrap();
+    let to_local = Matrix4::from_translation(-origin.offset_f32());
+    data.world = (to_local * Matrix4::from(data.world)).into();
+    data
+  }
+
   pub fn proj_skybox(&self, graph: &Graph, world: &World) -> shaders::skybox::vs::ty::Data {
     let pos = world
       .get_entity(self.camera_entity)
@@ -154,6 +177,11 @@ impl Camera {
       .unwrap()
       .point3;
     //let camera_id = world.get_entity(self.camera_entity).unwrap().get::<CameraId>().unwrap();
-    format!("camera {:?}", pos)
+    let origin = world.get_resource::<WorldOrigin>().unwrap();
+    let absolute = origin.absolute(pos);
+    format!(
+      "camera {:?} absolute ({:.1}, {:.1}, {:.1})",
+      pos, absolute.x, absolute.y, absolute.z
+    )
   }
 }
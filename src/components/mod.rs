@@ -2,6 +2,8 @@ use bevy_ecs::bundle::Bundle;
 use cgmath::{Array, Point2, Point3, Vector3};
 use gltf::accessor::sparse::Indices;
 
+use std::fmt;
+
 use crate::game::Game;
 
 #[derive()]
@@ -138,3 +140,71 @@ impl Default for GameState {
     }
   }
 }
+
+// the camera and everything generated around it live in f32 coordinates
+// relative to this origin, which is moved along with the camera
+#[derive(Debug)]
+pub struct WorldOrigin {
+  pub offset: Vector3<f64>,
+}
+
+impl Default for WorldOrigin {
+  fn default() -> WorldOrigin {
+    WorldOrigin {
+      offset: Vector3::from_value(0.0),
+    }
+  }
+}
+
+impl WorldOrigin {
+  pub fn absolute(&self, local: Point3<f32>) -> Point3<f64> {
+    Point3::new(local.x as f64, local.y as f64, local.z as f64) + self.offset
+  }
+
+  pub fn shift(&mut self, by: Vector3<f32>) {
+    self.offset += Vector3::new(by.x as f64, by.y as f64, by.z as f64);
+  }
+
+  // loses precision far from the absolute origin, where anything placed
+  // relative to the absolute origin is out of view anyway
+  pub fn offset_f32(&self) -> Vector3<f32> {
+    Vector3::new(
+      self.offset.x as f32,
+      self.offset.y as f32,
+      self.offset.z as f32,
+    )
+  }
+}
+
+// hours since midnight, drives the sun and the sky, see render::Daylight
+#[derive(Debug)]
+pub struct TimeOfDay {
+  pub hours: f32,
+  // real seconds a whole day takes
+  pub day_length: f32,
+  // stays at hours, headless renders are the same every run
+  pub frozen: bool,
+}
+
+impl Default for TimeOfDay {
+  fn default() -> TimeOfDay {
+    TimeOfDay {
+      hours: 10.0,
+      day_length: 600.0,
+      frozen: false,
+    }
+  }
+}
+
+impl TimeOfDay {
+  pub fn advance(&mut self, hours: f32) {
+    self.hours = (self.hours + hours).rem_euclid(24.0);
+  }
+}
+
+impl fmt::Display for TimeOfDay {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let minutes = (self.hours * 60.0) as u32;
+    write!(f, "{:02}:{:02}", minutes / 60 % 24, minutes % 60)
+  }
+}
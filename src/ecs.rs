@@ -23,6 +23,8 @@ impl Ecs {
     world.insert_resource(Events::<InputEvent>::default());
 
     world.insert_resource(GameState::default());
+    world.insert_resource(WorldOrigin::default());
+    world.insert_resource(TimeOfDay::default());
 
     let mut schedule = Schedule::default();
     schedule.add_stage(
@@ -34,10 +36,19 @@ impl Ecs {
         .with_system(camera_emits_position_changed_event.system())
         .with_system(input_state_from_game_events.system())
         .with_system(game_reacts_to_keyboard.system())
+        .with_system(capture_reacts_to_keyboard.system())
+        .with_system(view_reacts_to_keyboard.system())
+        .with_system(time_of_day_advances.system())
         .with_system(camera_reacts_to_input.system())
         .with_system(velocity_accel.system())
         .with_system(movement.system()),
     );
+    // after everything moved, so nothing sees the camera between a move and a shift
+    schedule.add_stage_after(
+      "update",
+      "origin",
+      SystemStage::single(rebase_origin.system()),
+    );
 
     let ecs = Ecs { world, schedule };
     ecs
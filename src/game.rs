@@ -1,10 +1,18 @@
 use crate::input::GameEvent;
 use crate::input::GameWantsExitEvent;
+use crate::input::CaptureEvent;
 use crate::input::InputEvent;
+use crate::input::ViewEvent;
 use bevy_ecs::event::ManualEventReader;
-use cgmath::Point3;
+use cgmath::{Matrix4, Point3, Vector3};
 use profiling;
-use vulkano::command_buffer::{AutoCommandBufferBuilder, CommandBufferUsage, SubpassContents};
+use vulkano::command_buffer::{
+  AutoCommandBufferBuilder, CommandBufferUsage, DynamicState, PrimaryAutoCommandBuffer,
+  SubpassContents,
+};
+use vulkano::descriptor::descriptor_set::{DescriptorSet, DescriptorSetsCollection};
+use vulkano::format::ClearValue;
+use vulkano::pipeline::GraphicsPipelineAbstract;
 use vulkano::swapchain;
 use vulkano::swapchain::AcquireError;
 use vulkano::sync;
@@ -12,19 +20,28 @@ use vulkano::sync::{FlushError, GpuFuture};
 use vulkano_text::DrawTextTrait;
 
 use std::boxed::Box;
+use std::cell::RefCell;
 use std::path::Path;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::mpsc::Receiver;
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
 use std::sync::Arc;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime};
 use std::vec::Vec;
 
 use crate::camera::Camera;
+use crate::components::{TimeOfDay, WorldOrigin};
 use crate::ecs::Ecs;
 use crate::executor::Executor;
 use crate::myworld::MyWorld;
+use crate::render::{readback_buffer, save_png, Capture, Readbacks};
+use crate::render::{Frustum, Instance, InstanceCulling, InstancedModel};
+use crate::render::{Cascades, Daylight, CASCADES};
+use crate::render::CubeFaces;
+use crate::render::{DebugLines, DebugView, RenderMode, CYAN, KEEPS_VERTICES, YELLOW};
+use crate::render::{save_pipeline_cache, FileWatcher, ReloadableShaders};
 use crate::render::System;
 use crate::render::Textures;
+use crate::shaders;
 use crate::sign_post::SignPost;
 use crate::sounds::{Sounds, NoSounds};
 use crate::things::CountingWindowAvg;
@@ -32,9 +49,65 @@ use crate::things::Lap;
 use crate::things::Texts;
 use crate::things::{PrimitiveCube, PrimitiveTriangle};
 use crate::Graph;
+use crate::Target;
 use crate::Model;
 use crate::Settings;
 
+// draws of the last frame, models outside the camera frustum are culled
+#[derive(Debug, Default, Copy, Clone)]
+struct DrawStats {
+  drawn: usize,
+  culled: usize,
+}
+
+impl DrawStats {
+  fn draw<S>(
+    &mut self,
+    model: &Model,
+    frustum: &Frustum,
+    builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+    dynamic: &DynamicState,
+    set: S,
+  ) where
+    S: DescriptorSetsCollection,
+  {
+    if model.is_visible(frustum) {
+      self.drawn += 1;
+      model.draw_indexed(builder, pipeline, dynamic, set);
+    } else {
+      self.culled += 1;
+    }
+  }
+
+  // instances are culled one by one, see InstanceCulling, the visible ones are drawn with a
+  // single call
+  fn draw_instanced<S>(
+    &mut self,
+    model: &InstancedModel,
+    instances: &[Instance],
+    visible: &[Instance],
+    builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    system: &System,
+    set: S,
+  ) where
+    S: DescriptorSetsCollection,
+  {
+    self.culled += instances.len() - visible.len();
+    if visible.is_empty() {
+      return;
+    }
+    self.drawn += visible.len();
+    model.draw_instanced(
+      builder,
+      system.pipeline_instanced.clone(),
+      &system.dynamic_state,
+      set,
+      system.instances(visible.to_vec()),
+    );
+  }
+}
+
 pub struct Game {
   ecs: Ecs,
   events_reader: Option<ManualEventReader<GameEvent>>,
@@ -44,6 +117,22 @@ pub struct Game {
   settings: Settings,
   graph: Graph,
   sounds: Box<dyn Sounds>,
+  executor: Executor,
+  capture: Capture,
+  readbacks: Readbacks,
+  // one for each of the static and then the other instanced models of myworld
+  instance_culling: RefCell<Vec<InstanceCulling>>,
+  // the same for the shadow map of each cascade
+  shadow_culling: RefCell<Vec<Vec<InstanceCulling>>>,
+  // index into Settings::skyboxes
+  skybox: usize,
+  skybox_loading: Option<Receiver<image::ImageResult<CubeFaces>>>,
+  debug_view: DebugView,
+  render_mode: RenderMode,
+  // the glsl files of ReloadableShaders, only watched with Settings::shader_hot_reload
+  shader_watcher: Option<FileWatcher>,
+  // the compiler output of the last reload that failed, shown under the status
+  shader_error: Option<String>,
 
   myworld: MyWorld,
   recreate_swapchain: bool,
@@ -51,6 +140,7 @@ pub struct Game {
   models: Vec<Model>,
   i_frame: u64,
   last_frame_took: u32,
+  draw_stats: DrawStats,
 
   pub game_exited: Arc<AtomicBool>,
   frame_times_avg: CountingWindowAvg,
@@ -66,6 +156,11 @@ impl Game {
     recv: Receiver<InputEvent>,
   ) -> Game {
     let mut ecs = Ecs::new();
+    ecs.world.insert_resource(TimeOfDay {
+      hours: settings.start_hour,
+      day_length: settings.day_length,
+      frozen: false,
+    });
 
     let camera = Camera::new(&mut ecs);
 
@@ -75,7 +170,6 @@ impl Game {
     let mut sign_posts = vec![];
     for i in -200..200 {
       sign_posts.push(SignPost::new(
-        &graph.device,
         Point3::new(i as f32, -2.0, 0.0),
         i.to_string(),
         &texts,
@@ -84,7 +178,6 @@ impl Game {
 
     for i in -200..200 {
       sign_posts.push(SignPost::new(
-        &graph.device,
         Point3::new(-2.0, i as f32, 0.0),
         i.to_string(),
         &texts,
@@ -93,34 +186,38 @@ impl Game {
 
     for i in -200..200 {
       sign_posts.push(SignPost::new(
-        &graph.device,
         Point3::new(-2.0, -2.0, i as f32),
         i.to_string(),
         &texts,
       ));
     }
 
-    let myworld = MyWorld::new(settings.clone(), executor, &graph, sign_posts);
+    let myworld = MyWorld::new(settings.clone(), executor.clone(), &graph, sign_posts);
 
     let recreate_swapchain = false;
 
     let mut models = vec![];
+    // copies into device local memory, the first frame waits for them
+    let mut uploads: Vec<Box<dyn GpuFuture>> = vec![];
     if settings.dog_enabled {
-      models.push(Model::from_gltf(Path::new("models/dog.glb"), &graph.device));
+      for (model, upload) in Model::from_gltf(Path::new("models/dog.glb"), &graph.queue) {
+        models.push(model);
+        uploads.push(upload.boxed());
+      }
     };
     if settings.box_enabled {
-      models.push(
-        PrimitiveCube::new(2.0, 4.0, 8.0, (-8.0, 0.0, 0.0))
-          .mesh
-          .get_buffers(&graph.device),
-      );
+      let (model, upload) = PrimitiveCube::new(2.0, 4.0, 8.0, (-8.0, 0.0, 0.0))
+        .mesh
+        .upload_buffers(&graph.queue);
+      models.push(model);
+      uploads.push(upload.boxed());
     };
     if settings.box_enabled {
-      models.push(
-        PrimitiveTriangle::new(Point3::new(10.0, 0.0, 0.0))
-          .mesh
-          .get_buffers(&graph.device),
-      );
+      let (model, upload) = PrimitiveTriangle::new(Point3::new(10.0, 0.0, 0.0))
+        .mesh
+        .upload_buffers(&graph.queue);
+      models.push(model);
+      uploads.push(upload.boxed());
     };
     if settings.lap_enabled {
       models.push(Lap::new(&graph.device).model);
@@ -128,9 +225,13 @@ impl Game {
 
     let textures = Textures::new(&texts);
 
-    let (system, system_future) = System::new(&graph, textures);
+    let (system, system_future) = System::new(&graph, textures, &startup_skybox(&settings));
 
-    let previous_frame_end = Some(system_future);
+    let previous_frame_end = Some(
+      uploads
+        .into_iter()
+        .fold(system_future, |future, upload| future.join(upload).boxed()),
+    );
 
     let last_frame_took = 0;
 
@@ -138,12 +239,31 @@ impl Game {
 
     let sounds = Box::new(NoSounds::new());
 
+    let capture = Capture::new(settings.capture_dir.clone());
+    let debug_view = settings.debug;
+    let shader_watcher = if settings.shader_hot_reload {
+      Some(FileWatcher::new(ReloadableShaders::paths()))
+    } else {
+      None
+    };
+
     Game {
       ecs,
       events_reader: None,
       settings,
       graph,
       sounds,
+      executor,
+      capture,
+      readbacks: Readbacks::new(),
+      instance_culling: RefCell::new(vec![]),
+      shadow_culling: RefCell::new(vec![]),
+      skybox: 0,
+      skybox_loading: None,
+      debug_view,
+      render_mode: RenderMode::default(),
+      shader_watcher,
+      shader_error: None,
       camera,
       myworld,
       recreate_swapchain,
@@ -152,6 +272,7 @@ impl Game {
       system,
       i_frame: 0,
       last_frame_took,
+      draw_stats: DrawStats::default(),
       game_exited,
       frame_times_avg,
       recv,
@@ -172,6 +293,16 @@ impl Game {
         profiling::finish_frame!();
       }
     }
+    self.save_pipeline_cache();
+  }
+
+  // the next run builds its pipelines from what this one built
+  pub fn save_pipeline_cache(&self) {
+    if let Some(path) = &self.settings.pipeline_cache {
+      if let Err(e) = save_pipeline_cache(&self.graph.pipeline_cache, path) {
+        println!("failed to save pipeline cache {:?} {:?}", path, e);
+      }
+    }
   }
 
   #[profiling::function]
@@ -192,6 +323,10 @@ impl Game {
       self.previous_frame_end.as_mut().unwrap().cleanup_finished();
       //}
     }
+    self.save_captures();
+    self.join_uploads();
+    self.swap_skybox();
+    self.reload_shaders();
     if self.recreate_swapchain {
       profiling::scope!("recreate_swap_chain");
       self.previous_frame_end.as_mut().unwrap().cleanup_finished();
@@ -200,26 +335,15 @@ impl Game {
       self.recreate_swapchain = false;
     }
 
-    let set = {
-      profiling::scope!("main_set");
-      self.system.main_set(
-        self.camera.proj(&self.graph, &self.ecs.world),
-        self.myworld.get_scenes(),
-        self.camera.get_pos(&self.ecs.world),
-      )
-    };
-
-    let set_skybox = {
-      profiling::scope!("sky_box_set");
-      self
-        .system
-        .skybox_set(self.camera.proj_skybox(&self.graph, &self.ecs.world))
+    let swapchain = match &self.graph.target {
+      Target::Window(window) => window.swapchain.clone(),
+      Target::Offscreen(_) => panic!("draw needs a window, use render_offscreen"),
     };
 
     let (image_num, suboptimal, acquire_future) = {
       profiling::scope!("acquire_next_image");
       let (image_num, suboptimal, acquire_future) =
-        match swapchain::acquire_next_image(self.graph.swapchain.clone(), None) {
+        match swapchain::acquire_next_image(swapchain.clone(), None) {
           Ok(r) => r,
           Err(AcquireError::OutOfDate) => {
             self.recreate_swapchain = true;
@@ -240,31 +364,415 @@ impl Game {
       CommandBufferUsage::OneTimeSubmit,
     )
     .unwrap();
+    self.draw_stats = self.record_frame(&mut builder, image_num);
+    // copied before the status text is drawn over the frame
+    let capture = if self.capture.is_active() {
+      profiling::scope!("capture");
+      let paths = self.capture.next_frame(SystemTime::now());
+      let dimensions = swapchain.dimensions();
+      let buffer = self.readbacks.buffer(&self.graph.device, dimensions);
+      if let Target::Window(window) = &self.graph.target {
+        builder
+          .copy_image_to_buffer(window.images[image_num].clone(), buffer.clone())
+          .unwrap();
+      }
+      Some((paths, dimensions, swapchain.format(), buffer))
+    } else {
+      None
+    };
+    {
+      profiling::scope!("draw-text");
+      let status = self.status_string();
+      if let Target::Window(window) = &mut self.graph.target {
+        let mut y = 50.0;
+        for line in status.split('\n') {
+          window
+            .draw_text
+            .queue_text(200.0, y, 40.0, [1.0, 1.0, 1.0, 1.0], line);
+          y += 40.0;
+        }
+        builder.draw_text(&mut window.draw_text, image_num);
+      }
+    }
+    let command_buffer = builder.build().unwrap();
+
+    let future = self
+      .previous_frame_end
+      .take()
+      .unwrap()
+      .join(acquire_future)
+      .then_execute(self.graph.queue.clone(), command_buffer)
+      .unwrap()
+      .then_swapchain_present(self.graph.queue.clone(), swapchain, image_num)
+      .then_signal_fence_and_flush();
+
+    match future {
+      Ok(future) => {
+        if let Some((paths, dimensions, format, buffer)) = capture {
+          self.readbacks.push(paths, dimensions, format, buffer);
+        }
+        self.previous_frame_end = Some(future.boxed());
+      }
+      Err(FlushError::OutOfDate) => {
+        self.recreate_swapchain = true;
+        self.previous_frame_end = Some(sync::now(self.graph.device.clone()).boxed());
+      }
+      Err(e) => {
+        println!("Failed to flush future: {:?}", e);
+        self.previous_frame_end = Some(sync::now(self.graph.device.clone()).boxed());
+      }
+    }
+    let frame_end = Instant::now();
+    let last_frame = (frame_end - frame_start).as_millis() as u32;
+    self.last_frame_took = last_frame;
+    self.frame_times_avg.add(last_frame);
+  }
+
+  // renders one frame into the offscreen target and saves it as a png,
+  // there is no text overlay as that needs a swapchain
+  #[profiling::function]
+  pub fn render_offscreen(&mut self, path: &Path) -> image::ImageResult<()> {
+    let image = match &self.graph.target {
+      Target::Offscreen(image) => image.clone(),
+      Target::Window(_) => panic!("render_offscreen needs an offscreen graph"),
+    };
+    let buffer = readback_buffer(&self.graph.device, self.graph.dimensions);
+    self.join_uploads();
+
+    let mut builder = AutoCommandBufferBuilder::primary(
+      self.graph.device.clone(),
+      self.graph.queue.family(),
+      CommandBufferUsage::OneTimeSubmit,
+    )
+    .unwrap();
+    self.draw_stats = self.record_frame(&mut builder, 0);
+    builder.copy_image_to_buffer(image, buffer.clone()).unwrap();
+    let command_buffer = builder.build().unwrap();
+
+    self
+      .previous_frame_end
+      .take()
+      .unwrap()
+      .then_execute(self.graph.queue.clone(), command_buffer)
+      .unwrap()
+      .then_signal_fence_and_flush()
+      .unwrap()
+      .wait(None)
+      .unwrap();
+    self.previous_frame_end = Some(sync::now(self.graph.device.clone()).boxed());
+
+    let data = buffer.read().unwrap();
+    save_png(path, self.graph.dimensions, Graph::OFFSCREEN_FORMAT, &data)
+  }
+
+  // the sky tiles generated since the last frame are copied before it draws them
+  #[profiling::function]
+  fn join_uploads(&mut self) {
+    let uploads = self.myworld.take_uploads();
+    if uploads.is_empty() {
+      return;
+    }
+    let previous = self.previous_frame_end.take().unwrap();
+    self.previous_frame_end = Some(
+      uploads
+        .into_iter()
+        .fold(previous, |future, upload| future.join(upload).boxed()),
+    );
+  }
+
+  // frames captured a frame or more ago, encoding is slow so it is kept off the game loop
+  fn save_captures(&mut self) {
+    for readback in self.readbacks.finished() {
+      self
+        .executor
+        .do_background(async move {
+          for path in &readback.paths {
+            let (dimensions, format) = (readback.dimensions, readback.format);
+            if let Err(e) = save_png(path, dimensions, format, &readback.data) {
+              println!("failed to save {:?} {:?}", path, e);
+            }
+          }
+        })
+        .forget();
+    }
+  }
+
+  // the faces are loaded and converted in the background, see swap_skybox
+  fn next_skybox(&mut self) {
+    if self.settings.skyboxes.is_empty() || self.skybox_loading.is_some() {
+      return;
+    }
+    self.skybox = (self.skybox + 1) % self.settings.skyboxes.len();
+    let source = self.settings.skyboxes[self.skybox].clone();
+    let (send, recv) = channel();
+    self
+      .executor
+      .do_background(async move {
+        send.send(CubeFaces::load(&source)).ok();
+      })
+      .forget();
+    self.skybox_loading = Some(recv);
+  }
+
+  // the pipelines are rebuilt with the new shaders, the last good ones stay when compiling or
+  // building the pipelines fails
+  fn reload_shaders(&mut self) {
+    let changed = match &mut self.shader_watcher {
+      Some(watcher) => watcher.changed(),
+      None => false,
+    };
+    if !changed {
+      return;
+    }
+    profiling::scope!("reload_shaders");
+    match ReloadableShaders::compile(self.graph.device.clone()) {
+      Ok(shaders) => {
+        let previous = std::mem::replace(&mut self.graph.shaders, shaders);
+        self.shader_error = match self.system.rebuild_pipelines(&self.graph) {
+          Ok(()) => None,
+          Err(e) => {
+            // the shaders of the pipelines that are still in use
+            self.graph.shaders = previous;
+            Some(format!("building pipelines: {}", e))
+          }
+        };
+      }
+      Err(e) => {
+        println!("failed to reload shaders {}", e);
+        self.shader_error = Some(e);
+      }
+    }
+  }
+
+  fn swap_skybox(&mut self) {
+    let loaded = match self.skybox_loading.as_ref().map(|recv| recv.try_recv()) {
+      Some(Ok(loaded)) => loaded,
+      Some(Err(TryRecvError::Disconnected)) => {
+        self.skybox_loading = None;
+        return;
+      }
+      Some(Err(TryRecvError::Empty)) | None => return,
+    };
+    self.skybox_loading = None;
+    match loaded {
+      Ok(faces) => {
+        let upload = self.system.set_skybox(&self.graph, &faces);
+        let previous = self.previous_frame_end.take().unwrap();
+        self.previous_frame_end = Some(previous.join(upload).boxed());
+      }
+      Err(e) => println!(
+        "failed to load skybox {:?} {:?}",
+        self.settings.skyboxes[self.skybox], e
+      ),
+    }
+  }
+
+  // all the passes of a frame, from the models to the skybox composite,
+  // image_num is the final image the post passes write into
+  #[profiling::function]
+  fn record_frame(
+    &self,
+    builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    image_num: usize,
+  ) -> DrawStats {
+    let proj = self.camera.proj(&self.graph, &self.ecs.world);
+    let proj_absolute = self.camera.proj_absolute(&self.graph, &self.ecs.world);
+    let frustum = frustum_of(&proj);
+    let frustum_static = frustum_of(&proj_absolute);
+    let mut stats = DrawStats::default();
+    let daylight = Daylight::at(self.ecs.world.get_resource::<TimeOfDay>().unwrap().hours);
+    let cascades = Cascades::fit(
+      Matrix4::from(proj.proj) * Matrix4::from(proj.view),
+      Camera::NEAR,
+      Camera::FAR,
+      daylight.sun_direction,
+    );
+    let camera_position = self.camera.get_pos(&self.ecs.world);
+    let origin = self.ecs.world.get_resource::<WorldOrigin>().unwrap();
+    let fog = self.settings.fog.at_origin(origin.offset_f32());
+    let horizon_fog = fog.amount(
+      camera_position,
+      camera_position + Vector3::new(Camera::FAR, 0.0, 0.0),
+    );
+
+    let set = {
+      profiling::scope!("main_set");
+      self.system.main_set(
+        proj,
+        self.myworld.get_scenes(),
+        camera_position,
+        &cascades,
+        &fog,
+        &daylight,
+      )
+    };
+
+    // static models are not moved with the origin, their camera and fog are the absolute ones
+    let set_static = {
+      profiling::scope!("static_set");
+      self.system.main_set(
+        proj_absolute,
+        self.myworld.get_scenes(),
+        camera_position + origin.offset_f32(),
+        &cascades,
+        &self.settings.fog,
+        &daylight,
+      )
+    };
+
+    let (set_mode, set_mode_static) = {
+      profiling::scope!("mode_set");
+      (self.system.mode_set(proj), self.system.mode_set(proj_absolute))
+    };
+
+    // terrain and instances are drawn with the default material
+    let set_material = {
+      profiling::scope!("material_set");
+      self.system.material_set(None)
+    };
+
+    let set_water = {
+      profiling::scope!("water_set");
+      self
+        .system
+        .water_set(self.camera.proj(&self.graph, &self.ecs.world), &daylight)
+    };
+
+    let set_skybox = {
+      profiling::scope!("sky_box_set");
+      self.system.skybox_set(
+        self.camera.proj_skybox(&self.graph, &self.ecs.world),
+        &fog,
+        horizon_fog,
+        &daylight,
+        self.settings.clear_color,
+      )
+    };
+
+    {
+      profiling::scope!("shadow-passes");
+      self.record_shadows(builder, &cascades, &proj, &proj_absolute);
+    }
     {
       profiling::scope!("begin-render-pass");
+      let [r, g, b] = self.settings.clear_color;
+      let mut clear_values = vec![
+        [r, g, b, 1.0].into(),
+        1f32.into(),
+        // nothing covers the pixels yet, see render::coverage_blending
+        [r, g, b, 0.0].into(),
+      ];
+      if self.graph.samples > 1 {
+        // the resolved color
+        clear_values.push(ClearValue::None);
+      }
+      clear_values.push(1f32.into());
       builder
         .begin_render_pass(
-          self.system.framebuffers[image_num].clone(),
+          self.system.framebuffer.clone(),
           SubpassContents::Inline,
-          vec![
-            [0.0, 0.0, 0.0, 1.0].into(),
-            1f32.into(),
-            [0.0, 0.0, 0.0, 1.0].into(),
-            1f32.into(),
-          ],
+          clear_values,
         )
         .unwrap();
     }
     {
       profiling::scope!("iterate-models");
       for model in &self.models {
-        model.draw_indexed(&mut builder, self.system.pipeline.clone(), set.clone());
+        self.draw_model(
+          &mut stats,
+          model,
+          &frustum_static,
+          builder,
+          (set_static.clone(), self.system.material_set(model.material())),
+          set_mode_static.clone(),
+        );
       }
     }
     {
       profiling::scope!("iterate-myworld-models");
       for model in self.myworld.get_models() {
-        model.draw_indexed(&mut builder, self.system.pipeline.clone(), set.clone());
+        self.draw_model(
+          &mut stats,
+          &model,
+          &frustum,
+          builder,
+          (set.clone(), set_material.clone()),
+          set_mode.clone(),
+        );
+      }
+    }
+    {
+      profiling::scope!("iterate-instanced");
+      let (statics, others) = (
+        self.myworld.get_static_instanced(),
+        self.myworld.get_instanced(),
+      );
+      let mut culling = self.instance_culling.borrow_mut();
+      culling.resize_with(statics.len() + others.len(), InstanceCulling::default);
+      let mut cullings = culling.iter_mut();
+      for (model, instances) in statics {
+        let visible = cullings.next().unwrap().visible(model.aabb(), &instances, &frustum_static);
+        stats.draw_instanced(
+          model,
+          &instances,
+          visible,
+          builder,
+          &self.system,
+          (set_static.clone(), set_material.clone()),
+        );
+      }
+      for (model, instances) in others {
+        let visible = cullings.next().unwrap().visible(model.aabb(), &instances, &frustum);
+        stats.draw_instanced(
+          model,
+          &instances,
+          visible,
+          builder,
+          &self.system,
+          (set.clone(), set_material.clone()),
+        );
+      }
+    }
+    {
+      profiling::scope!("debug-lines");
+      let lines = self.debug_lines(&frustum, &frustum_static);
+      if let Some(buffer) = self.system.lines(&lines) {
+        builder
+          .draw(
+            self.system.pipeline_debug.clone(),
+            &self.system.dynamic_state,
+            vec![buffer],
+            self.system.debug_set(proj),
+            (),
+            vec![],
+          )
+          .unwrap();
+      }
+    }
+    {
+      profiling::scope!("iterate-myworld-water");
+      for model in self.myworld.get_water_models() {
+        stats.draw(
+          &model,
+          &frustum,
+          builder,
+          self.system.pipeline_water.clone(),
+          &self.system.dynamic_state,
+          set_water.clone(),
+        );
+      }
+    }
+    {
+      profiling::scope!("iterate-myworld-clouds");
+      for model in self.myworld.get_cloud_models() {
+        stats.draw(
+          &model,
+          &frustum,
+          builder,
+          self.system.pipeline_clouds.clone(),
+          &self.system.dynamic_state,
+          (set.clone(), set_material.clone()),
+        );
       }
     }
     builder.next_subpass(SubpassContents::Inline).unwrap();
@@ -272,79 +780,209 @@ impl Game {
       profiling::scope!("iterate-myworld-models");
       for model in self.myworld.get_models_skybox() {
         model.draw_indexed(
-          &mut builder,
+          builder,
           self.system.pipeline_skybox.clone(),
+          &self.system.dynamic_state,
           set_skybox.clone(),
         );
       }
     }
     builder.end_render_pass().unwrap();
     {
-      profiling::scope!("draw-text");
-      let mut y = 50.0;
-      let status = self.status_string();
-      for line in status.split('\n') {
-        self
-          .graph
-          .draw_text
-          .queue_text(200.0, y, 40.0, [1.0, 1.0, 1.0, 1.0], line);
-        y += 40.0;
+      profiling::scope!("post-passes");
+      self.system.post.record(builder, image_num, &self.settings.post);
+    }
+    stats
+  }
+
+  // with the main pipeline, or the one of render_mode replacing it, instanced models stay solid
+  fn draw_model<S>(
+    &self,
+    stats: &mut DrawStats,
+    model: &Model,
+    frustum: &Frustum,
+    builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    set: S,
+    set_mode: Arc<dyn DescriptorSet + Sync + Send>,
+  ) where
+    S: DescriptorSetsCollection,
+  {
+    let pipelines = &self.system.mode_pipelines;
+    let dynamic = &self.system.dynamic_state;
+    match pipelines.replacing(self.render_mode) {
+      Some(pipeline) => stats.draw(model, frustum, builder, pipeline, dynamic, set_mode.clone()),
+      None => stats.draw(model, frustum, builder, self.system.pipeline.clone(), dynamic, set),
+    }
+    // counted by the solid draw already
+    if let Some(pipeline) = pipelines.overlay(self.render_mode) {
+      if model.is_visible(frustum) {
+        model.draw_indexed(builder, pipeline, dynamic, set_mode);
       }
-      builder.draw_text(&mut self.graph.draw_text, image_num);
     }
-    let command_buffer = builder.build().unwrap();
+  }
 
-    let future = self
-      .previous_frame_end
-      .take()
-      .unwrap()
-      .join(acquire_future)
-      .then_execute(self.graph.queue.clone(), command_buffer)
-      .unwrap()
-      .then_swapchain_present(
-        self.graph.queue.clone(),
-        self.graph.swapchain.clone(),
-        image_num,
-      )
-      .then_signal_fence_and_flush();
+  // what debug_view asks for this frame, relative to the world origin like the myworld models
+  #[profiling::function]
+  fn debug_lines(&self, frustum: &Frustum, frustum_static: &Frustum) -> DebugLines {
+    let view = self.debug_view;
+    let mut lines = DebugLines::default();
+    if view.sky_grid {
+      self.myworld.debug_sky_tiles(&mut lines);
+    }
+    for model in self.myworld.get_models() {
+      if model.is_visible(frustum) {
+        debug_model(&mut lines, &model, &view);
+      }
+    }
+    // the static models and the axes are relative to the absolute origin
+    let origin = self.ecs.world.get_resource::<WorldOrigin>().unwrap();
+    lines.set_translation(-origin.offset_f32());
+    for model in &self.models {
+      if model.is_visible(frustum_static) {
+        debug_model(&mut lines, model, &view);
+      }
+    }
+    if view.axes {
+      lines.axes(Point3::new(0.0, 0.0, 0.0), 10.0);
+    }
+    lines
+  }
 
-    match future {
-      Ok(future) => {
-        self.previous_frame_end = Some(future.boxed());
+  // terrain and models into the shadow map of each cascade, culled by the sun's view
+  fn record_shadows(
+    &self,
+    builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    cascades: &Cascades,
+    proj: &shaders::main::vs::ty::Data,
+    proj_absolute: &shaders::main::vs::ty::Data,
+  ) {
+    let models = self.myworld.get_models();
+    let (statics, others) = (
+      self.myworld.get_static_instanced(),
+      self.myworld.get_instanced(),
+    );
+    // the instanced models of each cascade
+    let mut culling = self.shadow_culling.borrow_mut();
+    culling.resize_with(CASCADES, Vec::new);
+    let mut cullings = culling.iter_mut();
+    for i in 0..CASCADES {
+      let cascade_culling = cullings.next().unwrap();
+      cascade_culling.resize_with(statics.len() + others.len(), InstanceCulling::default);
+      let light = cascades.light_matrix(i, Matrix4::from(proj.world));
+      let light_static = cascades.light_matrix(i, Matrix4::from(proj_absolute.world));
+      let (set, set_static) = (
+        self.system.shadow_set(light),
+        self.system.shadow_set(light_static),
+      );
+      let (frustum, frustum_static) = (
+        Frustum::from_matrix(light),
+        Frustum::from_matrix(light_static),
+      );
+      builder
+        .begin_render_pass(
+          self.system.shadow_framebuffers[i].clone(),
+          SubpassContents::Inline,
+          vec![1f32.into()],
+        )
+        .unwrap();
+      // the shadow pipeline has the fixed viewport of the shadow maps
+      let dynamic = DynamicState::none();
+      for model in models.iter().filter(|m| m.is_visible(&frustum)) {
+        model.draw_indexed(
+          builder,
+          self.system.pipeline_shadow.clone(),
+          &dynamic,
+          set.clone(),
+        );
       }
-      Err(FlushError::OutOfDate) => {
-        self.recreate_swapchain = true;
-        self.previous_frame_end = Some(sync::now(self.graph.device.clone()).boxed());
+      for model in self.models.iter().filter(|m| m.is_visible(&frustum_static)) {
+        model.draw_indexed(
+          builder,
+          self.system.pipeline_shadow.clone(),
+          &dynamic,
+          set_static.clone(),
+        );
       }
-      Err(e) => {
-        println!("Failed to flush future: {:?}", e);
-        self.previous_frame_end = Some(sync::now(self.graph.device.clone()).boxed());
+      let instanced = statics
+        .iter()
+        .map(|item| (item, &frustum_static, &set_static))
+        .chain(others.iter().map(|item| (item, &frustum, &set)));
+      for ((item, frustum, set), culling) in instanced.zip(cascade_culling.iter_mut()) {
+        let (model, instances) = item;
+        let visible = culling.visible(model.aabb(), instances, frustum);
+        if visible.is_empty() {
+          continue;
+        }
+        model.draw_instanced(
+          builder,
+          self.system.pipeline_shadow_instanced.clone(),
+          &dynamic,
+          set.clone(),
+          self.system.instances(visible.to_vec()),
+        );
       }
+      builder.end_render_pass().unwrap();
     }
-    let frame_end = Instant::now();
-    let last_frame = (frame_end - frame_start).as_millis() as u32;
-    self.last_frame_took = last_frame;
-    self.frame_times_avg.add(last_frame);
+  }
+
+  pub fn set_camera_position(&mut self, position: Point3<f32>) {
+    self.camera.set_pos(&mut self.ecs.world, position);
+  }
+
+  // nothing depends on the wall clock or on background timing afterwards, so the same
+  // ticks always render the same frame
+  pub fn freeze(&mut self) {
+    self.ecs.world.get_resource_mut::<TimeOfDay>().unwrap().frozen = true;
+    self.system.pin_animation_time(0.0);
+  }
+
+  // blocks until the background jobs spawned so far are done
+  pub fn finish_background(&mut self) {
+    self.myworld.finish_background();
   }
 
   #[profiling::function]
   pub fn tick(&mut self) {
-    self.myworld.tick(&self.ecs);
+    // the world reacts to the events of this tick, so an origin shift of the camera
+    // is applied to the world before the frame is drawn
     self.ecs.tick();
+    self.myworld.tick(&self.ecs);
   }
 
   #[profiling::function]
   pub fn accept_events(&mut self, game_event_reader: &mut ManualEventReader<GameEvent>) {
     loop {
+      let mut next_skybox = false;
       let events = self.ecs.get_events::<GameEvent>();
       for event in game_event_reader.iter(events) {
         match event {
           GameEvent::Game(GameWantsExitEvent {}) => {
             self.game_exited.store(true, Ordering::Release);
           }
+          GameEvent::Capture(CaptureEvent::Screenshot) => self.capture.screenshot(),
+          GameEvent::Capture(CaptureEvent::Sequence) => self
+            .capture
+            .toggle_sequence(self.settings.capture_frames, SystemTime::now()),
+          GameEvent::View(ViewEvent::NextSkybox) => next_skybox = true,
+          GameEvent::View(ViewEvent::ToggleNormals) => {
+            self.debug_view.normals = !self.debug_view.normals
+          }
+          GameEvent::View(ViewEvent::ToggleAabbs) => self.debug_view.aabbs = !self.debug_view.aabbs,
+          GameEvent::View(ViewEvent::ToggleSkyGrid) => {
+            self.debug_view.sky_grid = !self.debug_view.sky_grid
+          }
+          GameEvent::View(ViewEvent::ToggleAxes) => self.debug_view.axes = !self.debug_view.axes,
+          GameEvent::View(ViewEvent::NextRenderMode) => {
+            let wireframe = self.system.mode_pipelines.has_wireframe();
+            self.render_mode = self.render_mode.next(wireframe)
+          }
           _ => {}
         }
       }
+      // the events borrow the ecs until here
+      if next_skybox {
+        self.next_skybox();
+      }
       let next = self.recv.try_recv();
       if next.is_err() {
         break;
@@ -375,15 +1013,68 @@ impl Game {
 
   fn status_string(&self) -> String {
     let camera_status = self.camera.to_string(&self.ecs.world);
+    let time_of_day = self.ecs.world.get_resource::<TimeOfDay>().unwrap();
     let avg = self.frame_times_avg.count();
     let all_avg = self.frame_times_avg.all_count();
+    let shader_error = match &self.shader_error {
+      Some(e) => format!("\nshader error {}", e),
+      None => String::new(),
+    };
+    let normals = if self.debug_view.normals && !KEEPS_VERTICES {
+      "\nnormals are only drawn by debug builds"
+    } else {
+      ""
+    };
     format!(
-      "camera {}\nmyworld {}\navgftw {:.2} navgft {:.2} ",
-      camera_status, self.myworld, avg, all_avg
+      "camera {}\ntime {}\nmyworld {}\navgftw {:.2} navgft {:.2} \nculled {}/{}\nmode {:?}{}{}",
+      camera_status,
+      time_of_day,
+      self.myworld,
+      avg,
+      all_avg,
+      self.draw_stats.culled,
+      self.draw_stats.culled + self.draw_stats.drawn,
+      self.render_mode,
+      normals,
+      shader_error
     )
   }
 }
 
+// the normals and the bounding box of a model, when debug_view shows them
+fn debug_model(lines: &mut DebugLines, model: &Model, view: &DebugView) {
+  if view.aabbs {
+    if let Some(aabb) = model.aabb() {
+      lines.aabb(&aabb, CYAN);
+    }
+  }
+  if let (true, Some(vertices)) = (view.normals, model.vertices()) {
+    // the vertices are where the model was uploaded
+    let translation = lines.translation();
+    lines.set_translation(translation + model.offset());
+    lines.normals(vertices, 0.5, YELLOW);
+    lines.set_translation(translation);
+  }
+}
+
+// an empty sky when there is none configured or it cannot be loaded
+fn startup_skybox(settings: &Settings) -> CubeFaces {
+  let source = match settings.skyboxes.first() {
+    Some(source) => source,
+    None => return CubeFaces::black(),
+  };
+  CubeFaces::load(source).unwrap_or_else(|e| {
+    println!("failed to load skybox {:?} {:?}", source, e);
+    CubeFaces::black()
+  })
+}
+
+fn frustum_of(data: &shaders::main::vs::ty::Data) -> Frustum {
+  Frustum::from_matrix(
+    Matrix4::from(data.proj) * Matrix4::from(data.view) * Matrix4::from(data.world),
+  )
+}
+
 #[profiling::function]
 pub fn mysleep_until(now: Instant, t: Instant) {
   let mut cur = now;
@@ -0,0 +1,197 @@
+use cgmath::Point3;
+
+use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
+use std::sync::mpsc::channel;
+use std::sync::Arc;
+
+use crate::executor::Executor;
+use crate::game::Game;
+use crate::Graph;
+use crate::Settings;
+
+// ggga --headless out.png [--size 1280x800] [--camera x,y,z] [--ticks 1]
+#[derive(Debug, Clone, PartialEq)]
+pub struct HeadlessArgs {
+  pub output: PathBuf,
+  pub dimensions: [u32; 2],
+  pub camera: Option<Point3<f32>>,
+  // ticks to run before rendering, lets the world generate around the camera
+  pub ticks: u32,
+}
+
+impl HeadlessArgs {
+  // None when the arguments do not ask for a headless render
+  pub fn parse(args: &[String]) -> Result<Option<HeadlessArgs>, String> {
+    let mut output: Option<PathBuf> = None;
+    let mut dimensions = [1280, 800];
+    let mut camera = None;
+    let mut ticks = 1;
+    let mut iter = args.iter().skip(1);
+    while let Some(arg) = iter.next() {
+      let mut value = || {
+        iter
+          .next()
+          .ok_or_else(|| format!("{} needs a value", arg))
+      };
+      match arg.as_str() {
+        "--headless" => output = Some(PathBuf::from(value()?)),
+        "--size" => dimensions = parse_size(value()?)?,
+        "--camera" => camera = Some(parse_point(value()?)?),
+        "--ticks" => {
+          let v = value()?;
+          ticks = v.parse().map_err(|_| format!("bad ticks {}", v))?
+        }
+        other => return Err(format!("unknown argument {}", other)),
+      }
+    }
+    Ok(output.map(|output| HeadlessArgs {
+      output,
+      dimensions,
+      camera,
+      ticks,
+    }))
+  }
+}
+
+fn parse_size(s: &str) -> Result<[u32; 2], String> {
+  let parts: Vec<&str> = s.split('x').collect();
+  match parts.as_slice() {
+    [w, h] => match (w.parse(), h.parse()) {
+      (Ok(w), Ok(h)) if w > 0 && h > 0 => Ok([w, h]),
+      _ => Err(format!("bad size {}", s)),
+    },
+    _ => Err(format!("bad size {}, expected WIDTHxHEIGHT", s)),
+  }
+}
+
+fn parse_point(s: &str) -> Result<Point3<f32>, String> {
+  let coords: Result<Vec<f32>, _> = s.split(',').map(|c| c.trim().parse()).collect();
+  match coords.as_ref().map(|c| c.as_slice()) {
+    Ok([x, y, z]) => Ok(Point3::new(*x, *y, *z)),
+    _ => Err(format!("bad position {}, expected x,y,z", s)),
+  }
+}
+
+// the error is reported and the caller exits with a failure, so scripts notice it
+pub fn run_headless(
+  settings: Settings,
+  executor: Executor,
+  args: HeadlessArgs,
+) -> image::ImageResult<()> {
+  match render_headless(settings, executor, &args) {
+    Ok(()) => {
+      println!("rendered {:?}", args.output);
+      Ok(())
+    }
+    Err(e) => {
+      println!("failed to save {:?} {:?}", args.output, e);
+      Err(e)
+    }
+  }
+}
+
+// a new game ticked and rendered once into args.output. time stands still and the tiles are
+// waited for, so the same args give the same image
+fn render_headless(
+  settings: Settings,
+  executor: Executor,
+  args: &HeadlessArgs,
+) -> image::ImageResult<()> {
+  let graph = Graph::new_headless(args.dimensions, &settings);
+  let (_send, recv) = channel();
+  let game_exited = Arc::new(AtomicBool::new(false));
+  let mut game = Game::new(settings, executor, graph, game_exited, recv);
+  game.init();
+  game.freeze();
+  if let Some(camera) = args.camera {
+    game.set_camera_position(camera);
+  }
+  for _ in 0..args.ticks {
+    game.tick();
+  }
+  game.finish_background();
+  let result = game.render_offscreen(&args.output);
+  game.save_pipeline_cache();
+  result
+}
+
+#[cfg(test)]
+mod test {
+  use crate::executor::Executor;
+  use crate::headless::{render_headless, HeadlessArgs};
+  use crate::settings::test::{args, settings};
+  use crate::Settings;
+  use cgmath::Point3;
+  use futures::executor::ThreadPool;
+  use image::RgbaImage;
+  use std::path::PathBuf;
+  use vulkano::instance::{Instance, InstanceExtensions, PhysicalDevice};
+
+  #[test]
+  pub fn test_parse() {
+    assert_eq!(HeadlessArgs::parse(&args("")), Ok(None));
+    assert_eq!(
+      HeadlessArgs::parse(&args("--size 64x32 --headless out/a.png --camera 1,-2.5,3")),
+      Ok(Some(HeadlessArgs {
+        output: PathBuf::from("out/a.png"),
+        dimensions: [64, 32],
+        camera: Some(Point3::new(1.0, -2.5, 3.0)),
+        ticks: 1,
+      }))
+    );
+    assert!(HeadlessArgs::parse(&args("--headless")).is_err());
+    assert!(HeadlessArgs::parse(&args("--headless a.png --size 0x10")).is_err());
+    assert!(HeadlessArgs::parse(&args("--headless a.png --camera 1,2")).is_err());
+  }
+
+  fn has_vulkan_device() -> bool {
+    match Instance::new(None, &InstanceExtensions::none(), vec![]) {
+      Ok(instance) => PhysicalDevice::enumerate(&instance)
+        .any(|device| device.queue_families().any(|q| q.supports_graphics())),
+      Err(_) => false,
+    }
+  }
+
+  // settings rendered into a temporary png and read back
+  fn render(settings: Settings, name: &str) -> RgbaImage {
+    let output = std::env::temp_dir().join(format!(
+      "ggga-headless-{}-{}.png",
+      name,
+      std::process::id()
+    ));
+    let args = HeadlessArgs {
+      output: output.clone(),
+      dimensions: [64, 32],
+      camera: None,
+      ticks: 1,
+    };
+    let executor = Executor::new(ThreadPool::new().unwrap());
+    render_headless(settings, executor, &args).unwrap();
+    let image = image::open(&output).unwrap().to_rgba8();
+    std::fs::remove_file(&output).unwrap();
+    image
+  }
+
+  #[test]
+  pub fn test_render_headless() {
+    if !has_vulkan_device() {
+      println!("skipped, there is no vulkan device");
+      return;
+    }
+    let image = render(settings(), "scene");
+    assert_eq!(image.dimensions(), (64, 32));
+    // time stands still and the tiles are waited for
+    assert!(render(settings(), "again") == image, "two renders differ");
+    // the terrain, the box and the triangle cover part of what is only sky without them
+    let sky_only = Settings {
+      sky_enabled: false,
+      box_enabled: false,
+      triangle_enabled: false,
+      ..settings()
+    };
+    let sky = render(sky_only, "sky");
+    let covered = image.pixels().zip(sky.pixels()).filter(|(a, b)| a != b).count();
+    assert!(covered > 64 * 32 / 100, "only {} pixels show the scene", covered);
+  }
+}
@@ -1,4 +1,4 @@
-use cgmath::Point3;
+use cgmath::{Point3, Vector3};
 use winit::dpi::PhysicalPosition;
 use winit::event::{MouseScrollDelta, VirtualKeyCode};
 
@@ -34,6 +34,29 @@ pub struct CameraEnteredEvent {
   pub position: Point3<f32>,
 }
 
+pub enum CaptureEvent {
+  Screenshot,
+  // starts or stops capturing a sequence of frames
+  Sequence,
+}
+
+pub enum ViewEvent {
+  // loads the next of Settings::skyboxes from disk
+  NextSkybox,
+  // the debug lines, see render::DebugView
+  ToggleNormals,
+  ToggleAabbs,
+  ToggleSkyGrid,
+  ToggleAxes,
+  // see render::RenderMode
+  NextRenderMode,
+}
+
+// everything in local coordinates has to be moved by -shift
+pub struct OriginShiftedEvent {
+  pub shift: Vector3<f32>,
+}
+
 #[derive(Debug)]
 pub enum InputEvent {
   KeyBoard(MyKeyboardInput),
@@ -46,4 +69,7 @@ pub enum GameEvent {
   Draw(),
   Camera(CameraEnteredEvent),
   Game(GameWantsExitEvent),
+  OriginShifted(OriginShiftedEvent),
+  Capture(CaptureEvent),
+  View(ViewEvent),
 }
@@ -1,9 +1,10 @@
 use crate::input::{GameEvent, MyKeyStatus};
 use vulkano::device::{Device, DeviceExtensions, Queue};
 use vulkano::format::Format;
-use vulkano::image::{ImageUsage, SwapchainImage};
-use vulkano::instance::Instance;
-use vulkano::instance::{PhysicalDevice, PhysicalDeviceType};
+use vulkano::image::{AttachmentImage, ImageUsage, SwapchainImage};
+use vulkano::instance::{Instance, InstanceExtensions};
+use vulkano::instance::{PhysicalDevice, QueueFamily};
+use vulkano::pipeline::cache::PipelineCache;
 use vulkano::render_pass::RenderPass;
 
 use vulkano::swapchain::{Surface, Swapchain, SwapchainCreationError};
@@ -24,6 +25,7 @@ extern crate vulkano_text;
 
 use futures::executor::ThreadPoolBuilder;
 
+use std::path::PathBuf;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::channel;
 use std::sync::Arc;
@@ -32,6 +34,7 @@ use std::sync::Mutex;
 mod actor;
 mod camera;
 mod game;
+mod headless;
 mod myworld;
 mod sign_post;
 mod sky;
@@ -50,38 +53,69 @@ mod utils;
 
 use executor::Executor;
 use game::Game;
+use headless::HeadlessArgs;
 use input::{InputEvent, MyKeyboardInput, MyMouseInput, MyMouseWheel};
 use render::Model;
+use render::{
+  clamp_samples, select_device, DebugView, DeviceDescription, DeviceOverride, Fog, PostSettings,
+  load_pipeline_cache, ReloadableShaders, SkyboxSource, HDR_FORMAT,
+};
 use settings::Settings;
-use shaders::{main, skybox};
+use shaders::{debug, instanced, modes, post, shadow, water};
 
 pub struct Graph {
-  surface: Arc<Surface<Window>>,
   dimensions: [u32; 2],
   device: Arc<Device>,
   queue: Arc<Queue>,
+  // the format of the final images
+  format: Format,
+  // msaa samples of the scene, 1 without msaa
+  samples: u32,
+  // renders the scene into the hdr buffer, see render::PostProcess
+  render_pass: Arc<RenderPass>,
+  bloom_render_pass: Arc<RenderPass>,
+  post_render_pass: Arc<RenderPass>,
+  // every pipeline is built with it, see Game::save_pipeline_cache
+  pipeline_cache: Arc<PipelineCache>,
+  // main and skybox, replaced when their glsl files change, see Game::reload_shaders
+  shaders: ReloadableShaders,
+  water_vs: water::vs::Shader,
+  water_fs: water::fs::Shader,
+  instanced_vs: instanced::vs::Shader,
+  shadow_vs: shadow::vs::Shader,
+  shadow_instanced_vs: shadow::instanced_vs::Shader,
+  shadow_fs: shadow::fs::Shader,
+  modes_fs: modes::fs::Shader,
+  debug_vs: debug::vs::Shader,
+  debug_fs: debug::fs::Shader,
+  post_vs: post::vs::Shader,
+  bright_fs: post::bright_fs::Shader,
+  blur_fs: post::blur_fs::Shader,
+  tonemap_fs: post::tonemap_fs::Shader,
+  target: Target,
+}
+
+// where the final image of a frame ends up
+pub enum Target {
+  Window(WindowTarget),
+  Offscreen(Arc<AttachmentImage>),
+}
+
+pub struct WindowTarget {
+  surface: Arc<Surface<Window>>,
   swapchain: Arc<Swapchain<Window>>,
   images: Vec<Arc<SwapchainImage<Window>>>,
-  render_pass: Arc<RenderPass>,
-  vs: main::vs::Shader,
-  fs: main::fs::Shader,
-  skybox_vs: skybox::vs::Shader,
-  skybox_fs: skybox::fs::Shader,
   draw_text: DrawText,
 }
 
 impl Graph {
-  fn new(event_loop: &EventLoop<()>) -> Graph {
+  // the format final images are rendered in when there is no swapchain to ask
+  const OFFSCREEN_FORMAT: Format = Format::B8G8R8A8Unorm;
+
+  fn new(event_loop: &EventLoop<()>, settings: &Settings) -> Graph {
     let required_extensions = vulkano_win::required_extensions();
     let instance = Instance::new(None, &required_extensions, vec![]).unwrap();
 
-    for device in PhysicalDevice::enumerate(&instance) {
-      println!(
-        "possible device: {} (type: {:?})",
-        device.name(),
-        device.ty()
-      );
-    }
     let device_ext = DeviceExtensions {
       khr_swapchain: true,
       ..DeviceExtensions::none()
@@ -92,18 +126,9 @@ impl Graph {
       .build_vk_surface(&event_loop, instance.clone())
       .unwrap();
     let dimensions: [u32; 2] = surface.window().inner_size().into();
-    let physical = PhysicalDevice::enumerate(&instance)
-      .find(|device| device.ty() == PhysicalDeviceType::DiscreteGpu)
-      .unwrap();
-    println!(
-      "Using device: {} (type: {:?})",
-      physical.name(),
-      physical.ty()
-    );
-    let queue_family = physical
-      .queue_families()
-      .find(|&q| q.supports_graphics() && surface.is_supported(q).unwrap_or(false))
-      .unwrap();
+    let (physical, queue_family) = choose_physical_device(&instance, settings, |q| {
+      q.supports_graphics() && surface.is_supported(q).unwrap_or(false)
+    });
 
     let (device, mut queues) = Device::new(
       physical,
@@ -123,33 +148,246 @@ impl Graph {
         .num_images(caps.min_image_count)
         .format(format)
         .dimensions(dimensions)
-        .usage(ImageUsage::color_attachment())
+        .usage(ImageUsage {
+          color_attachment: true,
+          // screenshots copy the swapchain image
+          transfer_source: true,
+          ..ImageUsage::none()
+        })
         .sharing_mode(&queue)
         .composite_alpha(alpha)
         .build()
         .unwrap()
     };
 
-    let render_pass = Arc::new(
-      vulkano::ordered_passes_renderpass!(
+    let draw_text = DrawText::new(device.clone(), queue.clone(), swapchain.clone(), &images);
+    let target = Target::Window(WindowTarget {
+      surface,
+      swapchain: swapchain.clone(),
+      images,
+      draw_text,
+    });
+    Graph::with_target(
+      device,
+      queue,
+      dimensions,
+      swapchain.format(),
+      target,
+      settings,
+    )
+  }
+
+  // renders into an image instead of a window, works without a display and on
+  // software vulkan implementations
+  fn new_headless(dimensions: [u32; 2], settings: &Settings) -> Graph {
+    let instance = Instance::new(None, &InstanceExtensions::none(), vec![]).unwrap();
+    let (physical, queue_family) =
+      choose_physical_device(&instance, settings, |q| q.supports_graphics());
+    let (device, mut queues) = Device::new(
+      physical,
+      physical.supported_features(),
+      &DeviceExtensions::none(),
+      [(queue_family, 0.5)].iter().cloned(),
+    )
+    .unwrap();
+    let queue = queues.next().unwrap();
+    let image = AttachmentImage::with_usage(
+      device.clone(),
+      dimensions,
+      Graph::OFFSCREEN_FORMAT,
+      ImageUsage {
+        color_attachment: true,
+        transfer_source: true,
+        ..ImageUsage::none()
+      },
+    )
+    .unwrap();
+    let target = Target::Offscreen(image);
+    Graph::with_target(
+      device,
+      queue,
+      dimensions,
+      Graph::OFFSCREEN_FORMAT,
+      target,
+      settings,
+    )
+  }
+
+  fn with_target(
+    device: Arc<Device>,
+    queue: Arc<Queue>,
+    dimensions: [u32; 2],
+    format: Format,
+    target: Target,
+    settings: &Settings,
+  ) -> Graph {
+    let supported = device.physical_device().limits().framebuffer_color_sample_counts()
+      & device.physical_device().limits().framebuffer_depth_sample_counts();
+    let samples = clamp_samples(settings.msaa_samples, supported);
+    if samples != settings.msaa_samples {
+      println!("{} msaa samples requested, using {}", settings.msaa_samples, samples);
+    }
+    let render_pass = Graph::main_render_pass(&device, samples);
+    // the bright pass and the blurs, each pass covers its whole target
+    let bloom_render_pass = Arc::new(
+      vulkano::single_pass_renderpass!(
+          device.clone(),
+          attachments: {
+              bloom: {
+                  load: DontCare,
+                  store: Store,
+                  format: HDR_FORMAT,
+                  samples: 1,
+              }
+          },
+          pass: {
+              color: [bloom],
+              depth_stencil: {}
+          }
+      )
+      .unwrap(),
+    );
+    // tonemaps the hdr buffer into the final image
+    let post_render_pass = Arc::new(
+      vulkano::single_pass_renderpass!(
           device.clone(),
           attachments: {
               final_color: {
+                  load: DontCare,
+                  store: Store,
+                  format: format,
+                  samples: 1,
+              }
+          },
+          pass: {
+              color: [final_color],
+              depth_stencil: {}
+          }
+      )
+      .unwrap(),
+    );
+    let pipeline_cache = load_pipeline_cache(&device, settings.pipeline_cache.as_deref());
+    let shaders = ReloadableShaders::load(device.clone());
+    //let tcs = tcs::Shader::load(device.clone()).unwrap();
+    //let tes = tes::Shader::load(device.clone()).unwrap();
+    let water_vs = water::vs::Shader::load(device.clone()).unwrap();
+    let water_fs = water::fs::Shader::load(device.clone()).unwrap();
+    let instanced_vs = instanced::vs::Shader::load(device.clone()).unwrap();
+    let shadow_vs = shadow::vs::Shader::load(device.clone()).unwrap();
+    let shadow_instanced_vs = shadow::instanced_vs::Shader::load(device.clone()).unwrap();
+    let shadow_fs = shadow::fs::Shader::load(device.clone()).unwrap();
+    let modes_fs = modes::fs::Shader::load(device.clone()).unwrap();
+    let debug_vs = debug::vs::Shader::load(device.clone()).unwrap();
+    let debug_fs = debug::fs::Shader::load(device.clone()).unwrap();
+    let post_vs = post::vs::Shader::load(device.clone()).unwrap();
+    let bright_fs = post::bright_fs::Shader::load(device.clone()).unwrap();
+    let blur_fs = post::blur_fs::Shader::load(device.clone()).unwrap();
+    let tonemap_fs = post::tonemap_fs::Shader::load(device.clone()).unwrap();
+
+    Graph {
+      dimensions,
+      device,
+      queue,
+      format,
+      samples,
+      render_pass,
+      bloom_render_pass,
+      post_render_pass,
+      pipeline_cache,
+      shaders,
+      water_vs,
+      water_fs,
+      instanced_vs,
+      shadow_vs,
+      shadow_instanced_vs,
+      shadow_fs,
+      modes_fs,
+      debug_vs,
+      debug_fs,
+      post_vs,
+      bright_fs,
+      blur_fs,
+      tonemap_fs,
+      target,
+    }
+  }
+
+  // the scene is drawn in the first subpass, the second composites the sky behind it where
+  // the depth is still at the far plane. with msaa the first subpass resolves its color into
+  // the input of the second, the depth stays multisampled
+  fn main_render_pass(device: &Arc<Device>, samples: u32) -> Arc<RenderPass> {
+    if samples == 1 {
+      return Arc::new(
+        vulkano::ordered_passes_renderpass!(
+            device.clone(),
+            attachments: {
+                hdr: {
+                    load: Clear,
+                    store: Store,
+                    format: HDR_FORMAT,
+                    samples: 1,
+                },
+                depth: {
+                    load: Clear,
+                    store: DontCare,
+                    format: Format::D16Unorm,
+                    samples: 1,
+                },
+                color: {
+                    load: Clear,
+                    store: DontCare,
+                    format: HDR_FORMAT,
+                    samples: 1,
+                },
+                depth2: {
+                    load: Clear,
+                    store: DontCare,
+                    format: Format::D16Unorm,
+                    samples: 1,
+                }
+            },
+            passes: [
+            {
+                color: [color],
+                depth_stencil: {depth},
+                input: []
+            },
+            {
+                color: [hdr],
+                depth_stencil: {depth2},
+                input: [color, depth]
+            }
+            ]
+        )
+        .unwrap(),
+      );
+    }
+    Arc::new(
+      vulkano::ordered_passes_renderpass!(
+          device.clone(),
+          attachments: {
+              hdr: {
                   load: Clear,
                   store: Store,
-                  format: swapchain.format(),
+                  format: HDR_FORMAT,
                   samples: 1,
               },
               depth: {
                   load: Clear,
-                  store: Store,
+                  store: DontCare,
                   format: Format::D16Unorm,
-                  samples: 1,
+                  samples: samples,
               },
               color: {
                   load: Clear,
-                  store: Store,
-                  format: swapchain.format(),
+                  store: DontCare,
+                  format: HDR_FORMAT,
+                  samples: samples,
+              },
+              color_resolved: {
+                  load: DontCare,
+                  store: DontCare,
+                  format: HDR_FORMAT,
                   samples: 1,
               },
               depth2: {
@@ -163,62 +401,76 @@ impl Graph {
           {
               color: [color],
               depth_stencil: {depth},
-              input: []
+              input: [],
+              resolve: [color_resolved]
           },
           {
-              color: [final_color],
+              color: [hdr],
               depth_stencil: {depth2},
-              input: [color, depth]
+              input: [color_resolved, depth]
           }
           ]
       )
       .unwrap(),
-    );
-    let vs = main::vs::Shader::load(device.clone()).unwrap();
-    //let tcs = tcs::Shader::load(device.clone()).unwrap();
-    //let tes = tes::Shader::load(device.clone()).unwrap();
-    let fs = main::fs::Shader::load(device.clone()).unwrap();
-    let skybox_vs = skybox::vs::Shader::load(device.clone()).unwrap();
-    let skybox_fs = skybox::fs::Shader::load(device.clone()).unwrap();
-
-    let draw_text = DrawText::new(device.clone(), queue.clone(), swapchain.clone(), &images);
-
-    Graph {
-      surface,
-      dimensions,
-      device,
-      queue,
-      swapchain,
-      images,
-      render_pass,
-      vs,
-      fs,
-      skybox_vs,
-      skybox_fs,
-      draw_text,
-    }
+    )
   }
 
   pub fn recreate_swapchain(&mut self) {
-    let dimensions: [u32; 2] = self.surface.window().inner_size().into();
-    let (new_swapchain, new_images) = match self.swapchain.recreate().dimensions(dimensions).build()
-    {
-      Ok(r) => r,
-      Err(SwapchainCreationError::UnsupportedDimensions) => return,
-      Err(e) => panic!("Failed to recreate swapchain: {:?}", e),
+    let window = match &mut self.target {
+      Target::Window(window) => window,
+      Target::Offscreen(_) => return,
     };
-    self.swapchain = new_swapchain;
-    self.images = new_images.clone();
+    let dimensions: [u32; 2] = window.surface.window().inner_size().into();
+    let (new_swapchain, new_images) =
+      match window.swapchain.recreate().dimensions(dimensions).build() {
+        Ok(r) => r,
+        Err(SwapchainCreationError::UnsupportedDimensions) => return,
+        Err(e) => panic!("Failed to recreate swapchain: {:?}", e),
+      };
+    window.swapchain = new_swapchain;
+    window.images = new_images.clone();
 
-    self.draw_text = DrawText::new(
+    window.draw_text = DrawText::new(
       self.device.clone(),
       self.queue.clone(),
-      self.swapchain.clone(),
+      window.swapchain.clone(),
       &new_images,
     );
   }
 }
 
+// ranks the devices, see render::select_device, panics when none can render
+fn choose_physical_device<'a, F>(
+  instance: &'a Arc<Instance>,
+  settings: &Settings,
+  supported: F,
+) -> (PhysicalDevice<'a>, QueueFamily<'a>)
+where
+  F: Fn(QueueFamily<'a>) -> bool,
+{
+  let descriptions: Vec<DeviceDescription> = PhysicalDevice::enumerate(instance)
+    .map(|device| DeviceDescription {
+      index: device.index(),
+      name: device.name().to_string(),
+      kind: device.ty().into(),
+      usable: device.queue_families().any(|q| supported(q)),
+    })
+    .collect();
+  for device in &descriptions {
+    println!(
+      "possible device #{}: {} (type: {:?}, usable: {})",
+      device.index, device.name, device.kind, device.usable
+    );
+  }
+  let device_override = DeviceOverride::from_env_or(settings.device.as_deref());
+  let selection = select_device(&descriptions, device_override.as_ref())
+    .expect("no vulkan device with a usable graphics queue");
+  println!("Using {}", selection);
+  let physical = PhysicalDevice::from_index(instance, selection.index).unwrap();
+  let queue_family = physical.queue_families().find(|&q| supported(q)).unwrap();
+  (physical, queue_family)
+}
+
 fn main() {
   let mut thread_pool_builder = ThreadPoolBuilder::new();
   thread_pool_builder
@@ -229,20 +481,58 @@ fn main() {
     });
   let thread_pool = thread_pool_builder.create().unwrap();
 
-  let event_loop = EventLoop::<()>::new();
-  let graph = Graph::new(&event_loop);
-
   let executor = Executor::new(thread_pool);
 
-  let settings = Settings {
+  let mut settings = Settings {
     sky_enabled: true,
     box_enabled: true,
     dog_enabled: false,
     letters_enabled: true,
     triangle_enabled: true,
     lap_enabled: true,
+    terrain_cache_dir: Some(PathBuf::from("cache/terrain")),
+    clouds_enabled: false,
+    sea_level: 0.01,
+    device: None,
+    capture_dir: PathBuf::from("screenshots"),
+    capture_frames: 300,
+    fog: Fog::default(),
+    start_hour: 10.0,
+    day_length: 600.0,
+    skyboxes: vec![SkyboxSource::Faces(PathBuf::from("assets/interstellar_skybox"))],
+    clear_color: [0.0, 0.0, 0.0],
+    post: PostSettings::default(),
+    msaa_samples: 4,
+    debug: DebugView::default(),
+    shader_hot_reload: cfg!(feature = "shader-hot-reload"),
+    pipeline_cache: Some(PathBuf::from("cache/pipelines.bin")),
   };
 
+  let args: Vec<String> = std::env::args().collect();
+  let args = match settings.apply_args(&args) {
+    Ok(args) => args,
+    Err(e) => {
+      println!("{}", e);
+      std::process::exit(1);
+    }
+  };
+  match HeadlessArgs::parse(&args) {
+    Ok(Some(headless_args)) => {
+      if headless::run_headless(settings, executor, headless_args).is_err() {
+        std::process::exit(1);
+      }
+      return;
+    }
+    Ok(None) => {}
+    Err(e) => {
+      println!("{}", e);
+      std::process::exit(1);
+    }
+  }
+
+  let event_loop = EventLoop::<()>::new();
+  let graph = Graph::new(&event_loop, &settings);
+
   let (send, recv) = channel();
 
   let game_exited = Arc::new(AtomicBool::new(false));
@@ -263,6 +553,10 @@ fn main() {
     println!("got event {:?}", event);
     if game_exited.load(Ordering::Acquire) {
       println!("exiting..");
+      // the game loop saves the pipeline cache before it ends
+      if let Some(handle) = thread_handle.lock().unwrap().take() {
+        handle.join().unwrap();
+      }
       *control_flow = ControlFlow::Exit;
       return;
     } 
@@ -3,12 +3,13 @@ use cgmath::Point3;
 use profiling;
 
 use bevy_ecs::event::ManualEventReader;
+use vulkano::sync::GpuFuture;
 
 use std::fmt;
 
 use crate::ecs::Ecs;
 use crate::executor::Executor;
-use crate::render::Model;
+use crate::render::{DebugLines, Instance, InstancedModel, Model};
 use crate::render::Scene;
 use crate::sign_post::SignPost;
 use crate::sky::Sky;
@@ -47,6 +48,7 @@ pub struct MyWorld {
   pub mode: Mode,
   sky: Sky,
   sign_posts: Vec<SignPost>,
+  sign_post_model: InstancedModel,
   skybox: PrimitiveSkyBox,
   events_camera_entered_reader: Option<ManualEventReader<GameEvent>>,
 }
@@ -57,14 +59,16 @@ impl MyWorld {
     graph: &Graph,
     sign_posts: Vec<SignPost>,
   ) -> Self {
-    let sky = Sky::new(settings.clone(), &graph.device, 0.0, 0.0);
+    let sky = Sky::new(settings.clone(), &graph.queue, 0.0, 0.0);
     let skybox = PrimitiveSkyBox::new(&graph.device);
+    let sign_post_model = SignPost::model(&graph.device);
     Self {
       settings,
       executor,
       mode: Mode::MoveCameraPos,
       sky,
       sign_posts,
+      sign_post_model,
       skybox,
       events_camera_entered_reader: None,
     }
@@ -78,7 +82,6 @@ impl MyWorld {
   #[profiling::function]
   pub fn tick(&mut self, ecs: &Ecs) {
     let events = ecs.get_events::<GameEvent>();
-    self.sky.tick(&self.executor);
     for event in self
       .events_camera_entered_reader
       .as_mut()
@@ -89,9 +92,22 @@ impl MyWorld {
         GameEvent::Camera(event) => {
           self.camera_entered(&event.position);
         }
+        GameEvent::OriginShifted(event) => {
+          self.sky.origin_shifted(event.shift);
+        }
         _ => {}
       }
     }
+    self.sky.tick(&self.executor);
+  }
+
+  pub fn finish_background(&mut self) {
+    self.sky.finish_pending();
+  }
+
+  // copies into device local buffers the next frame has to join
+  pub fn take_uploads(&mut self) -> Vec<Box<dyn GpuFuture>> {
+    self.sky.take_uploads()
   }
 
   fn camera_entered(&mut self, pos: &Point3<f32>) {
@@ -110,19 +126,58 @@ impl MyWorld {
     res
   }
 
+  // models in coordinates relative to the current world origin
   pub fn get_models(&self) -> Vec<Model> {
     let mut res = vec![];
     if self.settings.sky_enabled {
       res.extend(self.sky.get_current());
     }
+    res
+  }
+
+  // transparent models, drawn after everything else, relative to the current world origin
+  pub fn get_water_models(&self) -> Vec<Model> {
+    let mut res = vec![];
+    if self.settings.sky_enabled {
+      res.extend(self.sky.get_current_water());
+    }
+    res
+  }
+
+  // transparent clouds, drawn after the water furthest first, relative to the current world origin
+  pub fn get_cloud_models(&self) -> Vec<Model> {
+    let mut res = vec![];
+    if self.settings.sky_enabled {
+      res.extend(self.sky.get_current_clouds());
+    }
+    res
+  }
+
+  // instanced models relative to the current world origin, each with all its instances
+  pub fn get_instanced(&self) -> Vec<(&InstancedModel, Vec<Instance>)> {
+    let mut res = vec![];
+    if self.settings.sky_enabled {
+      res.push(self.sky.get_current_laps());
+    }
+    res
+  }
+
+  // instanced models placed in absolute coordinates
+  pub fn get_static_instanced(&self) -> Vec<(&InstancedModel, Vec<Instance>)> {
+    let mut res = vec![];
     if self.settings.letters_enabled {
-      for sign_post in &self.sign_posts {
-        res.push(sign_post.get_model());
-      }
+      let instances = self.sign_posts.iter().map(|s| s.get_instance()).collect();
+      res.push((&self.sign_post_model, instances));
     }
     res
   }
 
+  pub fn debug_sky_tiles(&self, lines: &mut DebugLines) {
+    if self.settings.sky_enabled {
+      self.sky.debug_tiles(lines);
+    }
+  }
+
   #[profiling::function]
   pub fn get_models_skybox(&self) -> Vec<&Model> {
     let mut res = vec![];
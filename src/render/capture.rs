@@ -0,0 +1,273 @@
+use image::error::{
+  ImageFormatHint, ParameterError, ParameterErrorKind, UnsupportedError, UnsupportedErrorKind,
+};
+use image::{ImageBuffer, ImageError, ImageResult, Rgba};
+use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer};
+use vulkano::device::Device;
+use vulkano::format::Format;
+
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// the offscreen and most swapchain images are bgra, png wants rgba
+pub fn bgra_to_rgba(bgra: &[u8]) -> Vec<u8> {
+  bgra
+    .chunks_exact(4)
+    .flat_map(|p| vec![p[2], p[1], p[0], p[3]])
+    .collect()
+}
+
+// the 8 bit formats a swapchain or the offscreen target can have, anything else is an error
+pub fn to_rgba(format: Format, data: &[u8]) -> ImageResult<Vec<u8>> {
+  match format {
+    Format::B8G8R8A8Unorm | Format::B8G8R8A8Srgb => Ok(bgra_to_rgba(data)),
+    Format::R8G8B8A8Unorm | Format::R8G8B8A8Srgb => Ok(data.to_vec()),
+    other => Err(ImageError::Unsupported(UnsupportedError::from_format_and_kind(
+      ImageFormatHint::Unknown,
+      UnsupportedErrorKind::GenericFeature(format!("capturing {:?} images", other)),
+    ))),
+  }
+}
+
+pub fn save_png(path: &Path, dimensions: [u32; 2], format: Format, data: &[u8]) -> ImageResult<()> {
+  let rgba = to_rgba(format, data)?;
+  let image = ImageBuffer::<Rgba<u8>, _>::from_raw(dimensions[0], dimensions[1], rgba)
+    .ok_or_else(|| {
+      ImageError::Parameter(ParameterError::from_kind(ParameterErrorKind::DimensionMismatch))
+    })?;
+  if let Some(dir) = path.parent() {
+    std::fs::create_dir_all(dir)?;
+  }
+  image.save(path)
+}
+
+// a frame copied into a cpu buffer, the gpu may still be writing it
+struct PendingReadback {
+  paths: Vec<PathBuf>,
+  dimensions: [u32; 2],
+  format: Format,
+  buffer: Arc<CpuAccessibleBuffer<[u8]>>,
+}
+
+// a captured frame read back from the gpu
+pub struct Readback {
+  pub paths: Vec<PathBuf>,
+  pub dimensions: [u32; 2],
+  pub format: Format,
+  pub data: Vec<u8>,
+}
+
+// buffers frames are copied into, read once the fence of their frame signaled. the buffers are
+// reused until the swapchain changes size
+pub struct Readbacks {
+  dimensions: [u32; 2],
+  free: Vec<Arc<CpuAccessibleBuffer<[u8]>>>,
+  pending: VecDeque<PendingReadback>,
+}
+
+impl Readbacks {
+  pub fn new() -> Self {
+    Readbacks {
+      dimensions: [0, 0],
+      free: vec![],
+      pending: VecDeque::new(),
+    }
+  }
+
+  // a buffer an image with 4 bytes per pixel can be copied to
+  pub fn buffer(
+    &mut self,
+    device: &Arc<Device>,
+    dimensions: [u32; 2],
+  ) -> Arc<CpuAccessibleBuffer<[u8]>> {
+    if dimensions != self.dimensions {
+      self.dimensions = dimensions;
+      self.free.clear();
+    }
+    self.free.pop().unwrap_or_else(|| readback_buffer(device, dimensions))
+  }
+
+  // buffer was filled by the frame just submitted
+  pub fn push(
+    &mut self,
+    paths: Vec<PathBuf>,
+    dimensions: [u32; 2],
+    format: Format,
+    buffer: Arc<CpuAccessibleBuffer<[u8]>>,
+  ) {
+    self.pending.push_back(PendingReadback {
+      paths,
+      dimensions,
+      format,
+      buffer,
+    });
+  }
+
+  // the frames the gpu is done with, in the order they were captured. the buffers are only
+  // unlocked once the future of their frame is cleaned up
+  pub fn finished(&mut self) -> Vec<Readback> {
+    let mut res = vec![];
+    while let Some(pending) = self.pending.front() {
+      let data = match pending.buffer.read() {
+        Ok(data) => data.to_vec(),
+        Err(_) => break,
+      };
+      let pending = self.pending.pop_front().unwrap();
+      if pending.dimensions == self.dimensions {
+        self.free.push(pending.buffer);
+      }
+      res.push(Readback {
+        paths: pending.paths,
+        dimensions: pending.dimensions,
+        format: pending.format,
+        data,
+      });
+    }
+    res
+  }
+}
+
+pub fn readback_buffer(
+  device: &Arc<Device>,
+  dimensions: [u32; 2],
+) -> Arc<CpuAccessibleBuffer<[u8]>> {
+  CpuAccessibleBuffer::from_iter(
+    device.clone(),
+    BufferUsage::transfer_destination(),
+    false,
+    (0..dimensions[0] * dimensions[1] * 4).map(|_| 0u8),
+  )
+  .unwrap()
+}
+
+struct Sequence {
+  dir: PathBuf,
+  next: u32,
+  frames: u32,
+}
+
+// decides which frames get written where, a single screenshot or a sequence of
+// consecutive frames for making videos
+pub struct Capture {
+  dir: PathBuf,
+  screenshot: bool,
+  sequence: Option<Sequence>,
+}
+
+impl Capture {
+  pub fn new(dir: PathBuf) -> Self {
+    Capture {
+      dir,
+      screenshot: false,
+      sequence: None,
+    }
+  }
+
+  pub fn screenshot(&mut self) {
+    self.screenshot = true;
+  }
+
+  // starts a sequence of frames, or stops the one being captured
+  pub fn toggle_sequence(&mut self, frames: u32, now: SystemTime) {
+    if self.sequence.take().is_none() {
+      self.sequence = Some(Sequence {
+        dir: self.dir.join(format!("sequence_{}", timestamp(now))),
+        next: 0,
+        frames,
+      });
+    }
+  }
+
+  pub fn is_active(&self) -> bool {
+    self.screenshot || self.sequence.is_some()
+  }
+
+  // files the current frame has to be written to
+  pub fn next_frame(&mut self, now: SystemTime) -> Vec<PathBuf> {
+    let mut res = vec![];
+    if self.screenshot {
+      self.screenshot = false;
+      res.push(self.dir.join(format!("screenshot_{}.png", timestamp(now))));
+    }
+    if let Some(sequence) = self.sequence.as_mut() {
+      res.push(sequence.dir.join(format!("frame_{:05}.png", sequence.next)));
+      sequence.next += 1;
+      if sequence.next == sequence.frames {
+        self.sequence = None;
+      }
+    }
+    res
+  }
+}
+
+fn timestamp(now: SystemTime) -> u128 {
+  now
+    .duration_since(UNIX_EPOCH)
+    .map(|d| d.as_millis())
+    .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod test {
+  use crate::render::capture::{bgra_to_rgba, save_png, to_rgba, Capture};
+  use vulkano::format::Format;
+  use std::path::PathBuf;
+  use std::time::{Duration, UNIX_EPOCH};
+
+  #[test]
+  pub fn test_bgra_to_rgba() {
+    let bgra = vec![1, 2, 3, 4, 10, 20, 30, 40];
+    assert_eq!(bgra_to_rgba(&bgra), vec![3, 2, 1, 4, 30, 20, 10, 40]);
+  }
+
+  #[test]
+  pub fn test_formats() {
+    let data = vec![1, 2, 3, 4];
+    assert_eq!(to_rgba(Format::B8G8R8A8Srgb, &data).unwrap(), vec![3, 2, 1, 4]);
+    assert_eq!(to_rgba(Format::R8G8B8A8Unorm, &data).unwrap(), data);
+    assert!(to_rgba(Format::A2B10G10R10UnormPack32, &data).is_err());
+    assert!(to_rgba(Format::R16G16B16A16Sfloat, &data).is_err());
+    let path = std::env::temp_dir().join(format!("ggga-capture-{}.png", std::process::id()));
+    assert!(save_png(&path, [1, 1], Format::R16G16B16A16Sfloat, &data).is_err());
+    // the data of one pixel for two
+    assert!(save_png(&path, [2, 1], Format::B8G8R8A8Unorm, &data).is_err());
+    assert!(!path.exists());
+  }
+
+  #[test]
+  pub fn test_screenshot_and_sequence() {
+    let now = UNIX_EPOCH + Duration::from_millis(1234);
+    let mut capture = Capture::new(PathBuf::from("shots"));
+    assert!(!capture.is_active());
+    assert!(capture.next_frame(now).is_empty());
+
+    capture.screenshot();
+    assert_eq!(
+      capture.next_frame(now),
+      vec![PathBuf::from("shots/screenshot_1234.png")]
+    );
+    assert!(capture.next_frame(now).is_empty());
+
+    capture.toggle_sequence(3, now);
+    capture.screenshot();
+    assert_eq!(capture.next_frame(now).len(), 2);
+    assert_eq!(
+      capture.next_frame(now),
+      vec![PathBuf::from("shots/sequence_1234/frame_00001.png")]
+    );
+    assert_eq!(
+      capture.next_frame(now),
+      vec![PathBuf::from("shots/sequence_1234/frame_00002.png")]
+    );
+    assert!(!capture.is_active());
+    assert!(capture.next_frame(now).is_empty());
+
+    // toggling again stops a running sequence
+    capture.toggle_sequence(3, now);
+    capture.next_frame(now);
+    capture.toggle_sequence(3, now);
+    assert!(!capture.is_active());
+  }
+}
@@ -0,0 +1,98 @@
+use cgmath::{InnerSpace, Vector3};
+
+use std::f32::consts::PI;
+
+// the sun's path leans towards -z, it is never straight above
+const SUN_TILT: f32 = 0.5;
+const NOON_COLOR: [f32; 3] = [0.6, 0.6, 0.65];
+const SUNSET_COLOR: [f32; 3] = [0.7, 0.35, 0.15];
+const DAY_AMBIENT: [f32; 3] = [0.08, 0.08, 0.1];
+const NIGHT_AMBIENT: [f32; 3] = [0.01, 0.012, 0.03];
+
+// the sun and sky at a time of day
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Daylight {
+  // points at the sun
+  pub sun_direction: Vector3<f32>,
+  pub sun_color: [f32; 3],
+  pub ambient_color: [f32; 3],
+  // 0.0 by day, 1.0 once the sun is well below the horizon
+  pub night: f32,
+}
+
+impl Daylight {
+  // hours since midnight, the sun rises at 6 and sets at 18
+  pub fn at(hours: f32) -> Daylight {
+    let angle = (hours - 6.0) / 12.0 * PI;
+    // the world is y down
+    let sun_direction = Vector3::new(-angle.cos(), -angle.sin(), -SUN_TILT).normalize();
+    let elevation = -sun_direction.y;
+    let day = smoothstep(-0.05, 0.1, elevation);
+    let sun_color = mix(SUNSET_COLOR, NOON_COLOR, smoothstep(0.0, 0.4, elevation));
+    let night = 1.0 - smoothstep(-0.15, 0.05, elevation);
+    Daylight {
+      sun_direction,
+      sun_color: [sun_color[0] * day, sun_color[1] * day, sun_color[2] * day],
+      ambient_color: mix(DAY_AMBIENT, NIGHT_AMBIENT, night),
+      night,
+    }
+  }
+
+  // 1.0 by day, what is lit by the sky keeps a little light at night
+  pub fn sky_brightness(&self) -> f32 {
+    1.0 - 0.9 * self.night
+  }
+
+  // fog is lit by the sky, it darkens with it
+  pub fn fog_color(&self, color: [f32; 3]) -> [f32; 3] {
+    let light = self.sky_brightness();
+    [color[0] * light, color[1] * light, color[2] * light]
+  }
+}
+
+fn smoothstep(from: f32, to: f32, x: f32) -> f32 {
+  let t = ((x - from) / (to - from)).max(0.0).min(1.0);
+  t * t * (3.0 - 2.0 * t)
+}
+
+fn mix(a: [f32; 3], b: [f32; 3], t: f32) -> [f32; 3] {
+  [
+    a[0] + (b[0] - a[0]) * t,
+    a[1] + (b[1] - a[1]) * t,
+    a[2] + (b[2] - a[2]) * t,
+  ]
+}
+
+#[cfg(test)]
+mod test {
+  use crate::render::daylight::Daylight;
+
+  #[test]
+  pub fn test_sun_path() {
+    let elevation = |hours: f32| -Daylight::at(hours).sun_direction.y;
+    assert!(elevation(6.0).abs() < 1e-5);
+    assert!(elevation(18.0).abs() < 1e-5);
+    assert!(elevation(12.0) > elevation(9.0) && elevation(12.0) > elevation(15.0));
+    assert!(elevation(0.0) < 0.0);
+    // the sun rises on one side and sets on the other
+    assert!(Daylight::at(7.0).sun_direction.x * Daylight::at(17.0).sun_direction.x < 0.0);
+  }
+
+  #[test]
+  pub fn test_day_and_night() {
+    let noon = Daylight::at(12.0);
+    assert_eq!(noon.night, 0.0);
+    assert_eq!(noon.sun_color, [0.6, 0.6, 0.65]);
+    assert_eq!(noon.ambient_color, [0.08, 0.08, 0.1]);
+
+    let midnight = Daylight::at(0.0);
+    assert_eq!(midnight.night, 1.0);
+    assert_eq!(midnight.sun_color, [0.0, 0.0, 0.0]);
+    assert!(midnight.fog_color([1.0, 1.0, 1.0])[0] < 0.2);
+
+    // redder light when the sun is low
+    let morning = Daylight::at(6.5);
+    assert!(morning.sun_color[0] > 0.0);
+    assert!(morning.sun_color[0] / morning.sun_color[2] > noon.sun_color[0] / noon.sun_color[2]);
+  }
+}
@@ -0,0 +1,195 @@
+use cgmath::{InnerSpace, Point3, Vector2, Vector3, Zero};
+
+use crate::render::frustum::Aabb;
+use crate::utils::{LineVertex, MeshVertex};
+
+pub const RED: [f32; 4] = [1.0, 0.0, 0.0, 1.0];
+pub const GREEN: [f32; 4] = [0.0, 1.0, 0.0, 1.0];
+pub const BLUE: [f32; 4] = [0.0, 0.0, 1.0, 1.0];
+pub const YELLOW: [f32; 4] = [1.0, 1.0, 0.0, 1.0];
+pub const CYAN: [f32; 4] = [0.0, 1.0, 1.0, 1.0];
+pub const MAGENTA: [f32; 4] = [1.0, 0.0, 1.0, 1.0];
+
+// what the debug lines of a frame show, toggled by keys, see systems::view
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct DebugView {
+  // the vertex normals of every visible model
+  pub normals: bool,
+  // the bounding boxes models are culled by
+  pub aabbs: bool,
+  // the squares of the sky tiles around the camera
+  pub sky_grid: bool,
+  // x red, y green and z blue at the absolute world origin
+  pub axes: bool,
+}
+
+// immediate mode lines, collected again every frame and drawn as a line list
+#[derive(Debug)]
+pub struct DebugLines {
+  vertices: Vec<LineVertex>,
+  // added to every point, models drawn relative to the absolute origin need it
+  translation: Vector3<f32>,
+}
+
+impl Default for DebugLines {
+  fn default() -> Self {
+    DebugLines {
+      vertices: vec![],
+      translation: Vector3::zero(),
+    }
+  }
+}
+
+impl DebugLines {
+  pub fn set_translation(&mut self, translation: Vector3<f32>) {
+    self.translation = translation;
+  }
+
+  pub fn translation(&self) -> Vector3<f32> {
+    self.translation
+  }
+
+  pub fn vertices(&self) -> &[LineVertex] {
+    &self.vertices
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.vertices.is_empty()
+  }
+
+  pub fn line(&mut self, a: Point3<f32>, b: Point3<f32>, color: [f32; 4]) {
+    for p in [a, b].iter() {
+      let p = p + self.translation;
+      self.vertices.push(LineVertex {
+        position: (p.x, p.y, p.z),
+        color: (color[0], color[1], color[2], color[3]),
+      });
+    }
+  }
+
+  // the 12 edges of the box
+  pub fn aabb(&mut self, aabb: &Aabb, color: [f32; 4]) {
+    let c = aabb.corners();
+    // corners differing in exactly one coordinate, see Aabb::corners
+    let edges = [
+      (0, 1), (2, 3), (4, 5), (6, 7), (0, 2), (1, 3), (4, 6), (5, 7), (0, 4), (1, 5), (2, 6),
+      (3, 7),
+    ];
+    for (a, b) in edges.iter() {
+      self.line(c[*a], c[*b], color);
+    }
+  }
+
+  // a line with a head of four short lines at to
+  pub fn arrow(&mut self, from: Point3<f32>, to: Point3<f32>, color: [f32; 4]) {
+    self.line(from, to, color);
+    let along = to - from;
+    let length = along.magnitude();
+    if length < f32::EPSILON {
+      return;
+    }
+    let dir = along / length;
+    let other = if dir.y.abs() < 0.9 {
+      Vector3::unit_y()
+    } else {
+      Vector3::unit_x()
+    };
+    let side = dir.cross(other).normalize();
+    let up = dir.cross(side);
+    let head = length * 0.2;
+    let base = to - dir * head;
+    for offset in [side, -side, up, -up].iter() {
+      self.line(to, base + offset * head * 0.5, color);
+    }
+  }
+
+  // a grid of cells in the x, z plane at the height of min
+  pub fn grid(
+    &mut self,
+    min: Point3<f32>,
+    cell: Vector2<f32>,
+    cells: (usize, usize),
+    color: [f32; 4],
+  ) {
+    let size = Vector3::new(cell.x * cells.0 as f32, 0.0, cell.y * cells.1 as f32);
+    for i in 0..=cells.0 {
+      let a = min + Vector3::new(cell.x * i as f32, 0.0, 0.0);
+      self.line(a, a + Vector3::new(0.0, 0.0, size.z), color);
+    }
+    for j in 0..=cells.1 {
+      let a = min + Vector3::new(0.0, 0.0, cell.y * j as f32);
+      self.line(a, a + Vector3::new(size.x, 0.0, 0.0), color);
+    }
+  }
+
+  // x red, y green and z blue, y points down
+  pub fn axes(&mut self, origin: Point3<f32>, length: f32) {
+    self.arrow(origin, origin + Vector3::unit_x() * length, RED);
+    self.arrow(origin, origin + Vector3::unit_y() * length, GREEN);
+    self.arrow(origin, origin + Vector3::unit_z() * length, BLUE);
+  }
+
+  // the normal of every vertex, as the vertex shader gets it
+  pub fn normals(&mut self, vertices: &[MeshVertex], length: f32, color: [f32; 4]) {
+    for v in vertices {
+      let p = Point3::from(v.position);
+      let n = Vector3::from(v.normal);
+      if n.magnitude2() < f32::EPSILON {
+        continue;
+      }
+      self.line(p, p + n.normalize() * length, color);
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use cgmath::{Point3, Vector2, Vector3};
+
+  use crate::render::debug_lines::{DebugLines, RED};
+  use crate::render::frustum::Aabb;
+  use crate::utils::MeshVertex;
+
+  #[test]
+  pub fn test_line_counts() {
+    let mut lines = DebugLines::default();
+    assert!(lines.is_empty());
+    let aabb = Aabb {
+      min: Point3::new(0.0, 0.0, 0.0),
+      max: Point3::new(1.0, 2.0, 3.0),
+    };
+    lines.aabb(&aabb, RED);
+    assert_eq!(lines.vertices().len(), 12 * 2);
+    // every edge of the box is axis aligned
+    for edge in lines.vertices().chunks(2) {
+      let (a, b) = (edge[0].position, edge[1].position);
+      let same = [a.0 == b.0, a.1 == b.1, a.2 == b.2];
+      assert_eq!(same.iter().filter(|s| **s).count(), 2);
+    }
+
+    let mut lines = DebugLines::default();
+    lines.grid(Point3::new(0.0, 0.0, 0.0), Vector2::new(100.0, 100.0), (3, 2), RED);
+    assert_eq!(lines.vertices().len(), (4 + 3) * 2);
+
+    let mut lines = DebugLines::default();
+    lines.axes(Point3::new(0.0, 0.0, 0.0), 1.0);
+    assert_eq!(lines.vertices().len(), 3 * 5 * 2);
+  }
+
+  #[test]
+  pub fn test_translation_and_normals() {
+    let mut lines = DebugLines::default();
+    lines.set_translation(Vector3::new(10.0, 0.0, 0.0));
+    let vertex = MeshVertex {
+      position: (1.0, 2.0, 3.0),
+      normal: (0.0, -2.0, 0.0),
+      ..Default::default()
+    };
+    let flat = MeshVertex::default();
+    lines.normals(&[vertex, flat], 0.5, RED);
+    // the vertex without a normal has no line
+    assert_eq!(lines.vertices().len(), 2);
+    assert_eq!(lines.vertices()[0].position, (11.0, 2.0, 3.0));
+    assert_eq!(lines.vertices()[1].position, (11.0, 1.5, 3.0));
+  }
+}
@@ -0,0 +1,219 @@
+use vulkano::instance::PhysicalDeviceType;
+
+use std::fmt;
+
+// overrides Settings::device
+pub const DEVICE_ENV: &str = "GGGA_DEVICE";
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DeviceKind {
+  Discrete,
+  Integrated,
+  Virtual,
+  Cpu,
+  Other,
+}
+
+impl From<PhysicalDeviceType> for DeviceKind {
+  fn from(ty: PhysicalDeviceType) -> Self {
+    match ty {
+      PhysicalDeviceType::DiscreteGpu => DeviceKind::Discrete,
+      PhysicalDeviceType::IntegratedGpu => DeviceKind::Integrated,
+      PhysicalDeviceType::VirtualGpu => DeviceKind::Virtual,
+      PhysicalDeviceType::Cpu => DeviceKind::Cpu,
+      PhysicalDeviceType::Other => DeviceKind::Other,
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceDescription {
+  pub index: usize,
+  pub name: String,
+  pub kind: DeviceKind,
+  // has a graphics queue, that can present to the surface when there is one
+  pub usable: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DeviceOverride {
+  Index(usize),
+  // case insensitive substring of the device name
+  Name(String),
+}
+
+impl DeviceOverride {
+  pub fn parse(s: &str) -> Option<DeviceOverride> {
+    let s = s.trim();
+    if s.is_empty() {
+      None
+    } else if let Ok(index) = s.parse() {
+      Some(DeviceOverride::Index(index))
+    } else {
+      Some(DeviceOverride::Name(s.to_lowercase()))
+    }
+  }
+
+  // the environment variable wins over the settings
+  pub fn from_env_or(settings: Option<&str>) -> Option<DeviceOverride> {
+    std::env::var(DEVICE_ENV)
+      .ok()
+      .and_then(|s| DeviceOverride::parse(&s))
+      .or_else(|| settings.and_then(DeviceOverride::parse))
+  }
+
+  fn matches(&self, device: &DeviceDescription) -> bool {
+    match self {
+      DeviceOverride::Index(index) => device.index == *index,
+      DeviceOverride::Name(name) => device.name.to_lowercase().contains(name.as_str()),
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceSelection {
+  pub index: usize,
+  pub reason: String,
+}
+
+impl fmt::Display for DeviceSelection {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "device #{}: {}", self.index, self.reason)
+  }
+}
+
+// picks from plain descriptions, so the policy can be tested without vulkan.
+// discrete before integrated before virtual before cpu, unless overridden
+pub fn select_device(
+  devices: &[DeviceDescription],
+  device_override: Option<&DeviceOverride>,
+) -> Option<DeviceSelection> {
+  let mut notes = vec![];
+  if let Some(device_override) = device_override {
+    match devices.iter().find(|d| device_override.matches(d)) {
+      Some(device) if device.usable => {
+        return Some(DeviceSelection {
+          index: device.index,
+          reason: format!(
+            "{} ({:?}) requested by {:?}",
+            device.name, device.kind, device_override
+          ),
+        });
+      }
+      Some(device) => notes.push(format!(
+        "requested {} ({:?}) has no usable graphics queue",
+        device.name, device.kind
+      )),
+      None => notes.push(format!("no device matches {:?}", device_override)),
+    }
+  }
+  // min_by_key keeps the first of equally ranked devices
+  let best = devices
+    .iter()
+    .filter(|d| d.usable)
+    .min_by_key(|d| d.kind)?;
+  notes.push(format!(
+    "{} ({:?}) is the best ranked of {} devices",
+    best.name,
+    best.kind,
+    devices.len()
+  ));
+  Some(DeviceSelection {
+    index: best.index,
+    reason: notes.join(", "),
+  })
+}
+
+// the msaa sample count used for a requested one, 1, 2, 4 or 8 and supported by the device.
+// supported is a vulkan sample count mask, bit n set when 2^n samples work
+pub fn clamp_samples(requested: u32, supported: u32) -> u32 {
+  let requested = requested.max(1).min(8);
+  let mut samples = 1 << (31 - requested.leading_zeros());
+  while samples > 1 && supported & samples == 0 {
+    samples /= 2;
+  }
+  samples
+}
+
+#[cfg(test)]
+mod test {
+  use crate::render::device::{
+    clamp_samples, select_device, DeviceDescription, DeviceKind, DeviceOverride,
+  };
+
+  fn device(index: usize, name: &str, kind: DeviceKind, usable: bool) -> DeviceDescription {
+    DeviceDescription {
+      index,
+      name: name.to_string(),
+      kind,
+      usable,
+    }
+  }
+
+  fn laptop() -> Vec<DeviceDescription> {
+    vec![
+      device(0, "llvmpipe (LLVM 12.0.0, 256 bits)", DeviceKind::Cpu, true),
+      device(1, "Intel(R) UHD Graphics 620", DeviceKind::Integrated, true),
+      device(2, "NVIDIA GeForce MX150", DeviceKind::Discrete, false),
+    ]
+  }
+
+  #[test]
+  pub fn test_ranking() {
+    let selected = select_device(&laptop(), None).unwrap();
+    assert_eq!(selected.index, 1);
+
+    let mut devices = laptop();
+    devices[2].usable = true;
+    assert_eq!(select_device(&devices, None).unwrap().index, 2);
+
+    let software = vec![
+      device(0, "virtio", DeviceKind::Virtual, false),
+      device(1, "llvmpipe", DeviceKind::Cpu, true),
+      device(2, "swiftshader", DeviceKind::Cpu, true),
+    ];
+    assert_eq!(select_device(&software, None).unwrap().index, 1);
+    assert_eq!(select_device(&[], None), None);
+    assert_eq!(
+      select_device(&[device(0, "x", DeviceKind::Discrete, false)], None),
+      None
+    );
+  }
+
+  #[test]
+  pub fn test_override() {
+    let devices = laptop();
+    let by_index = DeviceOverride::parse("0").unwrap();
+    assert_eq!(by_index, DeviceOverride::Index(0));
+    assert_eq!(select_device(&devices, Some(&by_index)).unwrap().index, 0);
+
+    let by_name = DeviceOverride::parse(" LLVMpipe ").unwrap();
+    assert_eq!(select_device(&devices, Some(&by_name)).unwrap().index, 0);
+
+    // unusable or missing devices fall back to the ranking and say why
+    let unusable = DeviceOverride::parse("geforce").unwrap();
+    let selected = select_device(&devices, Some(&unusable)).unwrap();
+    assert_eq!(selected.index, 1);
+    assert!(selected.reason.contains("no usable graphics queue"));
+
+    let missing = DeviceOverride::Index(7);
+    let selected = select_device(&devices, Some(&missing)).unwrap();
+    assert_eq!(selected.index, 1);
+    assert!(selected.reason.contains("no device matches"));
+
+    assert_eq!(DeviceOverride::parse("  "), None);
+  }
+
+  #[test]
+  pub fn test_clamp_samples() {
+    // 1, 2, 4 and 8
+    let supported = 0b1111;
+    assert_eq!(clamp_samples(4, supported), 4);
+    assert_eq!(clamp_samples(0, supported), 1);
+    assert_eq!(clamp_samples(3, supported), 2);
+    assert_eq!(clamp_samples(16, supported), 8);
+    // 1 and 4
+    assert_eq!(clamp_samples(8, 0b0101), 4);
+    assert_eq!(clamp_samples(2, 0b0101), 1);
+  }
+}
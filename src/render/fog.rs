@@ -0,0 +1,89 @@
+use cgmath::{InnerSpace, Point3, Vector3};
+
+// exponential height fog, thickest below height and thinning out above it.
+// the world is y down, so the altitude of a point is -y
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Fog {
+  pub color: [f32; 3],
+  // extinction per unit of distance at the fog height, 0.0 turns the fog off
+  pub density: f32,
+  // how fast the density drops with altitude
+  pub height_falloff: f32,
+  pub height: f32,
+}
+
+impl Default for Fog {
+  fn default() -> Self {
+    Fog {
+      color: [0.6, 0.65, 0.75],
+      density: 0.01,
+      height_falloff: 0.05,
+      height: 0.0,
+    }
+  }
+}
+
+impl Fog {
+  // the same fog for coordinates relative to a shifted world origin
+  pub fn at_origin(&self, origin: Vector3<f32>) -> Fog {
+    Fog {
+      height: self.height + origin.y,
+      ..*self
+    }
+  }
+
+  // how much of the light from position is replaced by fog before it reaches the camera,
+  // the density integrated along the ray, the main fragment shader does the same
+  pub fn amount(&self, camera: Point3<f32>, position: Point3<f32>) -> f32 {
+    let ray = position - camera;
+    let distance = ray.magnitude();
+    let camera_density =
+      self.density * (-self.height_falloff * (-camera.y - self.height)).min(80.0).exp();
+    let rise = -ray.y * self.height_falloff;
+    let integral = if rise.abs() > 1e-4 {
+      (1.0 - (-rise).exp()) / rise
+    } else {
+      1.0
+    };
+    1.0 - (-camera_density * integral * distance).exp()
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::render::fog::Fog;
+  use cgmath::{Point3, Vector3};
+
+  #[test]
+  pub fn test_fog_amount() {
+    let fog = Fog::default();
+    let camera = Point3::new(0.0, 0.0, 0.0);
+    assert_eq!(fog.amount(camera, camera), 0.0);
+
+    // along the fog height the density is constant
+    let horizontal = fog.amount(camera, Point3::new(0.0, 0.0, 100.0));
+    assert!((horizontal - (1.0 - (-fog.density * 100.0).exp())).abs() < 1e-5);
+    assert!(fog.amount(camera, Point3::new(0.0, 0.0, 1000.0)) > horizontal);
+
+    // looking up goes through thinner fog than looking down
+    let up = fog.amount(camera, Point3::new(0.0, -50.0, 86.6));
+    let down = fog.amount(camera, Point3::new(0.0, 50.0, 86.6));
+    assert!(up < horizontal && horizontal < down, "{} {} {}", up, horizontal, down);
+
+    let off = Fog {
+      density: 0.0,
+      ..fog
+    };
+    assert_eq!(off.amount(camera, Point3::new(0.0, 0.0, 100.0)), 0.0);
+  }
+
+  #[test]
+  pub fn test_fog_at_origin() {
+    let fog = Fog::default();
+    let origin = Vector3::new(100.0, -30.0, 7.0);
+    let (camera, position) = (Point3::new(100.0, -20.0, 0.0), Point3::new(150.0, -10.0, 40.0));
+    let shifted = fog.at_origin(origin);
+    let local = shifted.amount(camera - origin, position - origin);
+    assert!((fog.amount(camera, position) - local).abs() < 1e-5);
+  }
+}
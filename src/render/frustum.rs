@@ -0,0 +1,153 @@
+use cgmath::{InnerSpace, Matrix4, Point3, Transform, Vector3, Vector4};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Aabb {
+  pub min: Point3<f32>,
+  pub max: Point3<f32>,
+}
+
+impl Aabb {
+  pub fn from_points<I>(points: I) -> Option<Aabb>
+  where
+    I: IntoIterator<Item = Point3<f32>>,
+  {
+    let mut iter = points.into_iter();
+    let first = iter.next()?;
+    Some(iter.fold(
+      Aabb {
+        min: first,
+        max: first,
+      },
+      |aabb, p| Aabb {
+        min: Point3::new(aabb.min.x.min(p.x), aabb.min.y.min(p.y), aabb.min.z.min(p.z)),
+        max: Point3::new(aabb.max.x.max(p.x), aabb.max.y.max(p.y), aabb.max.z.max(p.z)),
+      },
+    ))
+  }
+
+  pub fn corners(&self) -> [Point3<f32>; 8] {
+    let (a, b) = (self.min, self.max);
+    [
+      Point3::new(a.x, a.y, a.z),
+      Point3::new(b.x, a.y, a.z),
+      Point3::new(a.x, b.y, a.z),
+      Point3::new(b.x, b.y, a.z),
+      Point3::new(a.x, a.y, b.z),
+      Point3::new(b.x, a.y, b.z),
+      Point3::new(a.x, b.y, b.z),
+      Point3::new(b.x, b.y, b.z),
+    ]
+  }
+
+  pub fn translate(&self, by: Vector3<f32>) -> Aabb {
+    Aabb {
+      min: self.min + by,
+      max: self.max + by,
+    }
+  }
+
+  pub fn transform(&self, m: &Matrix4<f32>) -> Aabb {
+    Aabb::from_points(self.corners().iter().map(|p| m.transform_point(*p))).unwrap()
+  }
+}
+
+// planes as (normal, distance), the inside is where dot(normal, p) + distance >= 0
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Frustum {
+  planes: [Vector4<f32>; 6],
+}
+
+impl Frustum {
+  // Gribb and Hartmann, planes from the rows of proj * view * world.
+  // cgmath builds opengl style matrices, -w <= z is a bit more permissive than
+  // the 0 <= z vulkan clips to, so nothing visible is ever culled
+  pub fn from_matrix(m: Matrix4<f32>) -> Frustum {
+    let row = |i: usize| Vector4::new(m[0][i], m[1][i], m[2][i], m[3][i]);
+    let (r0, r1, r2, r3) = (row(0), row(1), row(2), row(3));
+    let normalize = |p: Vector4<f32>| p / p.truncate().magnitude();
+    Frustum {
+      planes: [
+        normalize(r3 + r0),
+        normalize(r3 - r0),
+        normalize(r3 + r1),
+        normalize(r3 - r1),
+        normalize(r3 + r2),
+        normalize(r3 - r2),
+      ],
+    }
+  }
+
+  pub fn intersects(&self, aabb: &Aabb) -> bool {
+    self.planes.iter().all(|plane| {
+      // the corner furthest along the plane normal
+      let p = Vector3::new(
+        if plane.x >= 0.0 { aabb.max.x } else { aabb.min.x },
+        if plane.y >= 0.0 { aabb.max.y } else { aabb.min.y },
+        if plane.z >= 0.0 { aabb.max.z } else { aabb.min.z },
+      );
+      plane.truncate().dot(p) + plane.w >= 0.0
+    })
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::render::frustum::{Aabb, Frustum};
+  use cgmath::{Matrix4, Point3, Rad, Vector3};
+
+  // looking down +z from the origin, like the camera starts
+  fn frustum() -> Frustum {
+    let mut proj = cgmath::perspective(Rad(std::f32::consts::FRAC_PI_2), 1.5, 0.1, 200.0);
+    proj[0][0] = -proj[0][0];
+    let view = Matrix4::look_at_rh(
+      Point3::new(0.0, 0.0, 0.0),
+      Point3::new(0.0, 0.0, 1.0),
+      Vector3::new(0.0, 1.0, 0.0),
+    );
+    Frustum::from_matrix(proj * view)
+  }
+
+  fn cube(x: f32, y: f32, z: f32) -> Aabb {
+    Aabb {
+      min: Point3::new(x - 1.0, y - 1.0, z - 1.0),
+      max: Point3::new(x + 1.0, y + 1.0, z + 1.0),
+    }
+  }
+
+  #[test]
+  pub fn test_aabb_from_points() {
+    let aabb = Aabb::from_points(vec![
+      Point3::new(1.0, -2.0, 3.0),
+      Point3::new(-1.0, 5.0, 0.0),
+    ])
+    .unwrap();
+    assert_eq!(aabb.min, Point3::new(-1.0, -2.0, 0.0));
+    assert_eq!(aabb.max, Point3::new(1.0, 5.0, 3.0));
+    assert_eq!(Aabb::from_points(vec![]), None);
+
+    let moved = aabb.transform(&Matrix4::from_translation(Vector3::new(10.0, 0.0, 0.0)));
+    assert_eq!(moved.min, Point3::new(9.0, -2.0, 0.0));
+  }
+
+  #[test]
+  pub fn test_frustum_culls_boxes() {
+    let frustum = frustum();
+    assert!(frustum.intersects(&cube(0.0, 0.0, 10.0)));
+    // behind the camera
+    assert!(!frustum.intersects(&cube(0.0, 0.0, -10.0)));
+    // beyond the far plane
+    assert!(!frustum.intersects(&cube(0.0, 0.0, 300.0)));
+    // far out to the sides, above and below
+    assert!(!frustum.intersects(&cube(100.0, 0.0, 10.0)));
+    assert!(!frustum.intersects(&cube(-100.0, 0.0, 10.0)));
+    assert!(!frustum.intersects(&cube(0.0, 100.0, 10.0)));
+    assert!(!frustum.intersects(&cube(0.0, -100.0, 10.0)));
+    // straddling the side plane, the 90 degree vertical fov reaches y = z
+    assert!(frustum.intersects(&cube(0.0, 10.5, 10.0)));
+    // surrounding the camera
+    assert!(frustum.intersects(&Aabb {
+      min: Point3::new(-50.0, -50.0, -50.0),
+      max: Point3::new(50.0, 50.0, 50.0),
+    }));
+  }
+}
@@ -1,15 +1,16 @@
-use cgmath::{Matrix4, Point2, Point3, Transform, One};
+use cgmath::{EuclideanSpace, Matrix4, Point2, Point3, Transform, One, Vector4};
 use gltf::buffer;
-use gltf::mesh::BoundingBox;
+use gltf::image;
 use gltf::scene::Node;
 
 use std::collections::HashMap;
 use std::option::Option;
 use std::path::Path;
 
-use crate::render::mymesh::{InterestingMeshData, MyMesh, MyMeshData};
-use crate::render::mymesh::transform_decomposed;
-use crate::render::{Normals, Vertex, Tex, TexOffset, Index, Trans, InvTrans};
+use crate::render::material::Material;
+use crate::render::mymesh::{InterestingMeshData, MyMesh, MyMeshData, WHITE};
+use crate::render::mymesh::{normal_matrix, transform_decomposed};
+use crate::render::{Normals, Vertex, Tex, TexOffset, Color, Index, Trans, InvTrans};
 
 
 #[derive(Default, Debug)]
@@ -19,6 +20,7 @@ struct State {
   all_normals: Normals,
   all_tex: Tex,
   all_tex_offset: TexOffset,
+  all_color: Color,
   all_index: Index,
 }
 
@@ -29,6 +31,7 @@ impl State {
     normals: &mut Normals,
     tex: &mut Tex,
     tex_offset: &mut TexOffset,
+    color: &mut Color,
     index: &mut Index,
     current_transform: Option<(Trans, InvTrans)>,
   ) {
@@ -36,13 +39,15 @@ impl State {
       for vert in vertex.iter_mut() {
         *vert = current_transform.transform_point(*vert);
       }
+      let normal_transform = normal_matrix(&current_transform);
       for norm in normals.iter_mut() {
-        *norm = current_transform.transform_point(*norm);
+        *norm = Point3::from_vec(normal_transform * norm.to_vec());
       }
     }
     self.all_normals.append(normals);
     self.all_tex.append(tex);
     self.all_tex_offset.append(tex_offset);
+    self.all_color.append(color);
     for ind in index.iter_mut() {
       *ind = self.last_index + *ind;
     }
@@ -51,12 +56,26 @@ impl State {
     self.all_vertex.append(vertex);
   }
 
+  // the primitives of other after the ones of self, they are transformed already
+  pub fn append(&mut self, other: &mut State) {
+    self.collect(
+      &mut other.all_vertex,
+      &mut other.all_normals,
+      &mut other.all_tex,
+      &mut other.all_tex_offset,
+      &mut other.all_color,
+      &mut other.all_index,
+      None,
+    );
+  }
+
   pub fn build_mesh_data(self, trans: Trans, inv_trans: Trans) -> MyMeshData {
     MyMeshData {
       vertex: self.all_vertex,
       normals: self.all_normals,
       tex: self.all_tex,
       tex_offset: self.all_tex_offset,
+      color: self.all_color,
       index: self.all_index,
       transform: trans,
       inverse_transform: inv_trans,
@@ -76,6 +95,7 @@ impl State {
       self.all_vertex,
       self.all_tex,
       self.all_tex_offset,
+      self.all_color,
       self.all_normals,
       self.all_index,
       transform,
@@ -85,26 +105,61 @@ impl State {
   }
 }
 
+// the primitives drawn with one material, key is the index of the gltf material
+#[derive(Debug)]
+struct Part {
+  key: Option<usize>,
+  material: Material,
+  state: State,
+}
+
+// the part of the material key, parts are added in the order their materials are first used
+fn part_index<F>(parts: &mut Vec<Part>, key: Option<usize>, material: F) -> usize
+where
+  F: FnOnce() -> Material,
+{
+  parts.iter().position(|part| part.key == key).unwrap_or_else(|| {
+    parts.push(Part {
+      key,
+      material: material(),
+      state: State::default(),
+    });
+    parts.len() - 1
+  })
+}
+
 #[derive(Debug)]
 struct VisitState {
   b: Vec<buffer::Data>,
-  state: State,
+  images: Vec<image::Data>,
+  parts: Vec<Part>,
   interesting_state: State,
-  bounding_boxes: Vec<BoundingBox>,
   print: bool,
   interesting_map: HashMap<String, MyMeshData>,
 }
 
 impl VisitState {
-  pub fn finish(self, transform: Trans, print: bool) -> MyMesh {
-    let mut res = self.state.build_mesh(self.interesting_map, transform, print);
+  // a mesh per material, the interesting parts go with the first one
+  pub fn finish(self, transform: Trans, print: bool) -> Vec<MyMesh> {
+    let mut interesting_map = Some(self.interesting_map);
+    self
+      .parts
+      .into_iter()
+      .map(|part| {
+        let interesting_map = interesting_map.take().unwrap_or_default();
+        let mesh = part.state.build_mesh(interesting_map, transform, print);
+        mesh.with_material(part.material)
+      })
+      .collect()
+  }
 
-    if print {
-      for bounding_box in self.bounding_boxes {
-        res.add_bounding_box(bounding_box.min, bounding_box.max);
-      }
+  // all the primitives in one mesh drawn with the default material
+  pub fn finish_merged(self, transform: Trans, print: bool) -> MyMesh {
+    let mut state = State::default();
+    for mut part in self.parts {
+      state.append(&mut part.state);
     }
-    res
+    state.build_mesh(self.interesting_map, transform, print)
   }
 }
 
@@ -139,9 +194,11 @@ fn collect_mesh(
     });
     let mut interesting_state = State::default();
     for primitive in mesh.primitives() {
-      visit_state
-        .bounding_boxes
-        .push(primitive.bounding_box().clone());
+      let material = primitive.material();
+      let images = &visit_state.images;
+      let part = part_index(&mut visit_state.parts, material.index(), || {
+        Material::from_gltf(&material, images)
+      });
       let reader = primitive.reader(|buffer| Some(&visit_state.b[buffer.index()]));
       let mut vertex = {
         let iter = reader.read_positions().unwrap_or_else(|| {
@@ -159,12 +216,21 @@ fn collect_mesh(
           })
           .collect::<Vec<_>>()
       };
-      let mut tex: Vec<Point2<f32>> = (0..vertex.len())
-        .map(|_i| Point2::new(-1.0, -1.0))
-        .collect();
+      // negative tex coordinates are untextured, materials without maps keep those so the
+      // mesh stays untextured when drawn with the default material
+      let has_maps = visit_state.parts[part].material.has_maps();
+      let mut tex: Vec<Point2<f32>> = reader
+        .read_tex_coords(0)
+        .filter(|_| has_maps)
+        .map(|tex| tex.into_f32().map(Point2::from).collect())
+        .unwrap_or_else(|| (0..vertex.len()).map(|_i| Point2::new(-1.0, -1.0)).collect());
 
       let mut tex_offset: Vec<Point2<i32>> =
         (0..vertex.len()).map(|_i| Point2::new(0, 0)).collect();
+      let mut color: Vec<Vector4<f32>> = reader
+        .read_colors(0)
+        .map(|colors| colors.into_rgba_f32().map(Vector4::from).collect())
+        .unwrap_or_else(|| vec![WHITE; vertex.len()]);
       let mut normals = {
         let iter = reader.read_normals().unwrap_or_else(|| {
           panic!(
@@ -190,6 +256,7 @@ fn collect_mesh(
           &mut normals.clone(),
           &mut tex.clone(),
           &mut tex_offset.clone(),
+          &mut color.clone(),
           &mut index.clone(),
           current_transform,
         );
@@ -203,11 +270,12 @@ fn collect_mesh(
           index.len()
         );
       }
-      visit_state.state.collect(
+      visit_state.parts[part].state.collect(
         &mut vertex,
         &mut normals,
         &mut tex,
         &mut tex_offset,
+        &mut color,
         &mut index,
         current_transform,
       );
@@ -253,9 +321,21 @@ fn collect_mesh(
   }
 }
 
-pub fn from_gltf(path: &Path, print: bool) -> MyMesh {
+// a mesh per material used by the primitives, the meshes share the root transform
+pub fn from_gltf(path: &Path, print: bool) -> Vec<MyMesh> {
+  let (visit_state, transform) = visit_gltf(path, print);
+  visit_state.finish(transform, print)
+}
+
+// the geometry of all the primitives, for models that are drawn with the default material
+pub fn from_gltf_merged(path: &Path, print: bool) -> MyMesh {
+  let (visit_state, transform) = visit_gltf(path, print);
+  visit_state.finish_merged(transform, print)
+}
+
+fn visit_gltf(path: &Path, print: bool) -> (VisitState, Trans) {
   println!("glb {:?}", path);
-  let (d, b, _i) = gltf::import(path).unwrap();
+  let (d, b, images) = gltf::import(path).unwrap();
 
   let default_scene = d.default_scene().unwrap();
   if default_scene.nodes().len() != 1 {
@@ -265,13 +345,71 @@ pub fn from_gltf(path: &Path, print: bool) -> MyMesh {
 
   let mut visit_state = VisitState {
     b,
-    state: State::default(),
+    images,
+    parts: vec![],
     interesting_state: State::default(),
-    bounding_boxes: Vec::default(),
     interesting_map: HashMap::default(),
     print: print,
   };
   collect_mesh(&mut visit_state, &root_node, None);
   let transform = Matrix4::from(root_node.transform().matrix());
-  visit_state.finish(transform, print)
+  (visit_state, transform)
+}
+
+#[cfg(test)]
+mod test {
+  use crate::render::gltfimporter::{part_index, State, VisitState};
+  use crate::render::material::Material;
+  use crate::render::mymesh::WHITE;
+  use cgmath::{Matrix4, One, Point2, Point3};
+  use std::collections::HashMap;
+
+  fn triangle(state: &mut State, x: f32) {
+    state.collect(
+      &mut vec![
+        Point3::new(x, 0.0, 0.0),
+        Point3::new(x + 1.0, 0.0, 0.0),
+        Point3::new(x, 1.0, 0.0),
+      ],
+      &mut vec![Point3::new(0.0, 0.0, 1.0); 3],
+      &mut vec![Point2::new(-1.0, -1.0); 3],
+      &mut vec![Point2::new(0, 0); 3],
+      &mut vec![WHITE; 3],
+      &mut vec![0, 1, 2],
+      None,
+    );
+  }
+
+  #[test]
+  pub fn test_mesh_per_material() {
+    let mut visit_state = VisitState {
+      b: vec![],
+      images: vec![],
+      parts: vec![],
+      interesting_state: State::default(),
+      print: false,
+      interesting_map: HashMap::default(),
+    };
+    let red = [1.0, 0.0, 0.0, 1.0];
+    let blue = [0.0, 0.0, 1.0, 1.0];
+    // primitives of the red, the blue and again the red material
+    for &(key, base_color, x) in &[(Some(3), red, 0.0), (Some(5), blue, 2.0), (Some(3), red, 4.0)] {
+      let material = || Material {
+        base_color,
+        ..Default::default()
+      };
+      let part = part_index(&mut visit_state.parts, key, material);
+      triangle(&mut visit_state.parts[part].state, x);
+    }
+    let meshes = visit_state.finish(Matrix4::one(), false);
+    assert_eq!(meshes.len(), 2);
+    let (red_mesh, blue_mesh) = (&meshes[0], &meshes[1]);
+    assert_eq!(red_mesh.material.as_ref().unwrap().base_color, red);
+    assert_eq!(blue_mesh.material.as_ref().unwrap().base_color, blue);
+    assert_eq!(red_mesh.data.vertex.len(), 6);
+    assert_eq!(red_mesh.data.index, vec![0, 1, 2, 3, 4, 5]);
+    assert_eq!(red_mesh.data.vertex[3], Point3::new(4.0, 0.0, 0.0));
+    assert_eq!(blue_mesh.data.index, vec![0, 1, 2]);
+    assert_eq!(blue_mesh.data.vertex[0], Point3::new(2.0, 0.0, 0.0));
+  }
 }
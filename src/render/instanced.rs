@@ -0,0 +1,169 @@
+use vulkano::buffer::{BufferAccess, CpuAccessibleBuffer};
+use vulkano::command_buffer::{AutoCommandBufferBuilder, DynamicState, PrimaryAutoCommandBuffer};
+use vulkano::descriptor::descriptor_set::DescriptorSetsCollection;
+use vulkano::pipeline::GraphicsPipelineAbstract;
+use profiling;
+
+use cgmath::{Matrix4, Vector4};
+
+use std::sync::Arc;
+
+use crate::render::frustum::{Aabb, Frustum};
+use crate::render::model::IndexBuffer;
+use crate::utils::{Instance, MeshVertex};
+
+// no tex rect, the tex coordinates of the mesh are used as they are
+const NO_TEX_RECT: (f32, f32, f32, f32) = (-1.0, -1.0, -1.0, -1.0);
+
+impl Instance {
+  pub fn new(transform: Matrix4<f32>) -> Self {
+    let column = |c: Vector4<f32>| (c.x, c.y, c.z, c.w);
+    Instance {
+      instance_x: column(transform.x),
+      instance_y: column(transform.y),
+      instance_z: column(transform.z),
+      instance_w: column(transform.w),
+      instance_tex_rect: NO_TEX_RECT,
+    }
+  }
+
+  // min u, min v, max u, max v of the texture
+  pub fn with_tex_rect(self, rect: [f32; 4]) -> Self {
+    Instance {
+      instance_tex_rect: (rect[0], rect[1], rect[2], rect[3]),
+      ..self
+    }
+  }
+
+  pub fn transform(&self) -> Matrix4<f32> {
+    let column = |c: (f32, f32, f32, f32)| Vector4::new(c.0, c.1, c.2, c.3);
+    Matrix4::from_cols(
+      column(self.instance_x),
+      column(self.instance_y),
+      column(self.instance_z),
+      column(self.instance_w),
+    )
+  }
+}
+
+// one mesh drawn many times with a single draw call, each instance brings its own transform
+#[derive(Clone)]
+pub struct InstancedModel {
+  vertex: Arc<CpuAccessibleBuffer<[MeshVertex]>>,
+  index: IndexBuffer,
+  aabb: Option<Aabb>,
+}
+
+impl InstancedModel {
+  pub fn new(
+    vertex: Arc<CpuAccessibleBuffer<[MeshVertex]>>,
+    index: IndexBuffer,
+    aabb: Option<Aabb>,
+  ) -> Self {
+    InstancedModel { vertex, index, aabb }
+  }
+
+  pub fn aabb(&self) -> Option<Aabb> {
+    self.aabb
+  }
+
+  #[profiling::function]
+  pub fn draw_instanced<S>(
+    &self,
+    builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+    dynamic: &DynamicState,
+    set: S,
+    instances: Arc<dyn BufferAccess + Send + Sync>,
+  ) where
+    S: DescriptorSetsCollection,
+  {
+    let vertex = self.vertex.clone() as Arc<dyn BufferAccess + Send + Sync>;
+    self
+      .index
+      .draw(builder, pipeline, dynamic, vec![vertex, instances], set, ());
+  }
+}
+
+// the instances of the last frame inside the frustum. the bounds of the instances are only
+// computed again when the instances change, and culled again when the frustum moved
+#[derive(Default)]
+pub struct InstanceCulling {
+  instances: Vec<Instance>,
+  // None when the mesh is empty, those are never culled
+  bounds: Vec<Option<Aabb>>,
+  frustum: Option<Frustum>,
+  visible: Vec<Instance>,
+}
+
+impl InstanceCulling {
+  // aabb is the one of the mesh shared by the instances
+  pub fn visible(
+    &mut self,
+    aabb: Option<Aabb>,
+    instances: &[Instance],
+    frustum: &Frustum,
+  ) -> &[Instance] {
+    if self.instances != instances {
+      self.instances = instances.to_vec();
+      self.bounds = instances
+        .iter()
+        .map(|instance| aabb.map(|aabb| aabb.transform(&instance.transform())))
+        .collect();
+      self.frustum = None;
+    }
+    if self.frustum.as_ref() != Some(frustum) {
+      self.visible = self
+        .instances
+        .iter()
+        .zip(self.bounds.iter())
+        .filter(|(_, bounds)| bounds.map_or(true, |aabb| frustum.intersects(&aabb)))
+        .map(|(instance, _)| *instance)
+        .collect();
+      self.frustum = Some(*frustum);
+    }
+    &self.visible
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::render::frustum::{Aabb, Frustum};
+  use crate::render::instanced::InstanceCulling;
+  use crate::utils::Instance;
+  use cgmath::{Matrix4, Point3, Rad, Vector3};
+
+  #[test]
+  pub fn test_instance_transform() {
+    let transform =
+      Matrix4::from_translation(Vector3::new(1.0, 2.0, 3.0)) * Matrix4::from_angle_y(Rad(0.5));
+    let instance = Instance::new(transform).with_tex_rect([0.1, 0.2, 0.3, 0.4]);
+    assert_eq!(instance.transform(), transform);
+    assert_eq!(instance.instance_w, (1.0, 2.0, 3.0, 1.0));
+    assert_eq!(instance.instance_tex_rect, (0.1, 0.2, 0.3, 0.4));
+    assert_eq!(Instance::new(transform).instance_tex_rect.0, -1.0);
+  }
+
+  #[test]
+  pub fn test_instance_culling() {
+    let aabb = Aabb {
+      min: Point3::new(-1.0, -1.0, -1.0),
+      max: Point3::new(1.0, 1.0, 1.0),
+    };
+    let at = |x: f32| Instance::new(Matrix4::from_translation(Vector3::new(x, 0.0, 0.0)));
+    let instances = vec![at(0.0), at(5.0), at(20.0)];
+    // the box from -10 to 10 on every axis
+    let frustum = Frustum::from_matrix(Matrix4::from_scale(0.1));
+    let mut culling = InstanceCulling::default();
+    assert_eq!(culling.visible(Some(aabb), &instances, &frustum), &instances[..2]);
+    // the same again, from the cache
+    assert_eq!(culling.visible(Some(aabb), &instances, &frustum), &instances[..2]);
+    let moved = Frustum::from_matrix(
+      Matrix4::from_scale(0.1) * Matrix4::from_translation(Vector3::new(-15.0, 0.0, 0.0)),
+    );
+    assert_eq!(culling.visible(Some(aabb), &instances, &moved), &instances[1..]);
+    let fewer = vec![at(0.0)];
+    assert!(culling.visible(Some(aabb), &fewer, &moved).is_empty());
+    assert_eq!(culling.visible(None, &[at(100.0)], &moved), &[at(100.0)]);
+  }
+}
@@ -0,0 +1,260 @@
+use gltf::image::Format as GltfFormat;
+use parking_lot::Mutex;
+use vulkano::descriptor::descriptor_set::{DescriptorSet, UnsafeDescriptorSetLayout};
+use vulkano::device::Queue;
+use vulkano::format::Format;
+use vulkano::image::{ImageDimensions, ImmutableImage, MipmapsCount};
+use vulkano::sync::GpuFuture;
+
+use std::sync::Arc;
+
+use crate::shaders;
+
+// rgba8 pixels of a material map
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextureData {
+  pub width: u32,
+  pub height: u32,
+  pub rgba: Vec<u8>,
+}
+
+impl TextureData {
+  fn from_gltf(image: &gltf::image::Data) -> Option<TextureData> {
+    let rgba = rgba8(image.format, &image.pixels);
+    if rgba.is_none() {
+      println!("unsupported texture format {:?}", image.format);
+    }
+    Some(TextureData {
+      width: image.width,
+      height: image.height,
+      rgba: rgba?,
+    })
+  }
+
+  // a single pixel, stands in for maps a material does not have
+  fn pixel(rgba: [u8; 4]) -> TextureData {
+    TextureData {
+      width: 1,
+      height: 1,
+      rgba: rgba.to_vec(),
+    }
+  }
+}
+
+// the 8 bit formats gltf images are decoded to, expanded to rgba
+pub fn rgba8(format: GltfFormat, pixels: &[u8]) -> Option<Vec<u8>> {
+  let expand: fn(&[u8]) -> [u8; 4] = match format {
+    GltfFormat::R8 => |p| [p[0], p[0], p[0], 255],
+    GltfFormat::R8G8 => |p| [p[0], p[1], 0, 255],
+    GltfFormat::R8G8B8 => |p| [p[0], p[1], p[2], 255],
+    GltfFormat::R8G8B8A8 => |p| [p[0], p[1], p[2], p[3]],
+    _ => return None,
+  };
+  let channels = match format {
+    GltfFormat::R8 => 1,
+    GltfFormat::R8G8 => 2,
+    GltfFormat::R8G8B8 => 3,
+    _ => 4,
+  };
+  Some(pixels.chunks_exact(channels).flat_map(|p| expand(p).to_vec()).collect())
+}
+
+// metallic roughness material as in gltf, the factors multiply the maps
+#[derive(Debug, Clone)]
+pub struct Material {
+  pub base_color: [f32; 4],
+  pub metallic: f32,
+  pub roughness: f32,
+  pub emissive: [f32; 3],
+  // non negative tex coordinates address the text texture instead of the base color map,
+  // the sign posts rely on it
+  pub text_atlas: bool,
+  pub base_color_texture: Option<TextureData>,
+  // roughness in green, metallic in blue
+  pub metallic_roughness_texture: Option<TextureData>,
+  pub normal_texture: Option<TextureData>,
+  pub emissive_texture: Option<TextureData>,
+}
+
+impl Default for Material {
+  fn default() -> Self {
+    Material {
+      base_color: [1.0, 1.0, 1.0, 1.0],
+      metallic: 0.0,
+      roughness: 0.8,
+      emissive: [0.0, 0.0, 0.0],
+      text_atlas: true,
+      base_color_texture: None,
+      metallic_roughness_texture: None,
+      normal_texture: None,
+      emissive_texture: None,
+    }
+  }
+}
+
+impl Material {
+  pub fn from_gltf(material: &gltf::Material, images: &[gltf::image::Data]) -> Material {
+    let texture =
+      |texture: gltf::Texture| TextureData::from_gltf(&images[texture.source().index()]);
+    let pbr = material.pbr_metallic_roughness();
+    Material {
+      base_color: pbr.base_color_factor(),
+      metallic: pbr.metallic_factor(),
+      roughness: pbr.roughness_factor(),
+      emissive: material.emissive_factor(),
+      text_atlas: false,
+      base_color_texture: pbr.base_color_texture().and_then(|i| texture(i.texture())),
+      metallic_roughness_texture: pbr
+        .metallic_roughness_texture()
+        .and_then(|i| texture(i.texture())),
+      normal_texture: material.normal_texture().and_then(|n| texture(n.texture())),
+      emissive_texture: material.emissive_texture().and_then(|i| texture(i.texture())),
+    }
+  }
+
+  pub fn has_maps(&self) -> bool {
+    self.base_color_texture.is_some()
+      || self.metallic_roughness_texture.is_some()
+      || self.normal_texture.is_some()
+      || self.emissive_texture.is_some()
+  }
+
+  // the factors as the main fragment shader reads them
+  pub fn uniform(&self) -> shaders::main::fs::ty::Material {
+    shaders::main::fs::ty::Material {
+      base_color: self.base_color,
+      emissive: self.emissive,
+      metallic: self.metallic,
+      roughness: self.roughness,
+      text_atlas: self.text_atlas as i32,
+      has_normal_map: self.normal_texture.is_some() as i32,
+      ..Default::default()
+    }
+  }
+}
+
+// the maps of a material in device local images, missing maps are single neutral pixels
+pub struct GpuMaterial {
+  pub uniform: shaders::main::fs::ty::Material,
+  pub base_color: Arc<ImmutableImage>,
+  pub metallic_roughness: Arc<ImmutableImage>,
+  pub normal: Arc<ImmutableImage>,
+  pub emissive: Arc<ImmutableImage>,
+  // the descriptor set built on first use, with the layout it was built for
+  set: Mutex<Option<MaterialSet>>,
+}
+
+type MaterialSet = (Arc<UnsafeDescriptorSetLayout>, Arc<dyn DescriptorSet + Sync + Send>);
+
+impl GpuMaterial {
+  pub fn upload(
+    material: &Material,
+    queue: &Arc<Queue>,
+  ) -> (Arc<GpuMaterial>, Box<dyn GpuFuture>) {
+    let white = TextureData::pixel([255, 255, 255, 255]);
+    // the factors are used as they are
+    let (base_color, base_color_future) = upload_texture(
+      material.base_color_texture.as_ref().unwrap_or(&white),
+      Format::R8G8B8A8Srgb,
+      queue,
+    );
+    let (metallic_roughness, metallic_roughness_future) = upload_texture(
+      material.metallic_roughness_texture.as_ref().unwrap_or(&white),
+      Format::R8G8B8A8Unorm,
+      queue,
+    );
+    // pointing straight out of the surface
+    let (normal, normal_future) = upload_texture(
+      material
+        .normal_texture
+        .as_ref()
+        .unwrap_or(&TextureData::pixel([128, 128, 255, 255])),
+      Format::R8G8B8A8Unorm,
+      queue,
+    );
+    let (emissive, emissive_future) = upload_texture(
+      material.emissive_texture.as_ref().unwrap_or(&white),
+      Format::R8G8B8A8Srgb,
+      queue,
+    );
+    let gpu_material = GpuMaterial {
+      uniform: material.uniform(),
+      base_color,
+      metallic_roughness,
+      normal,
+      emissive,
+      set: Mutex::new(None),
+    };
+    let future = base_color_future
+      .join(metallic_roughness_future)
+      .join(normal_future)
+      .join(emissive_future);
+    (Arc::new(gpu_material), future.boxed())
+  }
+
+  // the cached set, built again only when a pipeline rebuild changed the layout
+  pub fn set(
+    &self,
+    layout: &Arc<UnsafeDescriptorSetLayout>,
+    build: impl FnOnce() -> Arc<dyn DescriptorSet + Sync + Send>,
+  ) -> Arc<dyn DescriptorSet + Sync + Send> {
+    let mut cached = self.set.lock();
+    match &*cached {
+      Some((built_for, set)) if Arc::ptr_eq(built_for, layout) => set.clone(),
+      _ => {
+        let set = build();
+        *cached = Some((layout.clone(), set.clone()));
+        set
+      }
+    }
+  }
+}
+
+fn upload_texture(
+  texture: &TextureData,
+  format: Format,
+  queue: &Arc<Queue>,
+) -> (Arc<ImmutableImage>, impl GpuFuture) {
+  let dimensions = ImageDimensions::Dim2d {
+    width: texture.width,
+    height: texture.height,
+    array_layers: 1,
+  };
+  ImmutableImage::from_iter(
+    texture.rgba.iter().cloned(),
+    dimensions,
+    MipmapsCount::One,
+    format,
+    queue.clone(),
+  )
+  .unwrap()
+}
+
+#[cfg(test)]
+mod test {
+  use crate::render::material::{rgba8, Material};
+  use gltf::image::Format;
+
+  #[test]
+  pub fn test_rgba8() {
+    assert_eq!(rgba8(Format::R8, &[7, 9]), Some(vec![7, 7, 7, 255, 9, 9, 9, 255]));
+    assert_eq!(rgba8(Format::R8G8, &[1, 2]), Some(vec![1, 2, 0, 255]));
+    assert_eq!(
+      rgba8(Format::R8G8B8, &[1, 2, 3, 4, 5, 6]),
+      Some(vec![1, 2, 3, 255, 4, 5, 6, 255])
+    );
+    assert_eq!(rgba8(Format::R8G8B8A8, &[1, 2, 3, 4]), Some(vec![1, 2, 3, 4]));
+    assert_eq!(rgba8(Format::R16, &[1, 2]), None);
+  }
+
+  #[test]
+  pub fn test_default_material() {
+    let material = Material::default();
+    assert_eq!(material.base_color, [1.0, 1.0, 1.0, 1.0]);
+    assert!(material.text_atlas);
+    let uniform = material.uniform();
+    assert_eq!(uniform.text_atlas, 1);
+    assert_eq!(uniform.has_normal_map, 0);
+    assert_eq!(uniform.roughness, material.roughness);
+  }
+}
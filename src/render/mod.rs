@@ -1,25 +1,54 @@
+mod capture;
+mod daylight;
+mod debug_lines;
+mod device;
+mod fog;
+mod frustum;
 mod gltfimporter;
+mod instanced;
+mod material;
 mod model;
 mod mymesh;
+mod pipeline_cache;
+mod post;
+mod render_mode;
 mod scene;
+mod shader_reload;
+mod shadow;
 mod skybox;
+mod spirv_interface;
 mod system;
 mod textures;
 
-use cgmath::{Point2, Matrix4, Point3};
+use cgmath::{Point2, Matrix4, Point3, Vector4};
 
 pub type Vertex = Vec<Point3<f32>>;
 pub type Normals = Vec<Point3<f32>>;
 pub type Tex = Vec<Point2<f32>>;
 pub type TexOffset = Vec<Point2<i32>>;
+pub type Color = Vec<Vector4<f32>>;
 pub type Index = Vec<u32>;
 pub type Trans = Matrix4<f32>;
 pub type InvTrans = Matrix4<f32>;
 
+pub use self::capture::*;
+pub use self::daylight::*;
+pub use self::debug_lines::*;
+pub use self::device::*;
+pub use self::fog::*;
+pub use self::frustum::*;
 pub use self::gltfimporter::*;
+pub use self::instanced::*;
+pub use self::material::*;
 pub use self::model::*;
 pub use self::mymesh::*;
+pub use self::pipeline_cache::*;
+pub use self::post::*;
+pub use self::render_mode::*;
 pub use self::scene::*;
+pub use self::shader_reload::*;
+pub use self::shadow::*;
 pub use self::skybox::*;
+pub use self::spirv_interface::*;
 pub use self::system::*;
 pub use self::textures::*;
\ No newline at end of file
@@ -1,59 +1,143 @@
-use vulkano::buffer::CpuAccessibleBuffer;
+use vulkano::buffer::{BufferAccess, TypedBufferAccess};
 use vulkano::command_buffer::{AutoCommandBufferBuilder, DynamicState, PrimaryAutoCommandBuffer};
 use vulkano::descriptor::descriptor_set::DescriptorSetsCollection;
-use vulkano::device::Device;
+use vulkano::device::Queue;
 use vulkano::pipeline::GraphicsPipelineAbstract;
+use vulkano::sync::GpuFuture;
+use cgmath::{Vector3, Zero};
 use profiling;
 
 use std::path::Path;
 use std::sync::Arc;
 
+use crate::render::frustum::{Aabb, Frustum};
 use crate::render::gltfimporter::from_gltf;
-use crate::utils::{Normal, Vertex};
+use crate::render::material::GpuMaterial;
+use crate::shaders::main::vs::ty::Offset;
+use crate::utils::MeshVertex;
 
-#[derive(Clone, Debug)]
+// u16 when the mesh has few enough vertices, see IndexData::pack
+#[derive(Clone)]
+pub enum IndexBuffer {
+  U16(Arc<dyn TypedBufferAccess<Content = [u16]> + Send + Sync>),
+  U32(Arc<dyn TypedBufferAccess<Content = [u32]> + Send + Sync>),
+}
+
+impl IndexBuffer {
+  pub fn draw<S, Pc>(
+    &self,
+    builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+    dynamic: &DynamicState,
+    vertex_buffers: Vec<Arc<dyn BufferAccess + Send + Sync>>,
+    set: S,
+    constants: Pc,
+  ) where
+    S: DescriptorSetsCollection,
+  {
+    match self {
+      IndexBuffer::U16(index) => builder
+        .draw_indexed(pipeline, dynamic, vertex_buffers, index.clone(), set, constants, vec![])
+        .unwrap(),
+      IndexBuffer::U32(index) => builder
+        .draw_indexed(pipeline, dynamic, vertex_buffers, index.clone(), set, constants, vec![])
+        .unwrap(),
+    };
+  }
+}
+
+// a single interleaved vertex buffer, host visible or device local, see MyMesh::upload_buffers
+#[derive(Clone)]
 pub struct Model {
-  vertex: Arc<CpuAccessibleBuffer<[Vertex]>>,
-  normals: Arc<CpuAccessibleBuffer<[Normal]>>,
-  index: Arc<CpuAccessibleBuffer<[u32]>>,
+  vertex: Arc<dyn BufferAccess + Send + Sync>,
+  index: IndexBuffer,
+  // None for empty meshes, those are never culled
+  aabb: Option<Aabb>,
+  // None draws with the default material of System
+  material: Option<Arc<GpuMaterial>>,
+  // what the vertex buffer was filled from, kept for the debug lines in debug builds
+  vertices: Option<Arc<Vec<MeshVertex>>>,
+  // added to the vertices when drawn, moves the model without filling its buffers again
+  offset: Vector3<f32>,
 }
 
 impl Model {
   pub fn new(
-    vertex: Arc<CpuAccessibleBuffer<[Vertex]>>,
-    normals: Arc<CpuAccessibleBuffer<[Normal]>>,
-    index: Arc<CpuAccessibleBuffer<[u32]>>,
+    vertex: Arc<dyn BufferAccess + Send + Sync>,
+    index: IndexBuffer,
+    aabb: Option<Aabb>,
+    vertices: Option<Arc<Vec<MeshVertex>>>,
   ) -> Model {
     Model {
       vertex,
-      normals,
       index,
+      aabb,
+      material: None,
+      vertices,
+      offset: Vector3::zero(),
+    }
+  }
+
+  pub fn with_material(self, material: Arc<GpuMaterial>) -> Model {
+    Model {
+      material: Some(material),
+      ..self
     }
   }
 
+  pub fn material(&self) -> Option<&GpuMaterial> {
+    self.material.as_deref()
+  }
+
+  // the same buffers drawn moved by `by`
+  pub fn translated(&self, by: Vector3<f32>) -> Model {
+    Model {
+      offset: self.offset + by,
+      ..self.clone()
+    }
+  }
+
+  pub fn offset(&self) -> Vector3<f32> {
+    self.offset
+  }
+
+  pub fn aabb(&self) -> Option<Aabb> {
+    self.aabb.map(|aabb| aabb.translate(self.offset))
+  }
+
+  pub fn vertices(&self) -> Option<&[MeshVertex]> {
+    self.vertices.as_deref().map(|vertices| vertices.as_slice())
+  }
+
+  pub fn is_visible(&self, frustum: &Frustum) -> bool {
+    self.aabb().map_or(true, |aabb| frustum.intersects(&aabb))
+  }
+
   #[profiling::function]
+  // dynamic is DynamicState::none() for pipelines with a fixed viewport, the offset is pushed
+  // for the pipelines of the main, water and shadow vertex shaders
   pub fn draw_indexed<S>(
     &self,
     builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
     pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+    dynamic: &DynamicState,
     set: S,
   ) where
     S: DescriptorSetsCollection,
   {
-    builder
-      .draw_indexed(
-        pipeline.clone(),
-        &DynamicState::none(),
-        vec![self.vertex.clone(), self.normals.clone()],
-        self.index.clone(),
-        set,
-        (),
-        vec![]
-      )
-      .unwrap();
-  }
-
-  pub fn from_gltf(path: &Path, device: &Arc<Device>) -> Model {
-    from_gltf(path, false).get_buffers(device)
+    let offset = Offset {
+      offset: self.offset.into(),
+    };
+    self
+      .index
+      .draw(builder, pipeline, dynamic, vec![self.vertex.clone()], set, offset);
+  }
+
+  // a model per material of the file
+  pub fn from_gltf(path: &Path, queue: &Arc<Queue>) -> Vec<(Model, impl GpuFuture)> {
+    from_gltf(path, false)
+      .iter()
+      .map(|mesh| mesh.upload_buffers(queue))
+      .collect()
   }
 }
@@ -1,14 +1,10 @@
-use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer};
-use vulkano::device::Device;
-
-use genmesh::generators::{Cube, IndexedPolygon};
-use genmesh::{MapToVertices, Neighbors, Triangle, Triangulate, Vertices};
-
-use mint::Vector3 as MintVector3;
+use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer, ImmutableBuffer};
+use vulkano::device::{Device, Queue};
+use vulkano::sync::GpuFuture;
 
 //use cgmath::prelude::*;
-use cgmath::{Transform, One};
-use cgmath::{InnerSpace, Matrix3, Matrix4, Point2, Point3, Quaternion, SquareMatrix, Vector3};
+use cgmath::{EuclideanSpace, Matrix, Transform, One};
+use cgmath::{InnerSpace, Matrix3, Matrix4, Point2, Point3, Quaternion, SquareMatrix, Vector3, Vector4};
 
 use itertools::izip;
 
@@ -17,8 +13,11 @@ use std::ops::MulAssign;
 use std::sync::Arc;
 
 use crate::render::Trans;
-use crate::render::model::Model;
-use crate::utils::{Normal, Vertex};
+use crate::render::frustum::Aabb;
+use crate::render::material::{GpuMaterial, Material};
+use crate::render::instanced::InstancedModel;
+use crate::render::model::{IndexBuffer, Model};
+use crate::utils::MeshVertex;
 
 #[derive(Default, Debug, Clone)]
 pub struct InterestingMeshData {
@@ -30,6 +29,8 @@ pub struct MyMesh {
   pub data: MyMeshData,
   print: bool,
   interesting: InterestingMeshData,
+  // None draws with the default material
+  pub material: Option<Material>,
 }
 
 #[derive(Debug, Clone)]
@@ -37,12 +38,35 @@ pub struct MyMeshData {
   pub vertex: Vec<Point3<f32>>,
   pub tex: Vec<Point2<f32>>,
   pub tex_offset: Vec<Point2<i32>>,
+  pub color: Vec<Vector4<f32>>,
   pub normals: Vec<Point3<f32>>,
   pub index: Vec<u32>,
   pub transform: Matrix4<f32>,
   pub inverse_transform: Matrix4<f32>,
 }
 
+pub const WHITE: Vector4<f32> = Vector4::new(1.0, 1.0, 1.0, 1.0);
+
+// the inverse transpose of the upper 3x3 of transform, normals stay perpendicular to the
+// surface under non uniform scales and are not moved by the translation
+pub fn normal_matrix(transform: &Matrix4<f32>) -> Matrix3<f32> {
+  let column = |c: Vector4<f32>| c.truncate();
+  let m = Matrix3::from_cols(column(transform.x), column(transform.y), column(transform.z));
+  m.invert().unwrap_or(m).transpose()
+}
+
+// release builds drop the copy of the vertices once they are in a buffer, so they have no
+// normals to draw
+pub const KEEPS_VERTICES: bool = cfg!(debug_assertions);
+
+fn debug_vertices(vertices: Arc<Vec<MeshVertex>>) -> Option<Arc<Vec<MeshVertex>>> {
+  if KEEPS_VERTICES {
+    Some(vertices)
+  } else {
+    None
+  }
+}
+
 impl MyMesh {
   pub fn new(
     vertex: Vec<cgmath::Point3<f32>>,
@@ -53,10 +77,12 @@ impl MyMesh {
     transform: Matrix4<f32>,
     print: bool,
   ) -> MyMesh {
+    let color = vec![WHITE; vertex.len()];
     MyMesh::new_interesting(
       vertex,
       tex,
       tex_offset,
+      color,
       normals,
       index,
       transform,
@@ -69,6 +95,7 @@ impl MyMesh {
     vertex: Vec<cgmath::Point3<f32>>,
     tex: Vec<cgmath::Point2<f32>>,
     tex_offset: Vec<cgmath::Point2<i32>>,
+    color: Vec<cgmath::Vector4<f32>>,
     normals: Vec<cgmath::Point3<f32>>,
     index: Vec<u32>,
     transform: Matrix4<f32>,
@@ -80,6 +107,7 @@ impl MyMesh {
       vertex,
       tex,
       tex_offset,
+      color,
       normals,
       index,
       transform,
@@ -89,6 +117,7 @@ impl MyMesh {
       data,
       print,
       interesting: interesting,
+      material: None,
     };
     if print {
       mesh.printstats();
@@ -96,46 +125,92 @@ impl MyMesh {
     mesh
   }
 
+  pub fn with_material(self, material: Material) -> MyMesh {
+    MyMesh {
+      material: Some(material),
+      ..self
+    }
+  }
+
   pub fn reset_transform(&mut self) {
     self.data.transform = One::one();
   }
 
-  pub fn get_buffers(&self, device: &Arc<Device>) -> Model {
-    let vertices_vec: Vec<Vertex> = izip!(
+  // vertices with the mesh transform applied, normal interleaved with the rest
+  pub fn vertex_data(&self) -> Vec<MeshVertex> {
+    let normals = normal_matrix(&self.data.transform);
+    izip!(
       self.data.vertex.iter(),
       self.data.tex.iter(),
-      self.data.tex_offset.iter()
+      self.data.tex_offset.iter(),
+      self.data.normals.iter(),
+      self.data.color.iter()
     )
-    .map(|(pos, tex, tex_offset)| (self.data.transform.transform_point(*pos), tex, tex_offset))
-    .map(|(pos, tex, tex_offset)| Vertex {
-      position: (pos[0], pos[1], pos[2]),
-      tex: (tex.x, tex.y),
-      tex_offset: (tex_offset.x, tex_offset.y),
+    .map(|(pos, tex, tex_offset, normal, color)| {
+      let pos = self.data.transform.transform_point(*pos);
+      let normal = normals * normal.to_vec();
+      MeshVertex {
+        position: (pos[0], pos[1], pos[2]),
+        tex: (tex.x, tex.y),
+        tex_offset: (tex_offset.x, tex_offset.y),
+        normal: (normal[0], normal[1], normal[2]),
+        color: (color.x, color.y, color.z, color.w),
+      }
     })
-    .collect();
-    let vertices = vertices_vec.iter().cloned();
-    //println!("xxxxxxxxxxxxxxx vertices {:?}", vertices_vec);
-    let normals_vec: Vec<Normal> = self
-      .data
-      .normals
-      .iter()
-      .map(|pos| self.data.transform.transform_point(*pos))
-      .map(|pos| Normal {
-        normal: (pos[0], pos[1], pos[2]),
-      })
-      .collect();
-    let normals = normals_vec.iter().cloned();
+    .collect()
+  }
 
-    let indices = self.data.index.iter().cloned();
+  pub fn index_data(&self) -> IndexData {
+    IndexData::pack(&self.data.index, self.data.vertex.len())
+  }
 
-    let vertex_buffer =
-      CpuAccessibleBuffer::from_iter(device.clone(), BufferUsage::all(), false, vertices).unwrap();
-    let index_buffer =
-      CpuAccessibleBuffer::from_iter(device.clone(), BufferUsage::all(), false, indices).unwrap();
+  // host visible buffers, usable right away
+  pub fn get_buffers(&self, device: &Arc<Device>) -> Model {
+    let vertices = Arc::new(self.vertex_data());
+    let aabb = Aabb::from_points(vertices.iter().map(|v| v.position.into()));
+    let vertex_buffer = CpuAccessibleBuffer::from_iter(
+      device.clone(),
+      BufferUsage::all(),
+      false,
+      vertices.iter().copied(),
+    )
+    .unwrap();
+    let index_buffer = self.index_data().to_buffer(device);
+    Model::new(vertex_buffer, index_buffer, aabb, debug_vertices(vertices))
+  }
+
+  // device local buffers filled from staging buffers, the copies are submitted to the
+  // queue and the model must not be drawn before the returned future is joined
+  pub fn upload_buffers(&self, queue: &Arc<Queue>) -> (Model, impl GpuFuture) {
+    let vertices = Arc::new(self.vertex_data());
+    let aabb = Aabb::from_points(vertices.iter().map(|v| v.position.into()));
+    let (vertex_buffer, vertex_future) = ImmutableBuffer::from_iter(
+      vertices.iter().copied(),
+      BufferUsage::vertex_buffer(),
+      queue.clone(),
+    )
+    .unwrap();
+    let (index_buffer, index_future) = self.index_data().upload(queue);
+
+    let model = Model::new(vertex_buffer, index_buffer, aabb, debug_vertices(vertices));
+    let future = vertex_future.join(index_future).boxed();
+    match &self.material {
+      Some(material) => {
+        let (material, material_future) = GpuMaterial::upload(material, queue);
+        (model.with_material(material), future.join(material_future).boxed())
+      }
+      None => (model, future),
+    }
+  }
 
-    let normals_buffer =
-      CpuAccessibleBuffer::from_iter(device.clone(), BufferUsage::all(), false, normals).unwrap();
-    Model::new(vertex_buffer, normals_buffer, index_buffer)
+  // the mesh shared by instances
+  pub fn get_instanced_buffers(&self, device: &Arc<Device>) -> InstancedModel {
+    let vertices = self.vertex_data();
+    let aabb = Aabb::from_points(vertices.iter().map(|v| v.position.into()));
+    let vertex_buffer =
+      CpuAccessibleBuffer::from_iter(device.clone(), BufferUsage::all(), false, vertices.into_iter())
+        .unwrap();
+    InstancedModel::new(vertex_buffer, self.index_data().to_buffer(device), aabb)
   }
 
   pub fn translation_decomposed(&self) -> (Vector3<f32>, Quaternion<f32>, [f32; 3]) {
@@ -171,6 +246,12 @@ impl MyMesh {
     self.data.inverse_transform.concat_self(&inverse_transform);
   }
 
+  // moves the mesh in world space, unlike update_transform_2 which composes in model space
+  pub fn translate(&mut self, by: Vector3<f32>) {
+    self.data.transform = Matrix4::from_translation(by) * self.data.transform;
+    self.data.inverse_transform = self.data.inverse_transform * Matrix4::from_translation(-by);
+  }
+
   pub fn printstats(&self) {
     let max_x = self
       .data
@@ -228,51 +309,6 @@ impl MyMesh {
     //println!("normal {:?}", self.normals);
   }
 
-  pub fn add_bounding_box(&mut self, min: [f32; 3], max: [f32; 3]) {
-    //println!("adding bounding box {:?} {:?}", min, max);
-    let cube = Cube::new();
-    let mut vertex: Vec<Point3<f32>> = cube
-      .clone()
-      .vertex(|v| Point3::<f32>::new(v.pos.x, v.pos.y, v.pos.z))
-      .vertex(|v| {
-        Point3::<f32>::new(
-          if v.x < 0.0 { min[0] } else { max[0] },
-          if v.y < 0.0 { min[1] } else { max[1] },
-          if v.z < 0.0 { min[2] } else { max[2] },
-        )
-      })
-      .vertices()
-      .collect();
-
-    let triangles: Vec<Triangle<usize>> = cube.indexed_polygon_iter().triangulate().collect();
-
-    let neighbours = Neighbors::new(vertex.clone(), triangles.clone());
-
-    let mut index: Vec<u32> = triangles
-      .iter()
-      .cloned()
-      .vertices()
-      .map(|v| v as u32)
-      .collect();
-
-    let mut normals: Vec<Point3<f32>> = (0..vertex.len())
-      .map(|i| neighbours.normal_for_vertex(i, |v| MintVector3::<f32>::from([v.x, v.y, v.z])))
-      .map(|v| Point3::from((v.x, v.y, v.z)))
-      .collect();
-
-    let mut tex = (0..vertex.len())
-      .map(|_i| Point2::new(-1.0, -1.0))
-      .collect();
-
-    let mut tex_offset = (0..vertex.len()).map(|_i| Point2::new(0, 0)).collect();
-
-    self.data.vertex.append(&mut vertex);
-    self.data.normals.append(&mut normals);
-    self.data.tex.append(&mut tex);
-    self.data.tex_offset.append(&mut tex_offset);
-    self.data.index.append(&mut index);
-  }
-
   pub fn map_vertex<F>(&mut self, f: F)
   where
     F: Fn(&mut Point3<f32>),
@@ -288,6 +324,9 @@ impl MyMesh {
   pub fn add_consume(&mut self, other: &mut MyMesh) {
     self.data.add_consume(&mut other.data);
     self.interesting.add_consume(&mut other.interesting);
+    if self.material.is_none() {
+      self.material = other.material.take();
+    }
   }
 }
 
@@ -297,8 +336,9 @@ impl MyMeshData {
     for vert in other.vertex.iter_mut() {
       *vert = mult_mat.transform_point(*vert);
     }
+    let normals = normal_matrix(mult_mat);
     for norm in other.normals.iter_mut() {
-      *norm = mult_mat.transform_point(*norm);
+      *norm = Point3::from_vec(normals * norm.to_vec());
     }
 
     let index_add: u32 = self.vertex.len() as u32;
@@ -306,6 +346,7 @@ impl MyMeshData {
     self.normals.append(&mut other.normals);
     self.tex.append(&mut other.tex);
     self.tex_offset.append(&mut other.tex_offset);
+    self.color.append(&mut other.color);
     for ind in other.index.iter_mut() {
       *ind = index_add + *ind;
     }
@@ -313,6 +354,60 @@ impl MyMeshData {
   }
 }
 
+// indices packed as u16 whenever all vertices can be addressed with them
+#[derive(Debug, Clone, PartialEq)]
+pub enum IndexData {
+  U16(Vec<u16>),
+  U32(Vec<u32>),
+}
+
+impl IndexData {
+  pub fn pack(index: &[u32], vertex_count: usize) -> IndexData {
+    if vertex_count <= u16::MAX as usize + 1 {
+      IndexData::U16(index.iter().map(|i| *i as u16).collect())
+    } else {
+      IndexData::U32(index.to_vec())
+    }
+  }
+
+  // size of the index buffer in bytes
+  pub fn size(&self) -> usize {
+    match self {
+      IndexData::U16(index) => index.len() * 2,
+      IndexData::U32(index) => index.len() * 4,
+    }
+  }
+
+  fn to_buffer(self, device: &Arc<Device>) -> IndexBuffer {
+    match self {
+      IndexData::U16(index) => IndexBuffer::U16(
+        CpuAccessibleBuffer::from_iter(device.clone(), BufferUsage::all(), false, index.into_iter())
+          .unwrap(),
+      ),
+      IndexData::U32(index) => IndexBuffer::U32(
+        CpuAccessibleBuffer::from_iter(device.clone(), BufferUsage::all(), false, index.into_iter())
+          .unwrap(),
+      ),
+    }
+  }
+
+  fn upload(self, queue: &Arc<Queue>) -> (IndexBuffer, Box<dyn GpuFuture>) {
+    let usage = BufferUsage::index_buffer();
+    match self {
+      IndexData::U16(index) => {
+        let (buffer, future) =
+          ImmutableBuffer::from_iter(index.into_iter(), usage, queue.clone()).unwrap();
+        (IndexBuffer::U16(buffer), future.boxed())
+      }
+      IndexData::U32(index) => {
+        let (buffer, future) =
+          ImmutableBuffer::from_iter(index.into_iter(), usage, queue.clone()).unwrap();
+        (IndexBuffer::U32(buffer), future.boxed())
+      }
+    }
+  }
+}
+
 impl InterestingMeshData {
   pub fn add_consume(&mut self, other: &mut InterestingMeshData) {
     for (key, val) in other.map.drain() {
@@ -378,9 +473,9 @@ pub fn transform_decomposed(transform: &Trans) -> (Vector3<f32>, Quaternion<f32>
 
 #[cfg(test)]
 mod test {
-  use crate::render::mymesh::MyMesh;
+  use crate::render::mymesh::{normal_matrix, IndexData, MyMesh};
   use crate::things::PrimitiveCube;
-  use cgmath::{Matrix4, One, Vector3};
+  use cgmath::{InnerSpace, Matrix4, One, Transform, Vector3, Vector4};
 
   fn test_mesh() -> MyMesh {
     let mesh = PrimitiveCube::new(1.0, 1.0, 1.0, (1.0, 4.0, 9.0));
@@ -410,4 +505,67 @@ mod test {
     assert_eq!(t, Vector3::new(1.0 + 5.0, 4.0 + 6.0, 9.0 + 7.0));
     assert_eq!(s, [1.0, 1.0, 1.0]);
   }
+
+  #[test]
+  pub fn test_vertex_data_interleaved() {
+    let cube = test_mesh();
+    let vertices = cube.vertex_data();
+    assert_eq!(vertices.len(), cube.data.vertex.len());
+    for (i, v) in vertices.iter().enumerate() {
+      let pos = cube.data.transform.transform_point(cube.data.vertex[i]);
+      // the cube is only translated, its normals stay as they are
+      let normal = cube.data.normals[i];
+      assert_eq!(v.position, (pos.x, pos.y, pos.z));
+      assert_eq!(v.normal, (normal.x, normal.y, normal.z));
+      assert_eq!(v.tex, (cube.data.tex[i].x, cube.data.tex[i].y));
+      assert_eq!(v.color.3, cube.data.color[i].w);
+    }
+  }
+
+  #[test]
+  pub fn test_normal_matrix() {
+    let transform = Matrix4::from_translation(Vector3::new(5.0, 6.0, 7.0))
+      * Matrix4::from_nonuniform_scale(2.0, 1.0, 1.0);
+    let normals = normal_matrix(&transform);
+    assert_eq!(normals * Vector3::new(0.0, 1.0, 0.0), Vector3::new(0.0, 1.0, 0.0));
+    // the surface x = y is stretched along x, its normal tilts towards y
+    let normal = normals * Vector3::new(1.0, -1.0, 0.0);
+    let tangent = (transform * Vector4::new(1.0, 1.0, 0.0, 0.0)).truncate();
+    assert_eq!(normal.dot(tangent), 0.0);
+    assert_eq!(normal, Vector3::new(0.5, -1.0, 0.0));
+  }
+
+  #[test]
+  pub fn test_add_consume_normals() {
+    let mut mesh = test_mesh();
+    let mut other = test_mesh();
+    other.data.transform = Matrix4::from_translation(Vector3::new(5.0, 6.0, 7.0))
+      * Matrix4::from_nonuniform_scale(2.0, 1.0, 1.0);
+    let normals = other.data.normals.clone();
+    let count = mesh.data.normals.len();
+    mesh.add_consume(&mut other);
+    // moved vertices keep their normals, stretched ones tilt away from the stretch
+    for (normal, consumed) in normals.iter().zip(&mesh.data.normals[count..]) {
+      assert_eq!(consumed.x, normal.x * 0.5);
+      assert_eq!((consumed.y, consumed.z), (normal.y, normal.z));
+    }
+  }
+
+  #[test]
+  pub fn test_index_packing() {
+    assert_eq!(IndexData::pack(&[0, 1, 2], 3), IndexData::U16(vec![0, 1, 2]));
+    assert_eq!(IndexData::pack(&[0, 1, 2], 3).size(), 6);
+    // the largest mesh u16 indices can address
+    assert_eq!(
+      IndexData::pack(&[0, 65535], 65536),
+      IndexData::U16(vec![0, 65535])
+    );
+    let large = IndexData::pack(&[0, 65536], 65537);
+    assert_eq!(large, IndexData::U32(vec![0, 65536]));
+    assert_eq!(large.size(), 8);
+
+    let cube = test_mesh();
+    let expected = cube.data.index.iter().map(|i| *i as u16).collect();
+    assert_eq!(cube.index_data(), IndexData::U16(expected));
+  }
 }
@@ -0,0 +1,83 @@
+use vulkano::device::Device;
+use vulkano::pipeline::cache::PipelineCache;
+
+use std::convert::TryInto;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+// VkPipelineCacheHeaderVersionOne, the length of the header, its version, the vendor and device
+// ids and the pipeline cache uuid of the device
+const HEADER_SIZE: usize = 32;
+const HEADER_VERSION_ONE: u32 = 1;
+
+// what the pipelines of the last run were built with, empty without a file written by the same
+// driver on the same device
+pub fn load_pipeline_cache(device: &Arc<Device>, path: Option<&Path>) -> Arc<PipelineCache> {
+  let physical = device.physical_device();
+  let data = path.and_then(|path| fs::read(path).ok()).filter(|data| {
+    header_matches(
+      data,
+      physical.pci_vendor_id(),
+      physical.pci_device_id(),
+      physical.uuid(),
+    )
+  });
+  let cache = data.and_then(|data| unsafe { PipelineCache::with_data(device.clone(), &data) }.ok());
+  match cache {
+    Some(cache) => cache,
+    None => PipelineCache::empty(device.clone()).unwrap(),
+  }
+}
+
+// everything built with the cache so far, the next run starts from it
+pub fn save_pipeline_cache(cache: &PipelineCache, path: &Path) -> io::Result<()> {
+  let data = cache
+    .get_data()
+    .map_err(|e| io::Error::new(io::ErrorKind::Other, format!("{:?}", e)))?;
+  if let Some(dir) = path.parent() {
+    fs::create_dir_all(dir)?;
+  }
+  fs::write(path, data)
+}
+
+pub fn header_matches(data: &[u8], vendor_id: u32, device_id: u32, uuid: &[u8; 16]) -> bool {
+  if data.len() < HEADER_SIZE {
+    return false;
+  }
+  let word = |i: usize| u32::from_ne_bytes(data[i * 4..i * 4 + 4].try_into().unwrap());
+  word(0) as usize >= HEADER_SIZE
+    && word(1) == HEADER_VERSION_ONE
+    && word(2) == vendor_id
+    && word(3) == device_id
+    && &data[16..HEADER_SIZE] == uuid
+}
+
+#[cfg(test)]
+mod test {
+  use crate::render::pipeline_cache::header_matches;
+
+  fn header(vendor_id: u32, device_id: u32, uuid: &[u8; 16]) -> Vec<u8> {
+    let mut data = vec![];
+    for word in [32, 1, vendor_id, device_id].iter() {
+      data.extend_from_slice(&u32::to_ne_bytes(*word));
+    }
+    data.extend_from_slice(uuid);
+    // the pipelines follow the header
+    data.extend_from_slice(&[7; 8]);
+    data
+  }
+
+  #[test]
+  pub fn test_header_matches() {
+    let uuid = [3; 16];
+    let data = header(0x10de, 0x2204, &uuid);
+    assert!(header_matches(&data, 0x10de, 0x2204, &uuid));
+    assert!(!header_matches(&data, 0x1002, 0x2204, &uuid));
+    assert!(!header_matches(&data, 0x10de, 0x2205, &uuid));
+    assert!(!header_matches(&data, 0x10de, 0x2204, &[4; 16]));
+    assert!(!header_matches(&data[..20], 0x10de, 0x2204, &uuid));
+    assert!(!header_matches(&[], 0x10de, 0x2204, &uuid));
+  }
+}
@@ -0,0 +1,458 @@
+use cgmath::{Matrix4, One, Point2, Point3};
+use vulkano::buffer::cpu_pool::CpuBufferPool;
+use vulkano::buffer::BufferUsage;
+use vulkano::command_buffer::{
+  AutoCommandBufferBuilder, DynamicState, PrimaryAutoCommandBuffer, SubpassContents,
+};
+use vulkano::descriptor::descriptor_set::{DescriptorSet, PersistentDescriptorSet};
+use vulkano::device::Device;
+use vulkano::format::{ClearValue, Format};
+use vulkano::image::view::ImageView;
+use vulkano::image::{AttachmentImage, ImageAccess, ImageUsage};
+use vulkano::pipeline::vertex::SingleBufferDefinition;
+use vulkano::pipeline::viewport::Viewport;
+use vulkano::pipeline::{GraphicsPipeline, GraphicsPipelineAbstract};
+use vulkano::render_pass::{Framebuffer, FramebufferAbstract, RenderPass, Subpass};
+use vulkano::sampler::{Filter, MipmapMode, Sampler, SamplerAddressMode};
+use profiling;
+
+use std::sync::Arc;
+
+use crate::render::model::Model;
+use crate::render::mymesh::MyMesh;
+use crate::shaders::post::{blur_fs, bright_fs, tonemap_fs};
+use crate::utils::MeshVertex;
+use crate::Graph;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Tonemap {
+  // clamps, bright light turns into flat white
+  Off,
+  Reinhard,
+  Aces,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PostSettings {
+  pub bloom: bool,
+  // scene brightness above which light bleeds into its surroundings
+  pub bloom_threshold: f32,
+  pub bloom_intensity: f32,
+  pub tonemap: Tonemap,
+  pub exposure: f32,
+  // gamma encodes the final image, unless its format does that by itself
+  pub gamma: bool,
+}
+
+impl Default for PostSettings {
+  fn default() -> Self {
+    PostSettings {
+      bloom: true,
+      bloom_threshold: 1.0,
+      bloom_intensity: 0.6,
+      tonemap: Tonemap::Aces,
+      exposure: 1.0,
+      gamma: true,
+    }
+  }
+}
+
+impl PostSettings {
+  pub fn uniform(&self, srgb_target: bool) -> tonemap_fs::ty::Post {
+    tonemap_fs::ty::Post {
+      exposure: self.exposure,
+      bloom_intensity: if self.bloom { self.bloom_intensity } else { 0.0 },
+      tonemap: match self.tonemap {
+        Tonemap::Off => 0,
+        Tonemap::Reinhard => 1,
+        Tonemap::Aces => 2,
+      },
+      gamma: if self.gamma && !srgb_target { 2.2 } else { 1.0 },
+    }
+  }
+}
+
+// the scene is rendered in this format, so lights can be brighter than white
+pub const HDR_FORMAT: Format = Format::R16G16B16A16Sfloat;
+
+fn is_srgb(format: Format) -> bool {
+  matches!(
+    format,
+    Format::B8G8R8A8Srgb | Format::R8G8B8A8Srgb | Format::A8B8G8R8SrgbPack32
+  )
+}
+
+// bloom is blurred at half the resolution of the scene
+pub fn bloom_dimensions(dimensions: [u32; 2]) -> [u32; 2] {
+  [(dimensions[0] / 2).max(1), (dimensions[1] / 2).max(1)]
+}
+
+// a triangle covering the whole screen, in clip space
+fn fullscreen_triangle(device: &Arc<Device>) -> Model {
+  let vertex = vec![
+    Point3::new(-1.0, -1.0, 0.0),
+    Point3::new(3.0, -1.0, 0.0),
+    Point3::new(-1.0, 3.0, 0.0),
+  ];
+  MyMesh::new(
+    vertex,
+    vec![Point2::new(0.0, 0.0); 3],
+    vec![Point2::new(0, 0); 3],
+    vec![Point3::new(0.0, 0.0, 1.0); 3],
+    vec![0, 1, 2],
+    Matrix4::one(),
+    false,
+  )
+  .get_buffers(device)
+}
+
+// the viewport of a pass over the whole of an image, every pipeline takes its viewport at draw
+pub fn dynamic_viewport(dimensions: [u32; 2]) -> DynamicState {
+  DynamicState {
+    viewports: Some(vec![Viewport {
+      origin: [0.0, 0.0],
+      dimensions: [dimensions[0] as f32, dimensions[1] as f32],
+      depth_range: 0.0..1.0,
+    }]),
+    ..DynamicState::none()
+  }
+}
+
+fn framebuffer<I>(
+  render_pass: Arc<RenderPass>,
+  image: Arc<I>,
+) -> Arc<dyn FramebufferAbstract + Send + Sync>
+where
+  I: ImageAccess + Send + Sync + 'static,
+{
+  Arc::new(
+    Framebuffer::start(render_pass)
+      .add(ImageView::new(image).unwrap())
+      .unwrap()
+      .build()
+      .unwrap(),
+  )
+}
+
+// bloom and tonemapping, from the hdr scene into the final images
+pub struct PostProcess {
+  // the scene with the sky composited, what the main render pass renders into
+  pub hdr_buffer: Arc<AttachmentImage>,
+  // the bright pass and the vertical blur end up in the first, the horizontal blur in the second
+  bloom_buffers: [Arc<AttachmentImage>; 2],
+  bloom_framebuffers: [Arc<dyn FramebufferAbstract + Send + Sync>; 2],
+  // one per final image
+  framebuffers: Vec<Arc<dyn FramebufferAbstract + Send + Sync>>,
+  pipeline_bright: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+  pipeline_blur: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+  pipeline_tonemap: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+  triangle: Model,
+  sampler: Arc<Sampler>,
+  bright_buffer: CpuBufferPool<bright_fs::ty::Bright>,
+  blur_buffer: CpuBufferPool<blur_fs::ty::Blur>,
+  post_buffer: CpuBufferPool<tonemap_fs::ty::Post>,
+  // of the hdr buffer and the final images, the bloom buffers are half of it
+  dimensions: [u32; 2],
+  srgb_target: bool,
+}
+
+type Targets = (
+  Arc<AttachmentImage>,
+  [Arc<AttachmentImage>; 2],
+  [Arc<dyn FramebufferAbstract + Send + Sync>; 2],
+  Vec<Arc<dyn FramebufferAbstract + Send + Sync>>,
+  [u32; 2],
+);
+
+// the buffers and framebuffers for the size of the final images
+fn targets<I>(graph: &Graph, images: &[Arc<I>]) -> Targets
+where
+  I: ImageAccess + Send + Sync + 'static,
+{
+  let device = graph.device.clone();
+  let dimensions = ImageAccess::dimensions(&*images[0]).width_height();
+  let bloom_dimensions = bloom_dimensions(dimensions);
+
+  let hdr_buffer = AttachmentImage::sampled(device.clone(), dimensions, HDR_FORMAT).unwrap();
+  // cleared instead of drawn into while bloom is off
+  let bloom_usage = ImageUsage {
+    sampled: true,
+    color_attachment: true,
+    transfer_destination: true,
+    ..ImageUsage::none()
+  };
+  let bloom_buffer = || {
+    AttachmentImage::with_usage(device.clone(), bloom_dimensions, HDR_FORMAT, bloom_usage).unwrap()
+  };
+  let bloom_buffers = [bloom_buffer(), bloom_buffer()];
+  let bloom_framebuffers = [
+    framebuffer(graph.bloom_render_pass.clone(), bloom_buffers[0].clone()),
+    framebuffer(graph.bloom_render_pass.clone(), bloom_buffers[1].clone()),
+  ];
+  let framebuffers = images
+    .iter()
+    .map(|image| framebuffer(graph.post_render_pass.clone(), image.clone()))
+    .collect();
+  (
+    hdr_buffer,
+    bloom_buffers,
+    bloom_framebuffers,
+    framebuffers,
+    dimensions,
+  )
+}
+
+impl PostProcess {
+  #[profiling::function]
+  pub fn new<I>(graph: &Graph, images: &[Arc<I>]) -> Self
+  where
+    I: ImageAccess + Send + Sync + 'static,
+  {
+    let device = graph.device.clone();
+    let (hdr_buffer, bloom_buffers, bloom_framebuffers, framebuffers, dimensions) =
+      targets(graph, images);
+
+    let pipeline_bright = Arc::new(
+      GraphicsPipeline::start()
+        .vertex_input(SingleBufferDefinition::<MeshVertex>::new())
+        .vertex_shader(graph.post_vs.main_entry_point(), ())
+        .triangle_list()
+        .viewports_dynamic_scissors_irrelevant(1)
+        .fragment_shader(graph.bright_fs.main_entry_point(), ())
+        .render_pass(Subpass::from(graph.bloom_render_pass.clone(), 0).unwrap())
+        .build_with_cache(graph.pipeline_cache.clone())
+        .build(device.clone())
+        .unwrap(),
+    );
+    let pipeline_blur = Arc::new(
+      GraphicsPipeline::start()
+        .vertex_input(SingleBufferDefinition::<MeshVertex>::new())
+        .vertex_shader(graph.post_vs.main_entry_point(), ())
+        .triangle_list()
+        .viewports_dynamic_scissors_irrelevant(1)
+        .fragment_shader(graph.blur_fs.main_entry_point(), ())
+        .render_pass(Subpass::from(graph.bloom_render_pass.clone(), 0).unwrap())
+        .build_with_cache(graph.pipeline_cache.clone())
+        .build(device.clone())
+        .unwrap(),
+    );
+    let pipeline_tonemap = Arc::new(
+      GraphicsPipeline::start()
+        .vertex_input(SingleBufferDefinition::<MeshVertex>::new())
+        .vertex_shader(graph.post_vs.main_entry_point(), ())
+        .triangle_list()
+        .viewports_dynamic_scissors_irrelevant(1)
+        .fragment_shader(graph.tonemap_fs.main_entry_point(), ())
+        .render_pass(Subpass::from(graph.post_render_pass.clone(), 0).unwrap())
+        .build_with_cache(graph.pipeline_cache.clone())
+        .build(device.clone())
+        .unwrap(),
+    );
+
+    let sampler = Sampler::new(
+      device.clone(),
+      Filter::Linear,
+      Filter::Linear,
+      MipmapMode::Nearest,
+      SamplerAddressMode::ClampToEdge,
+      SamplerAddressMode::ClampToEdge,
+      SamplerAddressMode::ClampToEdge,
+      0.0,
+      1.0,
+      0.0,
+      0.0,
+    )
+    .unwrap();
+
+    PostProcess {
+      hdr_buffer,
+      bloom_buffers,
+      bloom_framebuffers,
+      framebuffers,
+      pipeline_bright,
+      pipeline_blur,
+      pipeline_tonemap,
+      triangle: fullscreen_triangle(&device),
+      sampler,
+      bright_buffer: CpuBufferPool::new(device.clone(), BufferUsage::all()),
+      blur_buffer: CpuBufferPool::new(device.clone(), BufferUsage::all()),
+      post_buffer: CpuBufferPool::new(device, BufferUsage::all()),
+      dimensions,
+      srgb_target: is_srgb(graph.format),
+    }
+  }
+
+  // new targets for the new size, the pipelines stay
+  #[profiling::function]
+  pub fn resize<I>(&mut self, graph: &Graph, images: &[Arc<I>])
+  where
+    I: ImageAccess + Send + Sync + 'static,
+  {
+    let (hdr_buffer, bloom_buffers, bloom_framebuffers, framebuffers, dimensions) =
+      targets(graph, images);
+    self.hdr_buffer = hdr_buffer;
+    self.bloom_buffers = bloom_buffers;
+    self.bloom_framebuffers = bloom_framebuffers;
+    self.framebuffers = framebuffers;
+    self.dimensions = dimensions;
+  }
+
+  // the viewport of the hdr buffer and the final images
+  pub fn dynamic_state(&self) -> DynamicState {
+    dynamic_viewport(self.dimensions)
+  }
+
+  fn sampled_set<U>(
+    &self,
+    pipeline: &Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+    images: &[&Arc<AttachmentImage>],
+    pool: &CpuBufferPool<U>,
+    uniform: U,
+  ) -> Arc<dyn DescriptorSet + Sync + Send>
+  where
+    U: Send + Sync + 'static,
+  {
+    let layout = pipeline.descriptor_set_layout(0).unwrap();
+    let view = |image: &Arc<AttachmentImage>| ImageView::new(image.clone()).unwrap();
+    match images {
+      [image] => Arc::new(
+        PersistentDescriptorSet::start(layout.clone())
+          .add_sampled_image(view(image), self.sampler.clone())
+          .unwrap()
+          .add_buffer(pool.next(uniform).unwrap())
+          .unwrap()
+          .build()
+          .unwrap(),
+      ),
+      [first, second] => Arc::new(
+        PersistentDescriptorSet::start(layout.clone())
+          .add_sampled_image(view(first), self.sampler.clone())
+          .unwrap()
+          .add_sampled_image(view(second), self.sampler.clone())
+          .unwrap()
+          .add_buffer(pool.next(uniform).unwrap())
+          .unwrap()
+          .build()
+          .unwrap(),
+      ),
+      _ => panic!("post passes sample one or two images"),
+    }
+  }
+
+  fn pass(
+    &self,
+    builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    framebuffer: Arc<dyn FramebufferAbstract + Send + Sync>,
+    pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+    dynamic: &DynamicState,
+    set: Arc<dyn DescriptorSet + Sync + Send>,
+  ) {
+    builder
+      .begin_render_pass(framebuffer, SubpassContents::Inline, vec![ClearValue::None])
+      .unwrap();
+    self.triangle.draw_indexed(builder, pipeline, dynamic, set);
+    builder.end_render_pass().unwrap();
+  }
+
+  // has to be recorded after the main render pass, image_num is the final image
+  #[profiling::function]
+  pub fn record(
+    &self,
+    builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    image_num: usize,
+    settings: &PostSettings,
+  ) {
+    let bloom_dimensions = bloom_dimensions(self.dimensions);
+    let bloom_dynamic = dynamic_viewport(bloom_dimensions);
+    if settings.bloom {
+      let bright_set = self.sampled_set(
+        &self.pipeline_bright,
+        &[&self.hdr_buffer],
+        &self.bright_buffer,
+        bright_fs::ty::Bright {
+          threshold: settings.bloom_threshold,
+        },
+      );
+      self.pass(
+        builder,
+        self.bloom_framebuffers[0].clone(),
+        self.pipeline_bright.clone(),
+        &bloom_dynamic,
+        bright_set,
+      );
+      let texel = [
+        1.0 / bloom_dimensions[0] as f32,
+        1.0 / bloom_dimensions[1] as f32,
+      ];
+      let directions = [[texel[0], 0.0], [0.0, texel[1]]];
+      for (i, direction) in directions.iter().enumerate() {
+        let blur_set = self.sampled_set(
+          &self.pipeline_blur,
+          &[&self.bloom_buffers[i]],
+          &self.blur_buffer,
+          blur_fs::ty::Blur {
+            direction: *direction,
+          },
+        );
+        self.pass(
+          builder,
+          self.bloom_framebuffers[1 - i].clone(),
+          self.pipeline_blur.clone(),
+          &bloom_dynamic,
+          blur_set,
+        );
+      }
+    } else {
+      // the tonemap pass still samples it, stale bloom from before must not show through
+      builder
+        .clear_color_image(self.bloom_buffers[0].clone(), ClearValue::Float([0.0; 4]))
+        .unwrap();
+    }
+    let tonemap_set = self.sampled_set(
+      &self.pipeline_tonemap,
+      &[&self.hdr_buffer, &self.bloom_buffers[0]],
+      &self.post_buffer,
+      settings.uniform(self.srgb_target),
+    );
+    self.pass(
+      builder,
+      self.framebuffers[image_num].clone(),
+      self.pipeline_tonemap.clone(),
+      &self.dynamic_state(),
+      tonemap_set,
+    );
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::render::post::{bloom_dimensions, PostSettings, Tonemap};
+
+  #[test]
+  pub fn test_post_uniform() {
+    let settings = PostSettings::default();
+    let uniform = settings.uniform(false);
+    assert_eq!(uniform.bloom_intensity, settings.bloom_intensity);
+    assert_eq!(uniform.tonemap, 2);
+    assert_eq!(uniform.gamma, 2.2);
+    // the swapchain encodes srgb by itself
+    assert_eq!(settings.uniform(true).gamma, 1.0);
+
+    let off = PostSettings {
+      bloom: false,
+      tonemap: Tonemap::Off,
+      gamma: false,
+      ..settings
+    };
+    let uniform = off.uniform(false);
+    assert_eq!(uniform.bloom_intensity, 0.0);
+    assert_eq!(uniform.tonemap, 0);
+    assert_eq!(uniform.gamma, 1.0);
+  }
+
+  #[test]
+  pub fn test_bloom_dimensions() {
+    assert_eq!(bloom_dimensions([2400, 1600]), [1200, 800]);
+    assert_eq!(bloom_dimensions([1, 3]), [1, 1]);
+  }
+}
@@ -0,0 +1,72 @@
+// how models are drawn, F4 cycles through them, see systems::view
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum RenderMode {
+  Solid,
+  // the triangle edges over the solid models
+  WireframeOverlay,
+  Wireframe,
+  // lit per triangle instead of per vertex
+  Flat,
+  Normals,
+  // a checker pattern over the texture coordinates
+  UvChecker,
+}
+
+impl Default for RenderMode {
+  fn default() -> Self {
+    RenderMode::Solid
+  }
+}
+
+impl RenderMode {
+  const VALUES: [Self; 6] = [
+    Self::Solid,
+    Self::WireframeOverlay,
+    Self::Wireframe,
+    Self::Flat,
+    Self::Normals,
+    Self::UvChecker,
+  ];
+
+  // the modes drawn with lines need the fill_mode_non_solid device feature
+  pub fn is_wireframe(&self) -> bool {
+    matches!(self, RenderMode::WireframeOverlay | RenderMode::Wireframe)
+  }
+
+  // skips the wireframe modes when the device cannot draw them
+  pub fn next(&self, wireframe: bool) -> RenderMode {
+    let i = RenderMode::VALUES.iter().position(|mode| mode == self).unwrap();
+    (1..=RenderMode::VALUES.len())
+      .map(|step| RenderMode::VALUES[(i + step) % RenderMode::VALUES.len()])
+      .find(|mode| wireframe || !mode.is_wireframe())
+      .unwrap()
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::render::render_mode::RenderMode;
+
+  #[test]
+  pub fn test_next_cycles_through_all() {
+    let mut mode = RenderMode::default();
+    let mut seen = vec![];
+    for _ in 0..RenderMode::VALUES.len() {
+      seen.push(mode);
+      mode = mode.next(true);
+    }
+    assert_eq!(mode, RenderMode::Solid);
+    assert_eq!(seen.len(), 6);
+    for (i, a) in seen.iter().enumerate() {
+      assert!(!seen[i + 1..].contains(a));
+    }
+  }
+
+  #[test]
+  pub fn test_next_without_wireframe() {
+    assert_eq!(RenderMode::Solid.next(false), RenderMode::Flat);
+    assert_eq!(RenderMode::UvChecker.next(false), RenderMode::Solid);
+    // a mode that can not be drawn moves on to the next one that can
+    assert_eq!(RenderMode::WireframeOverlay.next(false), RenderMode::Flat);
+  }
+}
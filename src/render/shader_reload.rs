@@ -0,0 +1,300 @@
+use vulkano::descriptor::descriptor::ShaderStages;
+use vulkano::device::Device;
+use vulkano::pipeline::shader::{GraphicsEntryPoint, GraphicsShaderType, ShaderModule};
+
+use std::ffi::CStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use crate::render::ShaderInterface;
+use crate::shaders::{main, skybox};
+
+// the files vulkano_shaders compiles in, read again at runtime with the shader-hot-reload feature
+pub const MAIN_VS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/shaders/main.vert");
+pub const MAIN_FS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/shaders/main.frag");
+pub const SKYBOX_VS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/shaders/skybox.vert");
+pub const SKYBOX_FS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/shaders/skybox.frag");
+
+// the same files as vulkano_shaders saw them
+const BUILT_IN_MAIN_VS: &str = include_str!("../shaders/main.vert");
+const BUILT_IN_MAIN_FS: &str = include_str!("../shaders/main.frag");
+const BUILT_IN_SKYBOX_VS: &str = include_str!("../shaders/skybox.vert");
+const BUILT_IN_SKYBOX_FS: &str = include_str!("../shaders/skybox.frag");
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ShaderStage {
+  Vertex,
+  Fragment,
+}
+
+// the main and skybox shaders, compiled in or compiled from their glsl files at runtime.
+// the entry points keep the inputs, outputs and descriptors of the compiled in shaders, so
+// reloaded shaders that change those are rejected and still need a rebuild
+#[derive(Clone)]
+pub struct ReloadableShaders {
+  main_vs: Arc<ShaderModule>,
+  main_fs: Arc<ShaderModule>,
+  skybox_vs: Arc<ShaderModule>,
+  skybox_fs: Arc<ShaderModule>,
+  skybox_fs_msaa: Arc<ShaderModule>,
+}
+
+impl ReloadableShaders {
+  pub fn load(device: Arc<Device>) -> ReloadableShaders {
+    ReloadableShaders {
+      main_vs: main::vs::Shader::load(device.clone()).unwrap().module().clone(),
+      main_fs: main::fs::Shader::load(device.clone()).unwrap().module().clone(),
+      skybox_vs: skybox::vs::Shader::load(device.clone()).unwrap().module().clone(),
+      skybox_fs: skybox::fs::Shader::load(device.clone()).unwrap().module().clone(),
+      skybox_fs_msaa: skybox::fs_msaa::Shader::load(device).unwrap().module().clone(),
+    }
+  }
+
+  pub fn paths() -> Vec<PathBuf> {
+    [MAIN_VS, MAIN_FS, SKYBOX_VS, SKYBOX_FS]
+      .iter()
+      .map(PathBuf::from)
+      .collect()
+  }
+
+  // all of the files compiled again, the first error fails all of them. the glsl the binary
+  // was built from is compiled the same way to check the interface of each file against
+  pub fn compile(device: Arc<Device>) -> Result<ReloadableShaders, String> {
+    let module = |path: &str, built_in: &str, stage, defines: &[&str]| {
+      reload_module(&device, path, built_in, stage, defines)
+    };
+    Ok(ReloadableShaders {
+      main_vs: module(MAIN_VS, BUILT_IN_MAIN_VS, ShaderStage::Vertex, &[])?,
+      main_fs: module(MAIN_FS, BUILT_IN_MAIN_FS, ShaderStage::Fragment, &[])?,
+      skybox_vs: module(SKYBOX_VS, BUILT_IN_SKYBOX_VS, ShaderStage::Vertex, &[])?,
+      skybox_fs: module(SKYBOX_FS, BUILT_IN_SKYBOX_FS, ShaderStage::Fragment, &[])?,
+      skybox_fs_msaa: module(SKYBOX_FS, BUILT_IN_SKYBOX_FS, ShaderStage::Fragment, &["MSAA"])?,
+    })
+  }
+
+  pub fn main_vs(
+    &self,
+  ) -> GraphicsEntryPoint<(), main::vs::MainInput, main::vs::MainOutput, main::vs::Layout> {
+    unsafe {
+      self.main_vs.graphics_entry_point(
+        entry_point_name(),
+        main::vs::MainInput,
+        main::vs::MainOutput,
+        main::vs::Layout(vertex_stage()),
+        GraphicsShaderType::Vertex,
+      )
+    }
+  }
+
+  pub fn main_fs(
+    &self,
+  ) -> GraphicsEntryPoint<
+    main::fs::SpecializationConstants,
+    main::fs::MainInput,
+    main::fs::MainOutput,
+    main::fs::Layout,
+  > {
+    unsafe {
+      self.main_fs.graphics_entry_point(
+        entry_point_name(),
+        main::fs::MainInput,
+        main::fs::MainOutput,
+        main::fs::Layout(fragment_stage()),
+        GraphicsShaderType::Fragment,
+      )
+    }
+  }
+
+  pub fn skybox_vs(
+    &self,
+  ) -> GraphicsEntryPoint<(), skybox::vs::MainInput, skybox::vs::MainOutput, skybox::vs::Layout> {
+    unsafe {
+      self.skybox_vs.graphics_entry_point(
+        entry_point_name(),
+        skybox::vs::MainInput,
+        skybox::vs::MainOutput,
+        skybox::vs::Layout(vertex_stage()),
+        GraphicsShaderType::Vertex,
+      )
+    }
+  }
+
+  pub fn skybox_fs(
+    &self,
+  ) -> GraphicsEntryPoint<(), skybox::fs::MainInput, skybox::fs::MainOutput, skybox::fs::Layout> {
+    unsafe {
+      self.skybox_fs.graphics_entry_point(
+        entry_point_name(),
+        skybox::fs::MainInput,
+        skybox::fs::MainOutput,
+        skybox::fs::Layout(fragment_stage()),
+        GraphicsShaderType::Fragment,
+      )
+    }
+  }
+
+  pub fn skybox_fs_msaa(
+    &self,
+  ) -> GraphicsEntryPoint<
+    skybox::fs_msaa::SpecializationConstants,
+    skybox::fs_msaa::MainInput,
+    skybox::fs_msaa::MainOutput,
+    skybox::fs_msaa::Layout,
+  > {
+    unsafe {
+      self.skybox_fs_msaa.graphics_entry_point(
+        entry_point_name(),
+        skybox::fs_msaa::MainInput,
+        skybox::fs_msaa::MainOutput,
+        skybox::fs_msaa::Layout(fragment_stage()),
+        GraphicsShaderType::Fragment,
+      )
+    }
+  }
+}
+
+// a module of the glsl file at path, only if it has the interface of the built in glsl
+fn reload_module(
+  device: &Arc<Device>,
+  path: &str,
+  built_in: &str,
+  stage: ShaderStage,
+  defines: &[&str],
+) -> Result<Arc<ShaderModule>, String> {
+  let words = compile_glsl(Path::new(path), stage, defines)?;
+  let built_in_words = compile_source(built_in, path, stage, defines)?;
+  ShaderInterface::reflect(&words)?
+    .matches(&ShaderInterface::reflect(&built_in_words)?)
+    .map_err(|e| format!("{}: {}, that needs a rebuild", path, e))?;
+  // the entry points are only made for modules with the interface they describe
+  unsafe { ShaderModule::from_words(device.clone(), &words) }
+    .map_err(|e| format!("{}: {:?}", path, e))
+}
+
+fn entry_point_name() -> &'static CStr {
+  CStr::from_bytes_with_nul(b"main\0").unwrap()
+}
+
+fn vertex_stage() -> ShaderStages {
+  ShaderStages {
+    vertex: true,
+    ..ShaderStages::none()
+  }
+}
+
+fn fragment_stage() -> ShaderStages {
+  ShaderStages {
+    fragment: true,
+    ..ShaderStages::none()
+  }
+}
+
+// spir-v words, the error is the compiler output with file names and line numbers.
+// defines are set to 1 like the define option of vulkano_shaders
+pub fn compile_glsl(
+  path: &Path,
+  stage: ShaderStage,
+  defines: &[&str],
+) -> Result<Vec<u32>, String> {
+  let source = fs::read_to_string(path).map_err(|e| format!("{:?}: {}", path, e))?;
+  let file_name = path.file_name().and_then(|name| name.to_str()).unwrap_or("shader");
+  compile_source(&source, file_name, stage, defines)
+}
+
+#[cfg(feature = "shader-hot-reload")]
+fn compile_source(
+  source: &str,
+  file_name: &str,
+  stage: ShaderStage,
+  defines: &[&str],
+) -> Result<Vec<u32>, String> {
+  let kind = match stage {
+    ShaderStage::Vertex => shaderc::ShaderKind::Vertex,
+    ShaderStage::Fragment => shaderc::ShaderKind::Fragment,
+  };
+  let mut compiler =
+    shaderc::Compiler::new().ok_or_else(|| "shaderc is not available".to_string())?;
+  let mut options =
+    shaderc::CompileOptions::new().ok_or_else(|| "shaderc has no options".to_string())?;
+  for define in defines {
+    options.add_macro_definition(define, Some("1"));
+  }
+  let artifact = compiler
+    .compile_into_spirv(source, kind, file_name, "main", Some(&options))
+    .map_err(|e| e.to_string())?;
+  Ok(artifact.as_binary().to_vec())
+}
+
+#[cfg(not(feature = "shader-hot-reload"))]
+fn compile_source(
+  _source: &str,
+  file_name: &str,
+  _stage: ShaderStage,
+  _defines: &[&str],
+) -> Result<Vec<u32>, String> {
+  Err(format!(
+    "{} can not be compiled at runtime without the shader-hot-reload feature",
+    file_name
+  ))
+}
+
+// polls the modification times, a handful of files can be checked every frame
+pub struct FileWatcher {
+  paths: Vec<PathBuf>,
+  modified: Vec<Option<SystemTime>>,
+}
+
+impl FileWatcher {
+  pub fn new(paths: Vec<PathBuf>) -> FileWatcher {
+    let modified = paths.iter().map(|path| modified(path)).collect();
+    FileWatcher { paths, modified }
+  }
+
+  // true once for any number of files changed, removed or created since the last call
+  pub fn changed(&mut self) -> bool {
+    let mut changed = false;
+    for (path, last) in self.paths.iter().zip(self.modified.iter_mut()) {
+      let now = modified(path);
+      if now != *last {
+        *last = now;
+        changed = true;
+      }
+    }
+    changed
+  }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+  fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+#[cfg(test)]
+mod test {
+  use std::fs;
+
+  use crate::render::shader_reload::{FileWatcher, ReloadableShaders};
+
+  #[test]
+  pub fn test_file_watcher() {
+    let path = std::env::temp_dir().join(format!("ggga-watch-{}.frag", std::process::id()));
+    fs::write(&path, "void main() {}").unwrap();
+    let mut watcher = FileWatcher::new(vec![path.clone()]);
+    assert!(!watcher.changed());
+    fs::remove_file(&path).unwrap();
+    assert!(watcher.changed());
+    // reported once
+    assert!(!watcher.changed());
+    fs::write(&path, "void main() {}").unwrap();
+    assert!(watcher.changed());
+    fs::remove_file(&path).unwrap();
+  }
+
+  #[test]
+  pub fn test_shader_files_exist() {
+    for path in ReloadableShaders::paths() {
+      assert!(path.exists(), "{:?}", path);
+    }
+  }
+}
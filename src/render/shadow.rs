@@ -0,0 +1,164 @@
+use cgmath::{EuclideanSpace, InnerSpace, Matrix4, Point3, SquareMatrix, Vector3, Vector4};
+
+pub const CASCADES: usize = 3;
+// width and height of each cascade's depth map
+pub const SHADOW_SIZE: u32 = 2048;
+// blend between uniform (0.0) and logarithmic (1.0) splits
+const SPLIT_LAMBDA: f32 = 0.75;
+// casters up to this far behind a cascade towards the sun still cast into it
+const CASTER_MARGIN: f32 = 200.0;
+
+// cgmath builds opengl clip space with -1 <= z <= 1, vulkan wants 0 <= z <= 1
+#[rustfmt::skip]
+const VULKAN_CLIP: Matrix4<f32> = Matrix4::new(
+  1.0, 0.0, 0.0, 0.0,
+  0.0, 1.0, 0.0, 0.0,
+  0.0, 0.0, 0.5, 0.0,
+  0.0, 0.0, 0.5, 1.0,
+);
+
+// far distance of each cascade, the practical split scheme of parallel split shadow maps
+pub fn cascade_splits(near: f32, far: f32, count: usize, lambda: f32) -> Vec<f32> {
+  (1..=count)
+    .map(|i| {
+      let f = i as f32 / count as f32;
+      let log = near * (far / near).powf(f);
+      let uniform = near + (far - near) * f;
+      lambda * log + (1.0 - lambda) * uniform
+    })
+    .collect()
+}
+
+// corners of the part of the camera frustum between the view distances from and to
+fn slice_corners(
+  view_proj: Matrix4<f32>,
+  near: f32,
+  far: f32,
+  from: f32,
+  to: f32,
+) -> Vec<Point3<f32>> {
+  let inverse = view_proj.invert().unwrap();
+  let unproject = |x: f32, y: f32, z: f32| {
+    let p = inverse * Vector4::new(x, y, z, 1.0);
+    Point3::from_vec(p.truncate() / p.w)
+  };
+  let (t0, t1) = ((from - near) / (far - near), (to - near) / (far - near));
+  let mut corners = vec![];
+  for &(x, y) in &[(-1.0, -1.0), (1.0, -1.0), (-1.0, 1.0), (1.0, 1.0)] {
+    let (n, f) = (unproject(x, y, -1.0), unproject(x, y, 1.0));
+    // depth is linear along the frustum edges
+    corners.push(n + (f - n) * t0);
+    corners.push(n + (f - n) * t1);
+  }
+  corners
+}
+
+// light view projections, each fitting a slice of the camera frustum
+#[derive(Debug, Copy, Clone)]
+pub struct Cascades {
+  pub matrices: [Matrix4<f32>; CASCADES],
+  pub splits: [f32; CASCADES],
+}
+
+impl Cascades {
+  // view_proj of the camera with its near and far planes, towards_sun points at the sun
+  pub fn fit(view_proj: Matrix4<f32>, near: f32, far: f32, towards_sun: Vector3<f32>) -> Self {
+    let towards_sun = towards_sun.normalize();
+    let up = if towards_sun.y.abs() > 0.99 {
+      Vector3::unit_z()
+    } else {
+      Vector3::unit_y()
+    };
+    let splits = cascade_splits(near, far, CASCADES, SPLIT_LAMBDA);
+    let mut cascades = Cascades {
+      matrices: [Matrix4::identity(); CASCADES],
+      splits: [far; CASCADES],
+    };
+    let mut from = near;
+    for (i, to) in splits.iter().enumerate() {
+      let corners = slice_corners(view_proj, near, far, from, *to);
+      let center = Point3::centroid(&corners);
+      // a bounding sphere keeps the size of the cascade steady while the camera turns
+      let radius = corners
+        .iter()
+        .map(|c| (c - center).magnitude())
+        .fold(0.0, f32::max)
+        .ceil();
+      let eye = center + towards_sun * (radius + CASTER_MARGIN);
+      let view = Matrix4::look_at_rh(eye, center, up);
+      let proj = cgmath::ortho(-radius, radius, -radius, radius, 0.0, 2.0 * radius + CASTER_MARGIN);
+      cascades.matrices[i] = VULKAN_CLIP * proj * view;
+      cascades.splits[i] = *to;
+      from = *to;
+    }
+    cascades
+  }
+
+  // to the light clip space of a cascade from model coordinates placed with world
+  pub fn light_matrix(&self, cascade: usize, world: Matrix4<f32>) -> Matrix4<f32> {
+    self.matrices[cascade] * world
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::render::shadow::{cascade_splits, slice_corners, Cascades, CASCADES};
+  use cgmath::{Matrix4, Point3, Rad, Transform, Vector3};
+
+  #[test]
+  pub fn test_cascade_splits() {
+    let uniform = cascade_splits(1.0, 100.0, 4, 0.0);
+    assert_eq!(uniform, vec![25.75, 50.5, 75.25, 100.0]);
+
+    let log = cascade_splits(1.0, 100.0, 2, 1.0);
+    assert!((log[0] - 10.0).abs() < 1e-4);
+    assert!((log[1] - 100.0).abs() < 1e-4);
+
+    // 0.75 * 0.1 * 2000^(i / 3) + 0.25 * (0.1 + 199.9 * i / 3)
+    let splits = cascade_splits(0.1, 200.0, 3, 0.75);
+    let expected = [
+      0.75 * 1.259921 + 0.25 * 66.733333,
+      0.75 * 15.874011 + 0.25 * 133.366667,
+      200.0,
+    ];
+    assert_eq!(splits.len(), 3);
+    for (split, expected) in splits.iter().zip(expected.iter()) {
+      assert!((split - expected).abs() < 1e-3, "{} {}", split, expected);
+    }
+    assert!((splits[0] - 17.628274).abs() < 1e-3);
+    assert!((splits[1] - 45.247175).abs() < 1e-3);
+    // the near cascades cover less than an even split of the same range would
+    let even = cascade_splits(0.1, 200.0, 3, 0.0);
+    assert!(splits[0] < even[0] && splits[1] < even[1]);
+  }
+
+  fn camera() -> Matrix4<f32> {
+    let proj = cgmath::perspective(Rad(std::f32::consts::FRAC_PI_2), 1.5, 0.1, 200.0);
+    let view = Matrix4::look_at_rh(
+      Point3::new(10.0, -5.0, 0.0),
+      Point3::new(10.0, -5.0, 1.0),
+      Vector3::new(0.0, 1.0, 0.0),
+    );
+    proj * view
+  }
+
+  #[test]
+  pub fn test_cascades_contain_their_slice() {
+    let view_proj = camera();
+    let cascades = Cascades::fit(view_proj, 0.1, 200.0, Vector3::new(-0.577, -0.577, -0.577));
+    let mut from = 0.1;
+    for i in 0..CASCADES {
+      let to = cascades.splits[i];
+      for corner in slice_corners(view_proj, 0.1, 200.0, from, to) {
+        let p = cascades.light_matrix(i, Matrix4::from_scale(1.0)).transform_point(corner);
+        assert!(p.x.abs() <= 1.0 && p.y.abs() <= 1.0, "{} {:?}", i, p);
+        assert!(p.z >= 0.0 && p.z <= 1.0, "{} {:?}", i, p);
+      }
+      from = to;
+    }
+    // the slice corners are at the split distances along the view direction
+    let corners = slice_corners(view_proj, 0.1, 200.0, 10.0, 20.0);
+    assert!((corners[0].z - 10.0).abs() < 1e-3);
+    assert!((corners[1].z - 20.0).abs() < 1e-3);
+  }
+}
@@ -1,4 +1,7 @@
-use image::ImageFormat;
+use cgmath::{InnerSpace, Vector3};
+use image::codecs::hdr::HdrDecoder;
+use image::error::{ParameterError, ParameterErrorKind};
+use image::{ImageError, ImageResult, RgbaImage};
 use vulkano::device::Queue;
 use vulkano::format::Format;
 use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer};
@@ -8,76 +11,233 @@ use vulkano::image::{ImageDimensions, ImmutableImage, MipmapsCount, ImageUsage,
 use vulkano::sampler::{Filter, MipmapMode, Sampler, SamplerAddressMode};
 use vulkano::sync::GpuFuture;
 use vulkano::command_buffer::{AutoCommandBufferBuilder, CommandBufferUsage};
+use profiling;
 
+use std::f32::consts::PI;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
+// the layers of a vulkan cubemap, in order
+pub const FACE_NAMES: [&str; 6] = ["xpos", "xneg", "ypos", "yneg", "zpos", "zneg"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SkyboxSource {
+  // a directory with a png per face, named as in FACE_NAMES
+  Faces(PathBuf),
+  // a single panorama, .hdr or any other format the image crate reads
+  Equirectangular(PathBuf),
+}
+
+impl SkyboxSource {
+  pub fn from_path(path: &Path) -> SkyboxSource {
+    if path.is_dir() {
+      SkyboxSource::Faces(path.to_path_buf())
+    } else {
+      SkyboxSource::Equirectangular(path.to_path_buf())
+    }
+  }
+}
+
+// six square rgba srgb faces one after the other, in FACE_NAMES order
+#[derive(Debug, Clone, PartialEq)]
+pub struct CubeFaces {
+  pub size: u32,
+  pub data: Vec<u8>,
+}
+
+impl CubeFaces {
+  #[profiling::function]
+  pub fn load(source: &SkyboxSource) -> ImageResult<CubeFaces> {
+    match source {
+      SkyboxSource::Faces(dir) => {
+        let mut faces = vec![];
+        for name in FACE_NAMES.iter() {
+          faces.push(image::open(dir.join(format!("{}.png", name)))?.to_rgba8());
+        }
+        CubeFaces::from_faces(&faces)
+      }
+      SkyboxSource::Equirectangular(path) => {
+        let panorama = Panorama::open(path)?;
+        // a quarter of the panorama width keeps about the same detail around the horizon
+        let size = (panorama.width / 4).max(1);
+        Ok(panorama.to_cube(size))
+      }
+    }
+  }
+
+  pub fn from_faces(faces: &[RgbaImage]) -> ImageResult<CubeFaces> {
+    let mismatch =
+      || ImageError::Parameter(ParameterError::from_kind(ParameterErrorKind::DimensionMismatch));
+    if faces.len() != 6 {
+      return Err(mismatch());
+    }
+    let size = faces[0].width();
+    if faces.iter().any(|face| face.dimensions() != (size, size)) {
+      return Err(mismatch());
+    }
+    let mut data = Vec::with_capacity((size * size * 4 * 6) as usize);
+    for face in faces {
+      data.extend_from_slice(face.as_raw());
+    }
+    Ok(CubeFaces { size, data })
+  }
+
+  // when the configured skybox cannot be loaded there are no stars
+  pub fn black() -> CubeFaces {
+    CubeFaces {
+      size: 1,
+      data: [0, 0, 0, 255].repeat(6),
+    }
+  }
+}
+
+// the direction through s, t of a face, s and t go from 0 to 1 across the face like the
+// texture coordinates vulkan selects the face with, see the cube map image selection table
+pub fn face_direction(face: usize, s: f32, t: f32) -> Vector3<f32> {
+  let (sc, tc) = (2.0 * s - 1.0, 2.0 * t - 1.0);
+  let direction = match face {
+    0 => Vector3::new(1.0, -tc, -sc),
+    1 => Vector3::new(-1.0, -tc, sc),
+    2 => Vector3::new(sc, 1.0, tc),
+    3 => Vector3::new(sc, -1.0, -tc),
+    4 => Vector3::new(sc, -tc, 1.0),
+    5 => Vector3::new(-sc, -tc, -1.0),
+    _ => panic!("a cube has 6 faces, not {}", face),
+  };
+  direction.normalize()
+}
+
+// u goes around y starting behind -z, through +x at 0.75, v from +y at the top to -y at the bottom
+pub fn equirect_uv(direction: Vector3<f32>) -> (f32, f32) {
+  let d = direction.normalize();
+  let u = 0.5 + d.x.atan2(d.z) / (2.0 * PI);
+  let v = d.y.max(-1.0).min(1.0).acos() / PI;
+  (u, v)
+}
+
+fn srgb_to_linear(c: u8) -> f32 {
+  let c = c as f32 / 255.0;
+  if c <= 0.04045 {
+    c / 12.92
+  } else {
+    ((c + 0.055) / 1.055).powf(2.4)
+  }
+}
+
+// the cubemap is 8 bit, brighter than white hdr values are clamped
+fn linear_to_srgb(c: f32) -> u8 {
+  let c = c.max(0.0).min(1.0);
+  let s = if c <= 0.0031308 {
+    c * 12.92
+  } else {
+    1.055 * c.powf(1.0 / 2.4) - 0.055
+  };
+  (s * 255.0).round() as u8
+}
+
+// an equirectangular image in linear rgb
+pub struct Panorama {
+  pub width: u32,
+  pub height: u32,
+  pub pixels: Vec<[f32; 3]>,
+}
+
+impl Panorama {
+  #[profiling::function]
+  pub fn open(path: &Path) -> ImageResult<Panorama> {
+    let is_hdr = path
+      .extension()
+      .map_or(false, |ext| ext.eq_ignore_ascii_case("hdr"));
+    if is_hdr {
+      let decoder = HdrDecoder::new(BufReader::new(File::open(path)?))?;
+      let metadata = decoder.metadata();
+      let pixels = decoder.read_image_hdr()?.iter().map(|p| p.0).collect();
+      Ok(Panorama {
+        width: metadata.width,
+        height: metadata.height,
+        pixels,
+      })
+    } else {
+      let image = image::open(path)?.to_rgb8();
+      let pixels = image
+        .pixels()
+        .map(|p| [srgb_to_linear(p[0]), srgb_to_linear(p[1]), srgb_to_linear(p[2])])
+        .collect();
+      Ok(Panorama {
+        width: image.width(),
+        height: image.height(),
+        pixels,
+      })
+    }
+  }
+
+  // bilinear, wrapping around horizontally
+  pub fn sample(&self, direction: Vector3<f32>) -> [f32; 3] {
+    let (u, v) = equirect_uv(direction);
+    let (w, h) = (self.width as i64, self.height as i64);
+    let x = u * self.width as f32 - 0.5;
+    let y = v * self.height as f32 - 0.5;
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+    let at = |x: i64, y: i64| {
+      let (x, y) = (x.rem_euclid(w), y.max(0).min(h - 1));
+      self.pixels[(y * w + x) as usize]
+    };
+    let (x0, y0) = (x0 as i64, y0 as i64);
+    let lerp = |a: [f32; 3], b: [f32; 3], t: f32| {
+      [
+        a[0] + (b[0] - a[0]) * t,
+        a[1] + (b[1] - a[1]) * t,
+        a[2] + (b[2] - a[2]) * t,
+      ]
+    };
+    let top = lerp(at(x0, y0), at(x0 + 1, y0), fx);
+    let bottom = lerp(at(x0, y0 + 1), at(x0 + 1, y0 + 1), fx);
+    lerp(top, bottom, fy)
+  }
+
+  #[profiling::function]
+  pub fn to_cube(&self, size: u32) -> CubeFaces {
+    let mut data = Vec::with_capacity((size * size * 4 * 6) as usize);
+    for face in 0..6 {
+      for y in 0..size {
+        for x in 0..size {
+          let s = (x as f32 + 0.5) / size as f32;
+          let t = (y as f32 + 0.5) / size as f32;
+          let color = self.sample(face_direction(face, s, t));
+          data.extend_from_slice(&[
+            linear_to_srgb(color[0]),
+            linear_to_srgb(color[1]),
+            linear_to_srgb(color[2]),
+            255,
+          ]);
+        }
+      }
+    }
+    CubeFaces { size, data }
+  }
+}
+
 pub struct SkyboxCubemap {
   pub texture: Arc<ImmutableImage>,
   pub sampler: Arc<Sampler>,
 }
 
 impl SkyboxCubemap {
-  pub fn new(queue: &Arc<Queue>) -> (Self, Box<dyn GpuFuture>) {
-    let img_negx = image::load_from_memory_with_format(
-      include_bytes!("../../assets/interstellar_skybox/xneg.png"),
-      ImageFormat::Png,
-    )
-    .unwrap()
-    .to_rgba8();
-    let img_posx = image::load_from_memory_with_format(
-      include_bytes!("../../assets/interstellar_skybox/xpos.png"),
-      ImageFormat::Png,
-    )
-    .unwrap()
-    .to_rgba8();
-    let img_posy = image::load_from_memory_with_format(
-      include_bytes!("../../assets/interstellar_skybox/ypos.png"),
-      ImageFormat::Png,
-    )
-    .unwrap()
-    .to_rgba8();
-    let img_negy = image::load_from_memory_with_format(
-      include_bytes!("../../assets/interstellar_skybox/yneg.png"),
-      ImageFormat::Png,
-    )
-    .unwrap()
-    .to_rgba8();
-    let img_negz = image::load_from_memory_with_format(
-      include_bytes!("../../assets/interstellar_skybox/zneg.png"),
-      ImageFormat::Png,
-    )
-    .unwrap()
-    .to_rgba8();
-    let img_posz = image::load_from_memory_with_format(
-      include_bytes!("../../assets/interstellar_skybox/zpos.png"),
-      ImageFormat::Png,
-    )
-    .unwrap()
-    .to_rgba8();
-
-    let cubemap_images = [img_posx, img_negx, img_posy, img_negy, img_posz, img_negz];
-    let mut image_data: Vec<u8> = Vec::new();
-    let mut height = 0;
-    let mut width = 0;
-    for image in cubemap_images.iter() {
-      let (w, h) = image.dimensions();
-      height = h;
-      width = w;
-      let mut image0 = image.clone().into_raw().clone();
-      image_data.append(&mut image0);
-    }
-
+  pub fn new(queue: &Arc<Queue>, faces: &CubeFaces) -> (Self, Box<dyn GpuFuture>) {
     let source = CpuAccessibleBuffer::from_iter(
       queue.device().clone(),
       BufferUsage::transfer_source(),
       false,
-      image_data.iter().cloned(),
+      faces.data.iter().cloned(),
     )
     .unwrap();
 
     let dimensions = ImageDimensions::Dim2d {
-      width,
-      height,
+      width: faces.size,
+      height: faces.size,
       array_layers: 6,
     };
 
@@ -155,3 +315,114 @@ impl SkyboxCubemap {
     (SkyboxCubemap{ texture, sampler }, future.boxed())
   }
 }
+
+#[cfg(test)]
+mod test {
+  use cgmath::{InnerSpace, Vector3};
+  use image::RgbaImage;
+
+  use crate::render::skybox::{equirect_uv, face_direction, CubeFaces, Panorama};
+
+  const EPSILON: f32 = 1e-5;
+
+  // the face and texture coordinates vulkan samples for a direction
+  fn face_of(d: Vector3<f32>) -> (usize, f32, f32) {
+    let (ax, ay, az) = (d.x.abs(), d.y.abs(), d.z.abs());
+    let (face, sc, tc, ma) = if ax >= ay && ax >= az {
+      if d.x > 0.0 {
+        (0, -d.z, -d.y, ax)
+      } else {
+        (1, d.z, -d.y, ax)
+      }
+    } else if ay >= az {
+      if d.y > 0.0 {
+        (2, d.x, d.z, ay)
+      } else {
+        (3, d.x, -d.z, ay)
+      }
+    } else if d.z > 0.0 {
+      (4, d.x, -d.y, az)
+    } else {
+      (5, -d.x, -d.y, az)
+    };
+    (face, (sc / ma + 1.0) / 2.0, (tc / ma + 1.0) / 2.0)
+  }
+
+  #[test]
+  pub fn test_face_order() {
+    let axes = [
+      Vector3::new(1.0, 0.0, 0.0),
+      Vector3::new(-1.0, 0.0, 0.0),
+      Vector3::new(0.0, 1.0, 0.0),
+      Vector3::new(0.0, -1.0, 0.0),
+      Vector3::new(0.0, 0.0, 1.0),
+      Vector3::new(0.0, 0.0, -1.0),
+    ];
+    for (face, axis) in axes.iter().enumerate() {
+      assert!((face_direction(face, 0.5, 0.5) - axis).magnitude() < EPSILON);
+    }
+  }
+
+  #[test]
+  pub fn test_face_direction_is_sampled_back() {
+    for face in 0..6 {
+      for &(s, t) in &[(0.1, 0.2), (0.5, 0.5), (0.9, 0.3), (0.25, 0.8)] {
+        let (sampled, ss, st) = face_of(face_direction(face, s, t));
+        assert_eq!(sampled, face);
+        assert!((ss - s).abs() < EPSILON && (st - t).abs() < EPSILON);
+      }
+    }
+  }
+
+  #[test]
+  pub fn test_equirect_uv() {
+    let uv = |x, y, z| equirect_uv(Vector3::new(x, y, z));
+    let close = |(u, v): (f32, f32), (eu, ev): (f32, f32)| {
+      (u - eu).abs() < EPSILON && (v - ev).abs() < EPSILON
+    };
+    assert!(close(uv(0.0, 0.0, 1.0), (0.5, 0.5)));
+    assert!(close(uv(1.0, 0.0, 0.0), (0.75, 0.5)));
+    assert!(close(uv(-1.0, 0.0, 0.0), (0.25, 0.5)));
+    assert_eq!(uv(0.0, 1.0, 0.0).1, 0.0);
+    assert!(close((0.0, uv(0.0, -1.0, 0.0).1), (0.0, 1.0)));
+  }
+
+  #[test]
+  pub fn test_panorama_to_cube() {
+    // white sky over a black ground
+    let (width, height) = (16, 8);
+    let pixels = (0..width * height)
+      .map(|i| if i / width < height / 2 { [1.0; 3] } else { [0.0; 3] })
+      .collect();
+    let panorama = Panorama {
+      width,
+      height,
+      pixels,
+    };
+    let size = 4;
+    let cube = panorama.to_cube(size);
+    let face_len = (size * size * 4) as usize;
+    assert_eq!(cube.data.len(), face_len * 6);
+    let face = |i: usize| &cube.data[i * face_len..(i + 1) * face_len];
+    assert!(face(2).chunks(4).all(|p| p == [255, 255, 255, 255]));
+    assert!(face(3).chunks(4).all(|p| p == [0, 0, 0, 255]));
+    // the side faces have the sky in their top rows
+    let side = face(4);
+    assert_eq!(&side[0..4], &[255, 255, 255, 255]);
+    assert_eq!(&side[face_len - 4..], &[0, 0, 0, 255]);
+  }
+
+  #[test]
+  pub fn test_faces_must_match() {
+    let faces = vec![RgbaImage::new(2, 2); 6];
+    let cube = CubeFaces::from_faces(&faces).unwrap();
+    assert_eq!(cube.size, 2);
+    assert_eq!(cube.data.len(), 2 * 2 * 4 * 6);
+
+    let mut faces = faces;
+    faces[3] = RgbaImage::new(2, 3);
+    assert!(CubeFaces::from_faces(&faces).is_err());
+    assert!(CubeFaces::from_faces(&faces[0..5]).is_err());
+    assert!(CubeFaces::from_faces(&[]).is_err());
+  }
+}
@@ -0,0 +1,298 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Debug;
+
+// https://registry.khronos.org/SPIR-V/specs/unified1/SPIRV.html
+const MAGIC: u32 = 0x0723_0203;
+const HEADER_WORDS: usize = 5;
+
+const OP_TYPE_VOID: u32 = 19;
+const OP_TYPE_POINTER: u32 = 32;
+const OP_CONSTANT: u32 = 43;
+const OP_SPEC_CONSTANT_TRUE: u32 = 48;
+const OP_SPEC_CONSTANT: u32 = 50;
+const OP_VARIABLE: u32 = 59;
+const OP_DECORATE: u32 = 71;
+const OP_MEMBER_DECORATE: u32 = 72;
+
+const STORAGE_UNIFORM_CONSTANT: u32 = 0;
+const STORAGE_INPUT: u32 = 1;
+const STORAGE_UNIFORM: u32 = 2;
+const STORAGE_OUTPUT: u32 = 3;
+const STORAGE_PUSH_CONSTANT: u32 = 9;
+const STORAGE_STORAGE_BUFFER: u32 = 12;
+
+const DECORATION_SPEC_ID: u32 = 1;
+const DECORATION_BUILT_IN: u32 = 11;
+const DECORATION_LOCATION: u32 = 30;
+const DECORATION_BINDING: u32 = 33;
+const DECORATION_DESCRIPTOR_SET: u32 = 34;
+
+// what a pipeline relies on in a shader module: the types of its inputs and outputs by
+// location, of its descriptors by set and binding, of its push constants and of its
+// specialization constants by id. the types are written out with the offsets and other
+// decorations of their members, names are left out
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ShaderInterface {
+  pub inputs: BTreeMap<u32, String>,
+  pub outputs: BTreeMap<u32, String>,
+  pub descriptors: BTreeMap<(u32, u32), String>,
+  pub push_constants: Option<String>,
+  pub spec_constants: BTreeMap<u32, String>,
+}
+
+// the declarations of a module the interface is described with
+#[derive(Default)]
+struct Module {
+  // opcode and operands of each type, by result id
+  types: HashMap<u32, (u32, Vec<u32>)>,
+  // the first word of integer constants, array lengths are those
+  constants: HashMap<u32, u32>,
+  decorations: HashMap<u32, Vec<Vec<u32>>>,
+  member_decorations: HashMap<(u32, u32), Vec<Vec<u32>>>,
+  // result type, result id and storage class
+  variables: Vec<(u32, u32, u32)>,
+  // result type and result id
+  spec_constants: Vec<(u32, u32)>,
+}
+
+impl Module {
+  fn parse(words: &[u32]) -> Result<Module, String> {
+    if words.len() < HEADER_WORDS || words[0] != MAGIC {
+      return Err("not spir-v".to_string());
+    }
+    let mut module = Module::default();
+    let mut i = HEADER_WORDS;
+    while i < words.len() {
+      let count = (words[i] >> 16) as usize;
+      let opcode = words[i] & 0xffff;
+      if count == 0 || i + count > words.len() {
+        return Err(format!("truncated instruction at word {}", i));
+      }
+      let operands = &words[i + 1..i + count];
+      let operand = |n: usize| {
+        operands
+          .get(n)
+          .copied()
+          .ok_or_else(|| format!("opcode {} at word {} is missing operands", opcode, i))
+      };
+      match opcode {
+        OP_TYPE_VOID..=OP_TYPE_POINTER => {
+          module.types.insert(operand(0)?, (opcode, operands[1..].to_vec()));
+        }
+        OP_CONSTANT => {
+          module.constants.insert(operand(1)?, operand(2)?);
+        }
+        OP_SPEC_CONSTANT_TRUE..=OP_SPEC_CONSTANT => {
+          module.spec_constants.push((operand(0)?, operand(1)?));
+        }
+        OP_VARIABLE => {
+          module.variables.push((operand(0)?, operand(1)?, operand(2)?));
+        }
+        OP_DECORATE => {
+          let (target, _) = (operand(0)?, operand(1)?);
+          module.decorations.entry(target).or_default().push(operands[1..].to_vec());
+        }
+        OP_MEMBER_DECORATE => {
+          let (member, _) = ((operand(0)?, operand(1)?), operand(2)?);
+          let decorations = module.member_decorations.entry(member).or_default();
+          decorations.push(operands[2..].to_vec());
+        }
+        _ => {}
+      }
+      i += count;
+    }
+    Ok(module)
+  }
+
+  // the literal of a decoration of id, as Location 3 has 3
+  fn decoration(&self, id: u32, decoration: u32) -> Option<u32> {
+    self
+      .decorations
+      .get(&id)
+      .and_then(|all| all.iter().find(|d| d[0] == decoration))
+      .map(|d| d.get(1).copied().unwrap_or(0))
+  }
+
+  // the decorations of id without the ones listed
+  fn decorations_except(&self, id: u32, except: &[u32]) -> String {
+    let mut decorations: Vec<&Vec<u32>> = self
+      .decorations
+      .get(&id)
+      .map(|all| all.iter().filter(|d| !except.contains(&d[0])).collect())
+      .unwrap_or_default();
+    decorations.sort();
+    decorations.iter().map(|d| format!(" {:?}", d)).collect()
+  }
+
+  fn describe(&self, id: u32) -> Result<String, String> {
+    let (opcode, operands) = self.types.get(&id).ok_or_else(|| format!("no type {}", id))?;
+    let operand = |n: usize| {
+      operands
+        .get(n)
+        .copied()
+        .ok_or_else(|| format!("type {} is missing operands", id))
+    };
+    let decorations = self.decorations_except(id, &[]);
+    let description = match *opcode {
+      // vectors, matrices, images, sampled images and arrays of another type
+      23 | 24 | 25 | 27 | 28 | 29 => {
+        let inner = self.describe(operand(0)?)?;
+        let length = match *opcode {
+          28 => vec![self.constants.get(&operand(1)?).copied().unwrap_or(0)],
+          _ => operands[1..].to_vec(),
+        };
+        format!("{}<{} {:?}>", opcode, inner, length)
+      }
+      // structs, with the decorations of each member
+      30 => {
+        let mut members = vec![];
+        for (member, &ty) in operands.iter().enumerate() {
+          let mut decorations = self
+            .member_decorations
+            .get(&(id, member as u32))
+            .cloned()
+            .unwrap_or_default();
+          decorations.sort();
+          members.push(format!("{} {:?}", self.describe(ty)?, decorations));
+        }
+        format!("struct {{{}}}", members.join(", "))
+      }
+      OP_TYPE_POINTER => self.describe(operand(1)?)?,
+      _ => format!("{} {:?}", opcode, operands),
+    };
+    Ok(format!("{}{}", description, decorations))
+  }
+}
+
+impl ShaderInterface {
+  pub fn reflect(words: &[u32]) -> Result<ShaderInterface, String> {
+    let module = Module::parse(words)?;
+    let mut interface = ShaderInterface::default();
+    for &(ty, id, storage) in &module.variables {
+      let description = || -> Result<String, String> {
+        let except = [DECORATION_LOCATION, DECORATION_BINDING, DECORATION_DESCRIPTOR_SET];
+        Ok(format!("{}{}", module.describe(ty)?, module.decorations_except(id, &except)))
+      };
+      match storage {
+        STORAGE_INPUT | STORAGE_OUTPUT => {
+          // built ins like gl_Position have no location
+          if module.decoration(id, DECORATION_BUILT_IN).is_some() {
+            continue;
+          }
+          if let Some(location) = module.decoration(id, DECORATION_LOCATION) {
+            let interface_variables = match storage {
+              STORAGE_INPUT => &mut interface.inputs,
+              _ => &mut interface.outputs,
+            };
+            interface_variables.insert(location, description()?);
+          }
+        }
+        STORAGE_UNIFORM_CONSTANT | STORAGE_UNIFORM | STORAGE_STORAGE_BUFFER => {
+          let set = module.decoration(id, DECORATION_DESCRIPTOR_SET).unwrap_or(0);
+          if let Some(binding) = module.decoration(id, DECORATION_BINDING) {
+            let description = format!("storage {} {}", storage, description()?);
+            interface.descriptors.insert((set, binding), description);
+          }
+        }
+        STORAGE_PUSH_CONSTANT => interface.push_constants = Some(description()?),
+        _ => {}
+      }
+    }
+    for &(ty, id) in &module.spec_constants {
+      if let Some(spec_id) = module.decoration(id, DECORATION_SPEC_ID) {
+        interface.spec_constants.insert(spec_id, module.describe(ty)?);
+      }
+    }
+    Ok(interface)
+  }
+
+  // the first difference to the interface the pipelines were made for
+  pub fn matches(&self, built_in: &ShaderInterface) -> Result<(), String> {
+    matches("input location", &self.inputs, &built_in.inputs)?;
+    matches("output location", &self.outputs, &built_in.outputs)?;
+    matches("descriptor (set, binding)", &self.descriptors, &built_in.descriptors)?;
+    matches("specialization constant", &self.spec_constants, &built_in.spec_constants)?;
+    if self.push_constants != built_in.push_constants {
+      return Err("the push constants changed".to_string());
+    }
+    Ok(())
+  }
+}
+
+fn matches<K: Ord + Debug>(
+  what: &str,
+  reloaded: &BTreeMap<K, String>,
+  built_in: &BTreeMap<K, String>,
+) -> Result<(), String> {
+  for key in built_in.keys().chain(reloaded.keys()) {
+    match (reloaded.get(key), built_in.get(key)) {
+      (Some(a), Some(b)) if a == b => {}
+      (None, _) => return Err(format!("{} {:?} was removed", what, key)),
+      (_, None) => return Err(format!("{} {:?} was added", what, key)),
+      _ => return Err(format!("{} {:?} changed its type", what, key)),
+    }
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod test {
+  use crate::render::spirv_interface::ShaderInterface;
+
+  fn instruction(opcode: u32, operands: &[u32]) -> Vec<u32> {
+    let mut words = vec![((operands.len() as u32 + 1) << 16) | opcode];
+    words.extend_from_slice(operands);
+    words
+  }
+
+  // layout(location = 0) in vec4 color at location, with a uniform float at set 0, binding
+  // 2 and a gl_FragCoord the interface leaves out
+  fn module(location: u32, components: u32, binding: u32) -> Vec<u32> {
+    let mut words = vec![0x0723_0203, 0x0001_0000, 0, 20, 0];
+    let instructions = [
+      // float, vec, pointers to them
+      instruction(22, &[1, 32]),
+      instruction(23, &[2, 1, components]),
+      instruction(32, &[3, 1, 2]),
+      instruction(30, &[4, 1]),
+      instruction(32, &[5, 2, 4]),
+      instruction(32, &[6, 1, 2]),
+      instruction(71, &[7, 30, location]),
+      instruction(71, &[8, 33, binding]),
+      instruction(71, &[8, 34, 0]),
+      instruction(71, &[4, 2]),
+      instruction(72, &[4, 0, 35, 0]),
+      instruction(71, &[9, 11, 15]),
+      instruction(59, &[3, 7, 1]),
+      instruction(59, &[5, 8, 2]),
+      instruction(59, &[6, 9, 1]),
+    ];
+    for instruction in instructions.iter() {
+      words.extend_from_slice(instruction);
+    }
+    words
+  }
+
+  #[test]
+  pub fn test_reflect() {
+    let interface = ShaderInterface::reflect(&module(0, 4, 2)).unwrap();
+    assert_eq!(interface.inputs.keys().collect::<Vec<_>>(), vec![&0]);
+    assert!(interface.outputs.is_empty());
+    assert_eq!(interface.descriptors.keys().collect::<Vec<_>>(), vec![&(0, 2)]);
+    assert_eq!(interface.push_constants, None);
+    assert!(ShaderInterface::reflect(&[1, 2, 3]).is_err());
+    // the last instruction is cut short
+    let words = module(0, 4, 2);
+    assert!(ShaderInterface::reflect(&words[..words.len() - 1]).is_err());
+  }
+
+  #[test]
+  pub fn test_matches() {
+    let built_in = ShaderInterface::reflect(&module(0, 4, 2)).unwrap();
+    assert_eq!(ShaderInterface::reflect(&module(0, 4, 2)).unwrap().matches(&built_in), Ok(()));
+    for (location, components, binding) in [(1, 4, 2), (0, 3, 2), (0, 4, 1)].iter() {
+      let reloaded = ShaderInterface::reflect(&module(*location, *components, *binding)).unwrap();
+      assert!(reloaded.matches(&built_in).is_err());
+    }
+  }
+}
@@ -1,34 +1,41 @@
 use vulkano::buffer::cpu_pool::CpuBufferPool;
-use vulkano::buffer::BufferUsage;
+use vulkano::buffer::{BufferAccess, BufferUsage};
+use vulkano::command_buffer::DynamicState;
 use vulkano::descriptor::descriptor_set::{DescriptorSet, PersistentDescriptorSet};
-use vulkano::device::Device;
 use vulkano::format::Format;
-use vulkano::render_pass::{Framebuffer, FramebufferAbstract, RenderPass, Subpass};
-use vulkano::image::{AttachmentImage, ImmutableImage, SwapchainImage};
+use vulkano::render_pass::{Framebuffer, FramebufferAbstract, Subpass};
+use vulkano::image::{AttachmentImage, ImageAccess, ImmutableImage};
 use vulkano::image::view::{ImageView, ImageViewType};
+use vulkano::pipeline::blend::{AttachmentBlend, BlendFactor};
 use vulkano::pipeline::depth_stencil::{Compare, DepthStencil};
-use vulkano::pipeline::vertex::TwoBuffersDefinition;
+use vulkano::pipeline::vertex::{OneVertexOneInstanceDefinition, SingleBufferDefinition};
 use vulkano::pipeline::viewport::Viewport;
-use vulkano::pipeline::{GraphicsPipeline, GraphicsPipelineAbstract};
+use vulkano::pipeline::{GraphicsPipeline, GraphicsPipelineAbstract, GraphicsPipelineCreationError};
 use vulkano::sampler::{Filter, MipmapMode, Sampler, SamplerAddressMode};
 use vulkano::sync::GpuFuture;
 use profiling;
 
-use cgmath::Point3;
-
-use winit::window::Window;
+use cgmath::{Matrix4, Point3};
 
 use std::convert::TryInto;
 use std::iter;
 use std::sync::Arc;
-
+use std::time::Instant;
+
+use crate::render::daylight::Daylight;
+use crate::render::debug_lines::DebugLines;
+use crate::render::fog::Fog;
+use crate::render::material::{GpuMaterial, Material};
+use crate::render::post::{PostProcess, HDR_FORMAT};
+use crate::render::render_mode::RenderMode;
 use crate::render::scene::MergedScene;
+use crate::render::shadow::{Cascades, CASCADES, SHADOW_SIZE};
 use crate::render::scene::Scene;
-use crate::render::skybox::SkyboxCubemap;
+use crate::render::skybox::{CubeFaces, SkyboxCubemap};
 use crate::render::textures::Textures;
 use crate::shaders;
-use crate::utils::{Normal, Vertex};
-use crate::Graph;
+use crate::utils::{Instance, LineVertex, MeshVertex};
+use crate::{Graph, Target};
 
 pub struct System {
   text_texture: Arc<ImmutableImage>,
@@ -36,19 +43,60 @@ pub struct System {
   skybox_cubemap: SkyboxCubemap,
   pub pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
   pub pipeline_skybox: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
-  pub framebuffers: Vec<Arc<dyn FramebufferAbstract + Send + Sync>>,
+  pub pipeline_water: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+  // same descriptor set layout as the main pipeline, so main_set works for both
+  pub pipeline_instanced: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+  // the main shaders blended without depth writes, same descriptor sets as the main pipeline
+  pub pipeline_clouds: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+  // the main vertex shader with the fragment shaders of the other render modes
+  pub mode_pipelines: ModePipelines,
+  // a line list of DebugLines, depth tested like the models
+  pub pipeline_debug: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+  // over the hdr buffer of post
+  pub framebuffer: Arc<dyn FramebufferAbstract + Send + Sync>,
+  // the viewport of framebuffer, every pipeline of the main render pass takes it
+  pub dynamic_state: DynamicState,
+  pub post: PostProcess,
+  // depth only, renders the sun's view into one shadow map per cascade
+  pub pipeline_shadow: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+  // the same for instanced models, the shadow sets work with both
+  pub pipeline_shadow_instanced: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+  pub shadow_framebuffers: Vec<Arc<dyn FramebufferAbstract + Send + Sync>>,
+  shadow_maps: Vec<Arc<AttachmentImage>>,
+  shadow_sampler: Arc<Sampler>,
+  // for models without a material of their own
+  default_material: Arc<GpuMaterial>,
+  material_sampler: Arc<Sampler>,
+  material_buffer: CpuBufferPool<shaders::main::fs::ty::Material>,
   uniform_buffer: CpuBufferPool<shaders::main::vs::ty::Data>,
   uniform_skybox_buffer: CpuBufferPool<shaders::skybox::vs::ty::Data>,
+  skybox_sky_buffer: CpuBufferPool<shaders::skybox::fs::ty::Sky>,
+  uniform_water_buffer: CpuBufferPool<shaders::water::vs::ty::Data>,
   environment_buffer: CpuBufferPool<shaders::main::fs::ty::Environment>,
   point_lights_buffer: CpuBufferPool<shaders::main::fs::ty::PointLights>,
   directional_lights_buffer: CpuBufferPool<shaders::main::fs::ty::DirectionalLights>,
   spot_lights_buffer: CpuBufferPool<shaders::main::fs::ty::SpotLights>,
+  instance_buffer: CpuBufferPool<Instance>,
+  line_buffer: CpuBufferPool<LineVertex>,
+  uniform_debug_buffer: CpuBufferPool<shaders::debug::vs::ty::Data>,
+  uniform_shadow_buffer: CpuBufferPool<shaders::shadow::vs::ty::Data>,
+  shadows_buffer: CpuBufferPool<shaders::main::fs::ty::Shadows>,
+  // the input of the skybox pass, alpha is how much of a pixel the scene covers
   color_buffer: Arc<AttachmentImage>,
+  // the sky shows where the depth of the scene pass is still at the far plane
   depth_buffer: Arc<AttachmentImage>,
+  // drives the water waves
+  started: Instant,
+  // replaces the time since started, see pin_animation_time
+  animation_time: Option<f32>,
 }
 
 impl System {
-  pub fn new(graph: &Graph, textures: Textures) -> (Self, Box<dyn GpuFuture>) {
+  pub fn new(
+    graph: &Graph,
+    textures: Textures,
+    skybox: &CubeFaces,
+  ) -> (Self, Box<dyn GpuFuture>) {
     let (text_texture, text_future) = textures.draw(&graph.queue);
     let text_sampler = Sampler::new(
       graph.device.clone(),
@@ -64,23 +112,62 @@ impl System {
       1.0,
     )
     .unwrap();
-    let (skybox_cubemap, tex_future) = SkyboxCubemap::new(&graph.queue);
-
-    let (pipeline, pipeline_skybox, framebuffers, color_buffer, depth_buffer) =
-      window_size_dependent_setup(
-        graph.device.clone(),
-        &graph.vs,
-        &graph.fs,
-        &graph.skybox_vs,
-        &graph.skybox_fs,
-        &graph.images,
-        graph.render_pass.clone(),
-      );
+    let (skybox_cubemap, tex_future) = SkyboxCubemap::new(&graph.queue, skybox);
+    let shadow_sampler = Sampler::new(
+      graph.device.clone(),
+      Filter::Nearest,
+      Filter::Nearest,
+      MipmapMode::Nearest,
+      SamplerAddressMode::ClampToEdge,
+      SamplerAddressMode::ClampToEdge,
+      SamplerAddressMode::ClampToEdge,
+      0.0,
+      1.0,
+      0.0,
+      1.0,
+    )
+    .unwrap();
+    let (pipeline_shadow, pipeline_shadow_instanced, shadow_maps, shadow_framebuffers) =
+      shadow_setup(graph);
+    let (default_material, material_future) =
+      GpuMaterial::upload(&Material::default(), &graph.queue);
+    let material_sampler = Sampler::new(
+      graph.device.clone(),
+      Filter::Linear,
+      Filter::Linear,
+      MipmapMode::Nearest,
+      SamplerAddressMode::Repeat,
+      SamplerAddressMode::Repeat,
+      SamplerAddressMode::Repeat,
+      0.0,
+      1.0,
+      0.0,
+      1.0,
+    )
+    .unwrap();
+
+    let post = post_setup(graph);
+    let (
+      pipeline,
+      pipeline_skybox,
+      pipeline_water,
+      pipeline_instanced,
+      pipeline_clouds,
+      mode_pipelines,
+      pipeline_debug,
+    ) = pipelines_setup(graph).unwrap();
+    let (framebuffer, color_buffer, depth_buffer) =
+      window_size_dependent_setup(graph, post.hdr_buffer.clone());
+    let dynamic_state = post.dynamic_state();
 
     let uniform_buffer =
       CpuBufferPool::<shaders::main::vs::ty::Data>::new(graph.device.clone(), BufferUsage::all());
     let uniform_skybox_buffer =
       CpuBufferPool::<shaders::skybox::vs::ty::Data>::new(graph.device.clone(), BufferUsage::all());
+    let skybox_sky_buffer =
+      CpuBufferPool::<shaders::skybox::fs::ty::Sky>::new(graph.device.clone(), BufferUsage::all());
+    let uniform_water_buffer =
+      CpuBufferPool::<shaders::water::vs::ty::Data>::new(graph.device.clone(), BufferUsage::all());
 
     let environment_buffer = CpuBufferPool::<shaders::main::fs::ty::Environment>::new(
       graph.device.clone(),
@@ -98,6 +185,18 @@ impl System {
       graph.device.clone(),
       BufferUsage::all(),
     );
+    let instance_buffer = CpuBufferPool::<Instance>::vertex_buffer(graph.device.clone());
+    let line_buffer = CpuBufferPool::<LineVertex>::vertex_buffer(graph.device.clone());
+    let uniform_debug_buffer =
+      CpuBufferPool::<shaders::debug::vs::ty::Data>::new(graph.device.clone(), BufferUsage::all());
+    let uniform_shadow_buffer =
+      CpuBufferPool::<shaders::shadow::vs::ty::Data>::new(graph.device.clone(), BufferUsage::all());
+    let shadows_buffer =
+      CpuBufferPool::<shaders::main::fs::ty::Shadows>::new(graph.device.clone(), BufferUsage::all());
+    let material_buffer = CpuBufferPool::<shaders::main::fs::ty::Material>::new(
+      graph.device.clone(),
+      BufferUsage::all(),
+    );
 
     (
       System {
@@ -106,17 +205,41 @@ impl System {
         skybox_cubemap,
         pipeline,
         pipeline_skybox,
-        framebuffers,
+        pipeline_water,
+        pipeline_instanced,
+        pipeline_clouds,
+        mode_pipelines,
+        pipeline_debug,
+        framebuffer,
+        dynamic_state,
+        post,
+        pipeline_shadow,
+        pipeline_shadow_instanced,
+        shadow_framebuffers,
+        shadow_maps,
+        shadow_sampler,
+        default_material,
+        material_sampler,
+        material_buffer,
         uniform_buffer,
         uniform_skybox_buffer,
+        skybox_sky_buffer,
+        uniform_water_buffer,
         environment_buffer,
         point_lights_buffer,
         directional_lights_buffer,
         spot_lights_buffer,
+        instance_buffer,
+        line_buffer,
+        uniform_debug_buffer,
+        uniform_shadow_buffer,
+        shadows_buffer,
         color_buffer,
         depth_buffer,
+        started: Instant::now(),
+        animation_time: None,
       },
-      text_future.join(tex_future).boxed(),
+      text_future.join(tex_future).join(material_future).boxed(),
     )
   }
 
@@ -126,12 +249,24 @@ impl System {
     proj: shaders::main::vs::ty::Data,
     scenes: Vec<&Scene>,
     camera_position: Point3<f32>,
+    cascades: &Cascades,
+    fog: &Fog,
+    daylight: &Daylight,
   ) -> Arc<dyn DescriptorSet + Sync + Send> {
     let uniform_buffer_subbuffer = {
       let uniform_data = proj;
       self.uniform_buffer.next(uniform_data).unwrap()
     };
 
+    // the fragment shader gets positions before the world transform of this set
+    let shadows_buffer_subbuffer = {
+      let mut shadows = shaders::main::fs::ty::Shadows::default();
+      for i in 0..CASCADES {
+        shadows.shadow_matrix[i] = cascades.light_matrix(i, Matrix4::from(proj.world)).into();
+      }
+      self.shadows_buffer.next(shadows).unwrap()
+    };
+
     let mut all_scene = MergedScene::default();
     for scene in scenes {
       all_scene
@@ -147,13 +282,17 @@ impl System {
 
     let environment_buffer_subbuffer = {
       let environment = shaders::main::fs::ty::Environment {
-        ambient_color: [0.0, 0.0, 0.0],
+        ambient_color: daylight.ambient_color,
         camera_position: camera_position.into(),
         point_light_count: all_scene.point_lights.len() as i32,
         directional_light_count: all_scene.directional_lights.len() as i32,
         spot_light_count: all_scene.spot_lights.len() as i32,
-        sun_color: [0.6, 0.6, 0.65],
-        sun_direction: [-0.577, -0.577, -0.577],
+        sun_color: daylight.sun_color,
+        sun_direction: daylight.sun_direction.into(),
+        fog_color: daylight.fog_color(fog.color),
+        fog_density: fog.density,
+        fog_height_falloff: fog.height_falloff,
+        fog_height: fog.height,
         ..Default::default()
       };
       self.environment_buffer.next(environment).unwrap()
@@ -197,6 +336,11 @@ impl System {
 
     let layout = self.pipeline.descriptor_set_layout(0).unwrap();
     let text_image = ImageView::new(self.text_texture.clone()).unwrap();
+    let shadow_views: Vec<_> = self
+      .shadow_maps
+      .iter()
+      .map(|map| ImageView::new(map.clone()).unwrap())
+      .collect();
 
     Arc::new(
       PersistentDescriptorSet::start(layout.clone())
@@ -212,21 +356,94 @@ impl System {
         .unwrap()
         .add_buffer(spot_lights_buffer_subbuffer)
         .unwrap()
+        .add_buffer(shadows_buffer_subbuffer)
+        .unwrap()
+        .add_sampled_image(shadow_views[0].clone(), self.shadow_sampler.clone())
+        .unwrap()
+        .add_sampled_image(shadow_views[1].clone(), self.shadow_sampler.clone())
+        .unwrap()
+        .add_sampled_image(shadow_views[2].clone(), self.shadow_sampler.clone())
+        .unwrap()
+        .build()
+        .unwrap(),
+    )
+  }
+
+  // set 1 of the main and instanced pipelines, None is the default material.
+  // materials do not change, so each keeps its set
+  #[profiling::function]
+  pub fn material_set(
+    &self,
+    material: Option<&GpuMaterial>,
+  ) -> Arc<dyn DescriptorSet + Sync + Send> {
+    let material = material.unwrap_or(&self.default_material);
+    let layout = self.pipeline.descriptor_set_layout(1).unwrap();
+    material.set(layout, || {
+      let material_buffer_subbuffer = self.material_buffer.next(material.uniform).unwrap();
+      let view = |image: &Arc<ImmutableImage>| ImageView::new(image.clone()).unwrap();
+      Arc::new(
+        PersistentDescriptorSet::start(layout.clone())
+          .add_buffer(material_buffer_subbuffer)
+          .unwrap()
+          .add_sampled_image(view(&material.base_color), self.material_sampler.clone())
+          .unwrap()
+          .add_sampled_image(view(&material.metallic_roughness), self.material_sampler.clone())
+          .unwrap()
+          .add_sampled_image(view(&material.normal), self.material_sampler.clone())
+          .unwrap()
+          .add_sampled_image(view(&material.emissive), self.material_sampler.clone())
+          .unwrap()
+          .build()
+          .unwrap(),
+      )
+    })
+  }
+
+  // light_world takes model coordinates to the light clip space of a cascade
+  #[profiling::function]
+  pub fn shadow_set(&self, light_world: Matrix4<f32>) -> Arc<dyn DescriptorSet + Sync + Send> {
+    let layout = self.pipeline_shadow.descriptor_set_layout(0).unwrap();
+    let uniform_buffer_subbuffer = {
+      let uniform_data = shaders::shadow::vs::ty::Data {
+        light_world: light_world.into(),
+      };
+      self.uniform_shadow_buffer.next(uniform_data).unwrap()
+    };
+    Arc::new(
+      PersistentDescriptorSet::start(layout.clone())
+        .add_buffer(uniform_buffer_subbuffer)
+        .unwrap()
         .build()
         .unwrap(),
     )
   }
 
   #[profiling::function]
+  // horizon_fog is the amount of fog at the far plane, see Fog::amount
   pub fn skybox_set(
     &self,
     proj: shaders::skybox::vs::ty::Data,
+    fog: &Fog,
+    horizon_fog: f32,
+    daylight: &Daylight,
+    clear_color: [f32; 3],
   ) -> Arc<dyn DescriptorSet + Sync + Send> {
     let layout = self.pipeline_skybox.descriptor_set_layout(0).unwrap();
     let uniform_buffer_subbuffer = {
       let uniform_data = proj;
       self.uniform_skybox_buffer.next(uniform_data).unwrap()
     };
+    let sky_buffer_subbuffer = {
+      let sky = shaders::skybox::fs::ty::Sky {
+        fog_color: daylight.fog_color(fog.color),
+        horizon_fog,
+        sun_direction: daylight.sun_direction.into(),
+        night: daylight.night,
+        clear_color,
+        ..Default::default()
+      };
+      self.skybox_sky_buffer.next(sky).unwrap()
+    };
 
     let color_buffer_view = ImageView::new(self.color_buffer.clone()).unwrap();
     let depth_buffer_view = ImageView::new(self.depth_buffer.clone()).unwrap();
@@ -247,131 +464,543 @@ impl System {
           self.skybox_cubemap.sampler.clone(),
         )
         .unwrap()
+        .add_buffer(sky_buffer_subbuffer)
+        .unwrap()
         .build()
         .unwrap(),
     )
   }
 
   #[profiling::function]
-  pub fn recreate_swapchain(&mut self, graph: &Graph) {
-    let (pipeline, pipeline_skybox, framebuffers, color_buffer, depth_buffer) =
-      window_size_dependent_setup(
-        graph.device.clone(),
-        &graph.vs,
-        &graph.fs,
-        &graph.skybox_vs,
-        &graph.skybox_fs,
-        &graph.images,
-        graph.render_pass.clone(),
-      );
+  pub fn water_set(
+    &self,
+    proj: shaders::main::vs::ty::Data,
+    daylight: &Daylight,
+  ) -> Arc<dyn DescriptorSet + Sync + Send> {
+    let layout = self.pipeline_water.descriptor_set_layout(0).unwrap();
+    let uniform_buffer_subbuffer = {
+      let uniform_data = shaders::water::vs::ty::Data {
+        world: proj.world,
+        view: proj.view,
+        proj: proj.proj,
+        camera_position: proj.camera_position,
+        time: self.animation_time.unwrap_or_else(|| self.started.elapsed().as_secs_f32()),
+        sun_direction: daylight.sun_direction.extend(0.0).into(),
+        sun_color: [
+          daylight.sun_color[0],
+          daylight.sun_color[1],
+          daylight.sun_color[2],
+          daylight.sky_brightness(),
+        ],
+      };
+      self.uniform_water_buffer.next(uniform_data).unwrap()
+    };
+    Arc::new(
+      PersistentDescriptorSet::start(layout.clone())
+        .add_buffer(uniform_buffer_subbuffer)
+        .unwrap()
+        .build()
+        .unwrap(),
+    )
+  }
 
-    self.pipeline = pipeline;
-    self.pipeline_skybox = pipeline_skybox;
-    self.framebuffers = framebuffers;
+  // frames recorded before keep the old cubemap alive, the upload has to be joined
+  // before the next frame
+  pub fn set_skybox(&mut self, graph: &Graph, skybox: &CubeFaces) -> Box<dyn GpuFuture> {
+    let (skybox_cubemap, future) = SkyboxCubemap::new(&graph.queue, skybox);
+    self.skybox_cubemap = skybox_cubemap;
+    future
+  }
 
+  // for the mode pipelines, they only take the projection of the main vertex shader
+  pub fn mode_set(
+    &self,
+    proj: shaders::main::vs::ty::Data,
+  ) -> Arc<dyn DescriptorSet + Sync + Send> {
+    let layout = self.mode_pipelines.flat.descriptor_set_layout(0).unwrap();
+    Arc::new(
+      PersistentDescriptorSet::start(layout.clone())
+        .add_buffer(self.uniform_buffer.next(proj).unwrap())
+        .unwrap()
+        .build()
+        .unwrap(),
+    )
+  }
+
+  // same projection as main_set
+  pub fn debug_set(
+    &self,
+    proj: shaders::main::vs::ty::Data,
+  ) -> Arc<dyn DescriptorSet + Sync + Send> {
+    let layout = self.pipeline_debug.descriptor_set_layout(0).unwrap();
+    let uniform_buffer_subbuffer = {
+      let uniform_data = shaders::debug::vs::ty::Data {
+        world: proj.world,
+        view: proj.view,
+        proj: proj.proj,
+      };
+      self.uniform_debug_buffer.next(uniform_data).unwrap()
+    };
+    Arc::new(
+      PersistentDescriptorSet::start(layout.clone())
+        .add_buffer(uniform_buffer_subbuffer)
+        .unwrap()
+        .build()
+        .unwrap(),
+    )
+  }
+
+  // the debug lines of this frame, None when there are none
+  pub fn lines(&self, lines: &DebugLines) -> Option<Arc<dyn BufferAccess + Send + Sync>> {
+    if lines.is_empty() {
+      return None;
+    }
+    Some(Arc::new(self.line_buffer.chunk(lines.vertices().iter().copied()).unwrap()))
+  }
+
+  // instances of this frame, for a single instanced draw
+  pub fn instances(&self, instances: Vec<Instance>) -> Arc<dyn BufferAccess + Send + Sync> {
+    Arc::new(self.instance_buffer.chunk(instances).unwrap())
+  }
+
+  // the water waves stand still at time, for renders that do not depend on the wall clock
+  pub fn pin_animation_time(&mut self, time: f32) {
+    self.animation_time = Some(time);
+  }
+
+  // after a resize, the pipelines stay as they are
+  #[profiling::function]
+  pub fn recreate_swapchain(&mut self, graph: &Graph) {
+    post_resize(&mut self.post, graph);
+    let (framebuffer, color_buffer, depth_buffer) =
+      window_size_dependent_setup(graph, self.post.hdr_buffer.clone());
+    self.framebuffer = framebuffer;
     self.color_buffer = color_buffer;
     self.depth_buffer = depth_buffer;
+    self.dynamic_state = self.post.dynamic_state();
+  }
+
+  // after the shaders changed, see Game::reload_shaders. the pipelines stay as they were when
+  // the new shaders do not build
+  #[profiling::function]
+  pub fn rebuild_pipelines(&mut self, graph: &Graph) -> Result<(), GraphicsPipelineCreationError> {
+    let (
+      pipeline,
+      pipeline_skybox,
+      pipeline_water,
+      pipeline_instanced,
+      pipeline_clouds,
+      mode_pipelines,
+      pipeline_debug,
+    ) = pipelines_setup(graph).map_err(|e| {
+      println!("failed to rebuild pipelines {}", e);
+      e
+    })?;
+
+    self.pipeline = pipeline;
+    self.pipeline_skybox = pipeline_skybox;
+    self.pipeline_water = pipeline_water;
+    self.pipeline_instanced = pipeline_instanced;
+    self.pipeline_clouds = pipeline_clouds;
+    self.mode_pipelines = mode_pipelines;
+    self.pipeline_debug = pipeline_debug;
+    Ok(())
   }
 }
 
-#[profiling::function]
-fn window_size_dependent_setup(
-  device: Arc<Device>,
-  vs: &shaders::main::vs::Shader,
-  fs: &shaders::main::fs::Shader,
-  skybox_vs: &shaders::skybox::vs::Shader,
-  skybox_fs: &shaders::skybox::fs::Shader,
-  images: &[Arc<SwapchainImage<Window>>],
-  render_pass: Arc<RenderPass>,
+// the shadow maps do not depend on the window size, they are only built once
+fn shadow_setup(
+  graph: &Graph,
 ) -> (
   Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
   Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+  Vec<Arc<AttachmentImage>>,
   Vec<Arc<dyn FramebufferAbstract + Send + Sync>>,
-  Arc<AttachmentImage>,
-  Arc<AttachmentImage>,
 ) {
-  let dimensions = images[0].dimensions();
-
-  let depth_buffer =
-    AttachmentImage::input_attachment(device.clone(), dimensions, Format::D16Unorm).unwrap();
-
-  let color_buffer =
-    AttachmentImage::input_attachment(device.clone(), dimensions, Format::B8G8R8A8Unorm).unwrap();
+  let render_pass = Arc::new(
+    vulkano::single_pass_renderpass!(
+      graph.device.clone(),
+      attachments: {
+        depth: {
+          load: Clear,
+          store: Store,
+          format: Format::D16Unorm,
+          samples: 1,
+        }
+      },
+      pass: {
+        color: [],
+        depth_stencil: {depth}
+      }
+    )
+    .unwrap(),
+  );
 
-  let depth_buffer2 =
-    AttachmentImage::transient(device.clone(), dimensions, Format::D16Unorm).unwrap();
+  let shadow_maps: Vec<_> = (0..CASCADES)
+    .map(|_| {
+      AttachmentImage::sampled(graph.device.clone(), [SHADOW_SIZE, SHADOW_SIZE], Format::D16Unorm)
+        .unwrap()
+    })
+    .collect();
 
-  let framebuffers = images
+  let framebuffers = shadow_maps
     .iter()
-    .map(|image| {
-      let view = ImageView::new(image.clone()).unwrap();
-      let depth_view = ImageView::new(depth_buffer.clone()).unwrap();
-      let depth2_view = ImageView::new(depth_buffer2.clone()).unwrap();
-      let color_view = ImageView::new(color_buffer.clone()).unwrap();
+    .map(|map| {
       Arc::new(
         Framebuffer::start(render_pass.clone())
-          .add(view)
-          .unwrap()
-          .add(depth_view)
-          .unwrap()
-          .add(color_view)
-          .unwrap()
-          .add(depth2_view)
+          .add(ImageView::new(map.clone()).unwrap())
           .unwrap()
           .build()
           .unwrap(),
       ) as Arc<dyn FramebufferAbstract + Send + Sync>
     })
-    .collect::<Vec<_>>();
+    .collect();
 
-  // In the triangle example we use a dynamic viewport, as its a simple example.
-  // However in the teapot example, we recreate the pipelines with a hardcoded viewport instead.
-  // This allows the driver to optimize things, at the cost of slower window resizes.
-  // https://computergraphics.stackexchange.com/questions/5742/vulkan-best-way-of-updating-pipeline-viewport
+  let viewport = Viewport {
+    origin: [0.0, 0.0],
+    dimensions: [SHADOW_SIZE as f32, SHADOW_SIZE as f32],
+    depth_range: 0.0..1.0,
+  };
   let pipeline = Arc::new(
     GraphicsPipeline::start()
-      .vertex_input(TwoBuffersDefinition::<Vertex, Normal>::new())
-      .vertex_shader(vs.main_entry_point(), ())
+      .vertex_input(SingleBufferDefinition::<MeshVertex>::new())
+      .vertex_shader(graph.shadow_vs.main_entry_point(), ())
       .triangle_list()
-      .viewports_dynamic_scissors_irrelevant(1)
-      .viewports(iter::once(Viewport {
-        origin: [0.0, 0.0],
-        dimensions: [dimensions[0] as f32, dimensions[1] as f32],
-        depth_range: 0.0..1.0,
-      }))
-      .fragment_shader(fs.main_entry_point(), ())
+      .viewports(iter::once(viewport.clone()))
+      .fragment_shader(graph.shadow_fs.main_entry_point(), ())
       .depth_stencil_simple_depth()
       .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
-      .build(device.clone())
+      .build_with_cache(graph.pipeline_cache.clone())
+      .build(graph.device.clone())
+      .unwrap(),
+  );
+  let pipeline_instanced = Arc::new(
+    GraphicsPipeline::start()
+      .vertex_input(OneVertexOneInstanceDefinition::<MeshVertex, Instance>::new())
+      .vertex_shader(graph.shadow_instanced_vs.main_entry_point(), ())
+      .triangle_list()
+      .viewports(iter::once(viewport))
+      .fragment_shader(graph.shadow_fs.main_entry_point(), ())
+      .depth_stencil_simple_depth()
+      .render_pass(Subpass::from(render_pass, 0).unwrap())
+      .build_with_cache(graph.pipeline_cache.clone())
+      .build(graph.device.clone())
       .unwrap(),
   );
 
+  (pipeline, pipeline_instanced, shadow_maps, framebuffers)
+}
+
+// alpha blending that keeps in the alpha of the color attachment how much of a pixel
+// transparent geometry covers, the skybox pass shows the sky through the rest of it
+pub fn coverage_blending() -> AttachmentBlend {
+  AttachmentBlend {
+    alpha_source: BlendFactor::One,
+    ..AttachmentBlend::alpha_blending()
+  }
+}
+
+type Pipelines = (
+  Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+  Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+  Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+  Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+  Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+  ModePipelines,
+  Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+);
+
+pub struct ModePipelines {
+  // depth tested against the solid models without writing depth. the wireframes are None
+  // without the fill_mode_non_solid device feature
+  pub wireframe_overlay: Option<Arc<dyn GraphicsPipelineAbstract + Send + Sync>>,
+  pub wireframe: Option<Arc<dyn GraphicsPipelineAbstract + Send + Sync>>,
+  pub flat: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+  pub normals: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+  pub uv_checker: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+}
+
+impl ModePipelines {
+  pub fn has_wireframe(&self) -> bool {
+    self.wireframe.is_some()
+  }
+
+  // what models are drawn with instead of the main pipeline, None when they are drawn solid
+  pub fn replacing(
+    &self,
+    mode: RenderMode,
+  ) -> Option<Arc<dyn GraphicsPipelineAbstract + Send + Sync>> {
+    match mode {
+      RenderMode::Solid | RenderMode::WireframeOverlay => None,
+      // flat shading shows the triangles the best without lines
+      RenderMode::Wireframe => Some(self.wireframe.clone().unwrap_or_else(|| self.flat.clone())),
+      RenderMode::Flat => Some(self.flat.clone()),
+      RenderMode::Normals => Some(self.normals.clone()),
+      RenderMode::UvChecker => Some(self.uv_checker.clone()),
+    }
+  }
+
+  // drawn again over the solid models
+  pub fn overlay(
+    &self,
+    mode: RenderMode,
+  ) -> Option<Arc<dyn GraphicsPipelineAbstract + Send + Sync>> {
+    match mode {
+      RenderMode::WireframeOverlay => self.wireframe_overlay.clone(),
+      _ => None,
+    }
+  }
+}
+
+// the post passes write into the swapchain images or into the single offscreen image
+fn post_setup(graph: &Graph) -> PostProcess {
+  match &graph.target {
+    Target::Window(window) => PostProcess::new(graph, &window.images),
+    Target::Offscreen(image) => PostProcess::new(graph, &[image.clone()]),
+  }
+}
+
+fn post_resize(post: &mut PostProcess, graph: &Graph) {
+  match &graph.target {
+    Target::Window(window) => post.resize(graph, &window.images),
+    Target::Offscreen(image) => post.resize(graph, &[image.clone()]),
+  }
+}
+
+// the scene is rendered into hdr_buffer, post processing takes it from there
+#[profiling::function]
+fn window_size_dependent_setup(
+  graph: &Graph,
+  hdr_buffer: Arc<AttachmentImage>,
+) -> (Arc<dyn FramebufferAbstract + Send + Sync>, Arc<AttachmentImage>, Arc<AttachmentImage>) {
+  let device = graph.device.clone();
+  let render_pass = graph.render_pass.clone();
+  let dimensions = ImageAccess::dimensions(&*hdr_buffer).width_height();
+
+  let samples = graph.samples;
+  let depth_buffer = if samples == 1 {
+    AttachmentImage::transient_input_attachment(device.clone(), dimensions, Format::D16Unorm)
+  } else {
+    AttachmentImage::transient_multisampled_input_attachment(
+      device.clone(),
+      dimensions,
+      samples,
+      Format::D16Unorm,
+    )
+  }
+  .unwrap();
+  let depth_buffer2 =
+    AttachmentImage::transient(device.clone(), dimensions, Format::D16Unorm).unwrap();
+  let color_buffer =
+    AttachmentImage::transient_input_attachment(device.clone(), dimensions, HDR_FORMAT).unwrap();
+
+  // the attachments in the order of Graph::main_render_pass
+  let framebuffer = if samples == 1 {
+    Arc::new(
+      Framebuffer::start(render_pass.clone())
+        .add(ImageView::new(hdr_buffer).unwrap())
+        .unwrap()
+        .add(ImageView::new(depth_buffer.clone()).unwrap())
+        .unwrap()
+        .add(ImageView::new(color_buffer.clone()).unwrap())
+        .unwrap()
+        .add(ImageView::new(depth_buffer2).unwrap())
+        .unwrap()
+        .build()
+        .unwrap(),
+    ) as Arc<dyn FramebufferAbstract + Send + Sync>
+  } else {
+    let multisampled_color =
+      AttachmentImage::transient_multisampled(device.clone(), dimensions, samples, HDR_FORMAT)
+        .unwrap();
+    Arc::new(
+      Framebuffer::start(render_pass.clone())
+        .add(ImageView::new(hdr_buffer).unwrap())
+        .unwrap()
+        .add(ImageView::new(depth_buffer.clone()).unwrap())
+        .unwrap()
+        .add(ImageView::new(multisampled_color).unwrap())
+        .unwrap()
+        .add(ImageView::new(color_buffer.clone()).unwrap())
+        .unwrap()
+        .add(ImageView::new(depth_buffer2).unwrap())
+        .unwrap()
+        .build()
+        .unwrap(),
+    ) as Arc<dyn FramebufferAbstract + Send + Sync>
+  };
+
+  (framebuffer, color_buffer, depth_buffer)
+}
+
+// the viewports are dynamic, a resize keeps the pipelines, only the shaders rebuild them.
+// https://computergraphics.stackexchange.com/questions/5742/vulkan-best-way-of-updating-pipeline-viewport
+#[profiling::function]
+fn pipelines_setup(graph: &Graph) -> Result<Pipelines, GraphicsPipelineCreationError> {
+  let device = graph.device.clone();
+  let render_pass = graph.render_pass.clone();
+  let cache = graph.pipeline_cache.clone();
+  let reloadable = &graph.shaders;
+  let (water_vs, water_fs) = (&graph.water_vs, &graph.water_fs);
+  let instanced_vs = &graph.instanced_vs;
+
+  // the main fragment shader writes an alpha of 1 unless it is blended
+  let opaque = shaders::main::fs::SpecializationConstants { blended: 0 };
+  let blended = shaders::main::fs::SpecializationConstants { blended: 1 };
+
+  let pipeline = Arc::new(
+    GraphicsPipeline::start()
+      .vertex_input(SingleBufferDefinition::<MeshVertex>::new())
+      .vertex_shader(reloadable.main_vs(), ())
+      .triangle_list()
+      .viewports_dynamic_scissors_irrelevant(1)
+      .fragment_shader(reloadable.main_fs(), opaque)
+      .depth_stencil_simple_depth()
+      .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
+      .build_with_cache(cache.clone())
+      .build(device.clone())?,
+  );
+
   let mut depth_stencil = DepthStencil::simple_depth_test();
   depth_stencil.depth_compare = Compare::LessOrEqual;
 
-  let pipeline_skybox = Arc::new(
+  let skybox = GraphicsPipeline::start()
+    .vertex_input(SingleBufferDefinition::<MeshVertex>::new())
+    .vertex_shader(reloadable.skybox_vs(), ())
+    .triangle_list()
+    .viewports_dynamic_scissors_irrelevant(1)
+    .depth_stencil(depth_stencil)
+    .render_pass(Subpass::from(render_pass.clone(), 1).unwrap());
+  // a multisampled depth attachment is read with another shader
+  let pipeline_skybox: Arc<dyn GraphicsPipelineAbstract + Send + Sync> = if graph.samples == 1 {
+    Arc::new(
+      skybox
+        .fragment_shader(reloadable.skybox_fs(), ())
+        .build_with_cache(cache.clone())
+        .build(device.clone())?,
+    )
+  } else {
+    let samples = shaders::skybox::fs_msaa::SpecializationConstants {
+      samples: graph.samples as i32,
+    };
+    Arc::new(
+      skybox
+        .fragment_shader(reloadable.skybox_fs_msaa(), samples)
+        .build_with_cache(cache.clone())
+        .build(device.clone())?,
+    )
+  };
+
+  // water is blended over the terrain, it is depth tested but does not write depth
+  let mut water_depth_stencil = DepthStencil::simple_depth_test();
+  water_depth_stencil.depth_write = false;
+
+  let pipeline_water = Arc::new(
+    GraphicsPipeline::start()
+      .vertex_input(SingleBufferDefinition::<MeshVertex>::new())
+      .vertex_shader(water_vs.main_entry_point(), ())
+      .triangle_list()
+      .viewports_dynamic_scissors_irrelevant(1)
+      .fragment_shader(water_fs.main_entry_point(), ())
+      .depth_stencil(water_depth_stencil)
+      .blend_collective(coverage_blending())
+      .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
+      .build_with_cache(cache.clone())
+      .build(device.clone())?,
+  );
+
+  let pipeline_instanced = Arc::new(
+    GraphicsPipeline::start()
+      .vertex_input(OneVertexOneInstanceDefinition::<MeshVertex, Instance>::new())
+      .vertex_shader(instanced_vs.main_entry_point(), ())
+      .triangle_list()
+      .viewports_dynamic_scissors_irrelevant(1)
+      .fragment_shader(reloadable.main_fs(), opaque)
+      .depth_stencil_simple_depth()
+      .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
+      .build_with_cache(cache.clone())
+      .build(device.clone())?,
+  );
+
+  // clouds are sorted back to front and blended like the water, see Sky::get_current_clouds
+  let mut clouds_depth_stencil = DepthStencil::simple_depth_test();
+  clouds_depth_stencil.depth_write = false;
+
+  let pipeline_clouds = Arc::new(
     GraphicsPipeline::start()
-      .vertex_input(TwoBuffersDefinition::<Vertex, Normal>::new())
-      .vertex_shader(skybox_vs.main_entry_point(), ())
+      .vertex_input(SingleBufferDefinition::<MeshVertex>::new())
+      .vertex_shader(reloadable.main_vs(), ())
+      .triangle_list()
+      .viewports_dynamic_scissors_irrelevant(1)
+      .fragment_shader(reloadable.main_fs(), blended)
+      .depth_stencil(clouds_depth_stencil)
+      .blend_collective(coverage_blending())
+      .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
+      .build_with_cache(cache.clone())
+      .build(device.clone())?,
+  );
+
+  let mode_pipeline = |mode: i32, wireframe: bool, depth_stencil: DepthStencil| {
+    let builder = GraphicsPipeline::start()
+      .vertex_input(SingleBufferDefinition::<MeshVertex>::new())
+      .vertex_shader(reloadable.main_vs(), ())
       .triangle_list()
       .viewports_dynamic_scissors_irrelevant(1)
-      .viewports(iter::once(Viewport {
-        origin: [0.0, 0.0],
-        dimensions: [dimensions[0] as f32, dimensions[1] as f32],
-        depth_range: 0.0..1.0,
-      }))
-      .fragment_shader(skybox_fs.main_entry_point(), ())
+      .fragment_shader(
+        graph.modes_fs.main_entry_point(),
+        shaders::modes::fs::SpecializationConstants { mode },
+      )
       .depth_stencil(depth_stencil)
-      .render_pass(Subpass::from(render_pass.clone(), 1).unwrap())
-      .build(device)
-      .unwrap(),
+      .render_pass(Subpass::from(render_pass.clone(), 0).unwrap());
+    let builder = if wireframe {
+      builder.polygon_mode_line()
+    } else {
+      builder
+    };
+    Ok::<_, GraphicsPipelineCreationError>(Arc::new(
+      builder
+        .build_with_cache(cache.clone())
+        .build(device.clone())?,
+    )
+      as Arc<dyn GraphicsPipelineAbstract + Send + Sync>)
+  };
+  // lines over the triangles they are the edges of pass a less or equal test
+  let mut overlay_depth_stencil = DepthStencil::simple_depth_test();
+  overlay_depth_stencil.depth_compare = Compare::LessOrEqual;
+  overlay_depth_stencil.depth_write = false;
+  let wireframe = device.enabled_features().fill_mode_non_solid;
+  let mode_pipelines = ModePipelines {
+    wireframe_overlay: wireframe
+      .then(|| mode_pipeline(0, true, overlay_depth_stencil))
+      .transpose()?,
+    wireframe: wireframe
+      .then(|| mode_pipeline(0, true, DepthStencil::simple_depth_test()))
+      .transpose()?,
+    flat: mode_pipeline(1, false, DepthStencil::simple_depth_test())?,
+    normals: mode_pipeline(2, false, DepthStencil::simple_depth_test())?,
+    uv_checker: mode_pipeline(3, false, DepthStencil::simple_depth_test())?,
+  };
+
+  let pipeline_debug = Arc::new(
+    GraphicsPipeline::start()
+      .vertex_input(SingleBufferDefinition::<LineVertex>::new())
+      .vertex_shader(graph.debug_vs.main_entry_point(), ())
+      .line_list()
+      .viewports_dynamic_scissors_irrelevant(1)
+      .fragment_shader(graph.debug_fs.main_entry_point(), ())
+      .depth_stencil_simple_depth()
+      .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
+      .build_with_cache(cache)
+      .build(device)?,
   );
 
-  (
+  Ok((
     pipeline,
     pipeline_skybox,
-    framebuffers,
-    color_buffer,
-    depth_buffer,
-  )
+    pipeline_water,
+    pipeline_instanced,
+    pipeline_clouds,
+    mode_pipelines,
+    pipeline_debug,
+  ))
 }
@@ -1,3 +1,7 @@
+use std::path::{Path, PathBuf};
+
+use crate::render::{DebugView, Fog, PostSettings, SkyboxSource};
+
 #[derive(Debug, Clone)]
 pub struct Settings {
   pub sky_enabled: bool,
@@ -6,4 +10,137 @@ pub struct Settings {
   pub letters_enabled: bool,
   pub dog_enabled: bool,
   pub lap_enabled: bool,
+  pub terrain_cache_dir: Option<PathBuf>,
+  // sky tiles are drawn as transparent clouds with solid ground generated under them
+  pub clouds_enabled: bool,
+  // valleys of the solid terrain below it are flooded, in the units of the terrain noise
+  pub sea_level: f32,
+  // physical device index or name, the GGGA_DEVICE environment variable overrides it
+  pub device: Option<String>,
+  // screenshots and frame sequences are written here
+  pub capture_dir: PathBuf,
+  // frames in a sequence started with F11
+  pub capture_frames: u32,
+  pub fog: Fog,
+  // hours since midnight at startup
+  pub start_hour: f32,
+  // real seconds a whole day takes
+  pub day_length: f32,
+  // the first is shown at startup, F9 switches to the next one
+  pub skyboxes: Vec<SkyboxSource>,
+  // the background where there is neither geometry nor sky, any color works as the sky
+  // is drawn where the depth is still at the far plane
+  pub clear_color: [f32; 3],
+  // bloom, exposure and tonemapping of the hdr scene
+  pub post: PostSettings,
+  // 1, 2, 4 or 8, lowered to what the device supports
+  pub msaa_samples: u32,
+  // the debug lines shown at startup, F5 to F8 toggle them
+  pub debug: DebugView,
+  // recompiles the main and skybox shaders when their glsl files change, needs the
+  // shader-hot-reload feature
+  pub shader_hot_reload: bool,
+  // the vulkan pipeline cache is loaded from and saved to it, None builds every pipeline from
+  // scratch
+  pub pipeline_cache: Option<PathBuf>,
+}
+
+impl Settings {
+  // the options of every run, `--skybox <path>` shows a directory of faces or a panorama
+  // first and can be repeated, `--msaa <samples>` replaces msaa_samples. the other arguments
+  // are returned for HeadlessArgs
+  pub fn apply_args(&mut self, args: &[String]) -> Result<Vec<String>, String> {
+    let mut rest = vec![];
+    let mut skyboxes = vec![];
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+      match arg.as_str() {
+        "--skybox" => {
+          let path = iter.next().ok_or_else(|| format!("{} needs a value", arg))?;
+          skyboxes.push(SkyboxSource::from_path(Path::new(path)));
+        }
+        "--msaa" => {
+          let samples = iter.next().ok_or_else(|| format!("{} needs a value", arg))?;
+          self.msaa_samples = samples.parse().map_err(|_| format!("bad msaa {}", samples))?;
+        }
+        _ => rest.push(arg.clone()),
+      }
+    }
+    self.skyboxes.splice(0..0, skyboxes);
+    Ok(rest)
+  }
+}
+
+#[cfg(test)]
+pub mod test {
+  use crate::render::{DebugView, Fog, PostSettings, SkyboxSource};
+  use crate::Settings;
+  use std::path::PathBuf;
+
+  // the command line of the ggga binary
+  pub fn args(s: &str) -> Vec<String> {
+    std::iter::once("ggga")
+      .chain(s.split_whitespace())
+      .map(String::from)
+      .collect()
+  }
+
+  // what the headless renders and the argument tests start from
+  pub fn settings() -> Settings {
+    Settings {
+      sky_enabled: true,
+      box_enabled: true,
+      dog_enabled: false,
+      letters_enabled: false,
+      triangle_enabled: true,
+      lap_enabled: false,
+      terrain_cache_dir: None,
+      clouds_enabled: false,
+      sea_level: 0.01,
+      device: None,
+      capture_dir: std::env::temp_dir(),
+      capture_frames: 1,
+      fog: Fog::default(),
+      start_hour: 10.0,
+      day_length: 600.0,
+      skyboxes: vec![],
+      // not black, so an image that was never written to shows up as zeros
+      clear_color: [0.2, 0.3, 0.4],
+      post: PostSettings::default(),
+      msaa_samples: 1,
+      debug: DebugView::default(),
+      shader_hot_reload: false,
+      pipeline_cache: None,
+    }
+  }
+
+  #[test]
+  pub fn test_skybox_args() {
+    let mut settings = settings();
+    settings.skyboxes = vec![SkyboxSource::Faces(PathBuf::from("assets/interstellar_skybox"))];
+    let dir = std::env::temp_dir();
+    let rest = settings
+      .apply_args(&args(&format!("--skybox sky.hdr --headless a.png --skybox {}", dir.display())))
+      .unwrap();
+    assert_eq!(rest, args("--headless a.png"));
+    assert_eq!(
+      settings.skyboxes,
+      vec![
+        SkyboxSource::Equirectangular(PathBuf::from("sky.hdr")),
+        SkyboxSource::Faces(dir),
+        SkyboxSource::Faces(PathBuf::from("assets/interstellar_skybox")),
+      ]
+    );
+    assert!(settings.apply_args(&args("--skybox")).is_err());
+  }
+
+  #[test]
+  pub fn test_msaa_args() {
+    let mut settings = settings();
+    assert_eq!(settings.apply_args(&args("--msaa 8")), Ok(args("")));
+    assert_eq!(settings.msaa_samples, 8);
+    assert!(settings.apply_args(&args("--msaa four")).is_err());
+    assert!(settings.apply_args(&args("--msaa")).is_err());
+    assert_eq!(settings.msaa_samples, 8);
+  }
 }
@@ -0,0 +1,43 @@
+// colored lines of render::DebugLines, relative to the world origin like the main pipeline
+pub mod vs {
+  vulkano_shaders::shader! {
+      ty: "vertex",
+      src: "
+#version 450
+
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec4 color;
+
+layout(set = 0, binding = 0) uniform Data {
+    mat4 world;
+    mat4 view;
+    mat4 proj;
+} uniforms;
+
+layout(location = 0) out vec4 v_color;
+
+void main() {
+    v_color = color;
+    gl_Position = uniforms.proj * uniforms.view * uniforms.world * vec4(position, 1.0);
+}
+        "
+  }
+}
+
+pub mod fs {
+  vulkano_shaders::shader! {
+      ty: "fragment",
+      src: "
+#version 450
+
+layout(location = 0) in vec4 v_color;
+
+layout(location = 0) out vec4 f_color;
+
+void main() {
+    // the lines cover their pixels, the skybox pass reads the alpha as coverage
+    f_color = vec4(v_color.rgb, 1.0);
+}
+       "
+  }
+}
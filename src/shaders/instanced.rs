@@ -0,0 +1,54 @@
+// the main vertex shader with a transform and tex rect per instance, it is paired
+// with the main fragment shader
+pub mod vs {
+  vulkano_shaders::shader! {
+      ty: "vertex",
+      src: "
+#version 450
+
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec2 tex;
+layout(location = 2) in vec2 tex_offset;
+layout(location = 3) in vec3 normal;
+layout(location = 4) in vec4 color;
+
+layout(location = 5) in vec4 instance_x;
+layout(location = 6) in vec4 instance_y;
+layout(location = 7) in vec4 instance_z;
+layout(location = 8) in vec4 instance_w;
+layout(location = 9) in vec4 instance_tex_rect;
+
+layout(location = 0) out vec3 v_normal;
+layout(location = 1) out vec2 v_tex;
+layout(location = 2) out vec2 v_tex_offset;
+layout(location = 3) out vec3 v_position;
+layout(location = 4) out vec4 v_color;
+
+layout(set = 0, binding = 0) uniform Data {
+    mat4 world;
+    mat4 view;
+    mat4 proj;
+    vec3 camera_position;
+} uniforms;
+
+void main() {
+    mat4 instance = mat4(instance_x, instance_y, instance_z, instance_w);
+    vec4 position_instance = instance * vec4(position, 1.0);
+    mat4 worldview = uniforms.view * uniforms.world;
+    // lit in the same space as v_position, like the main vertex shader
+    v_normal = transpose(inverse(mat3(instance))) * normal;
+    // negative tex coordinates mean untextured, those are kept
+    if (instance_tex_rect.x < 0 || tex.x < 0 || tex.y < 0) {
+        v_tex = tex;
+    } else {
+        v_tex = mix(instance_tex_rect.xy, instance_tex_rect.zw, tex);
+    }
+    v_tex_offset = tex_offset;
+    v_position = position_instance.xyz;
+    v_color = color;
+    gl_Position = uniforms.proj * worldview * position_instance;
+}
+
+        "
+  }
+}
@@ -0,0 +1,247 @@
+#version 450
+
+layout(location = 0) in vec3 v_normal;
+layout(location = 1) in vec2 v_tex2;
+layout(location = 2) in vec2 v_tex_offset2;
+layout(location = 3) in vec3 v_position2;
+layout(location = 4) in vec4 v_color;
+
+layout(location = 0) out vec4 f_color;
+
+// opaque pipelines write an alpha of 1, the skybox pass reads it as coverage
+layout(constant_id = 0) const bool blended = false;
+
+const float PI = 3.14159265359;
+
+layout(set = 0, binding = 1) uniform sampler2D textureSrc;
+
+struct PointLight {
+    vec3 position;
+    vec3 color;
+    float intensity;
+};
+
+struct DirectionalLight {
+    vec3 color;
+    float intensity;
+    vec3 direction;
+};
+
+// angle is half the opening of the cone in radians, the outer smoothness part of it fades out
+struct SpotLight {
+    vec3 position;
+    vec3 color;
+    vec3 direction;
+    float angle;
+    float intensity;
+    float range;
+    float smoothness;
+};
+
+layout(std140, set = 0, binding = 2) uniform Environment {
+    vec3 ambient_color;
+    vec3 camera_position;
+    int point_light_count;
+    int directional_light_count;
+    int spot_light_count;
+    // https://github.com/jwagner/webglice/blob/master/shaders/sun.glsl
+    vec3 sun_color;
+    vec3 sun_direction;
+    // exponential height fog, see render::Fog
+    vec3 fog_color;
+    float fog_density;
+    float fog_height_falloff;
+    float fog_height;
+};
+
+layout(std140, set = 0, binding = 3) uniform PointLights {
+    PointLight plight[128];
+};
+
+layout(std140, set = 0, binding = 4) uniform DirectionalLights {
+    DirectionalLight dlight[16];
+};
+
+layout(std140, set = 0, binding = 5) uniform SpotLights {
+    SpotLight slight[128];
+};
+
+// light clip space of each sun shadow cascade, nearest first
+layout(std140, set = 0, binding = 6) uniform Shadows {
+    mat4 shadow_matrix[3];
+};
+
+layout(set = 0, binding = 7) uniform sampler2D shadow_map0;
+layout(set = 0, binding = 8) uniform sampler2D shadow_map1;
+layout(set = 0, binding = 9) uniform sampler2D shadow_map2;
+
+// gltf metallic roughness material, the factors multiply the maps
+layout(std140, set = 1, binding = 0) uniform Material {
+    vec4 base_color;
+    vec3 emissive;
+    float metallic;
+    float roughness;
+    // non negative tex coordinates sample textureSrc unlit
+    int text_atlas;
+    int has_normal_map;
+};
+
+layout(set = 1, binding = 1) uniform sampler2D base_color_map;
+// roughness in green, metallic in blue
+layout(set = 1, binding = 2) uniform sampler2D metallic_roughness_map;
+layout(set = 1, binding = 3) uniform sampler2D normal_map;
+layout(set = 1, binding = 4) uniform sampler2D emissive_map;
+
+const float SHADOW_SIZE = 2048.0;
+const float SHADOW_BIAS = 0.002;
+
+float shadow_depth(int cascade, vec2 uv) {
+  if (cascade == 0) {
+    return texture(shadow_map0, uv).r;
+  } else if (cascade == 1) {
+    return texture(shadow_map1, uv).r;
+  }
+  return texture(shadow_map2, uv).r;
+}
+
+// 1.0 when fully lit by the sun, the first cascade containing the position is used
+// and filtered with 3x3 pcf
+float sun_visibility(vec3 position) {
+  for (int i = 0; i < 3; i++) {
+    vec4 light = shadow_matrix[i] * vec4(position, 1.0);
+    vec3 p = light.xyz / light.w;
+    if (abs(p.x) < 1.0 && abs(p.y) < 1.0 && p.z >= 0.0 && p.z <= 1.0) {
+      vec2 uv = p.xy * 0.5 + 0.5;
+      float lit = 0.0;
+      for (int x = -1; x <= 1; x++) {
+        for (int y = -1; y <= 1; y++) {
+          float depth = shadow_depth(i, uv + vec2(x, y) / SHADOW_SIZE);
+          lit += p.z - SHADOW_BIAS > depth ? 0.0 : 1.0;
+        }
+      }
+      return lit / 9.0;
+    }
+  }
+  return 1.0;
+}
+
+// tangent frame from the screen space derivatives, no tangents needed in the vertices
+// http://www.thetenthplanet.de/archives/1180
+vec3 perturb_normal(vec3 n, vec3 position, vec2 uv) {
+  vec3 dp1 = dFdx(position);
+  vec3 dp2 = dFdy(position);
+  vec2 duv1 = dFdx(uv);
+  vec2 duv2 = dFdy(uv);
+  vec3 dp2perp = cross(dp2, n);
+  vec3 dp1perp = cross(n, dp1);
+  vec3 t = dp2perp * duv1.x + dp1perp * duv2.x;
+  vec3 b = dp2perp * duv1.y + dp1perp * duv2.y;
+  float invmax = inversesqrt(max(max(dot(t, t), dot(b, b)), 1e-12));
+  vec3 mapped = texture(normal_map, uv).xyz * 2.0 - 1.0;
+  return normalize(mat3(t * invmax, b * invmax, n) * mapped);
+}
+
+float distribution_ggx(float n_dot_h, float roughness) {
+  float a = roughness * roughness;
+  float a2 = a * a;
+  float d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+  return a2 / (PI * d * d);
+}
+
+float geometry_schlick_ggx(float n_dot_x, float roughness) {
+  float r = roughness + 1.0;
+  float k = r * r / 8.0;
+  return n_dot_x / (n_dot_x * (1.0 - k) + k);
+}
+
+vec3 fresnel_schlick(float cos_theta, vec3 f0) {
+  return f0 + (1.0 - f0) * pow(clamp(1.0 - cos_theta, 0.0, 1.0), 5.0);
+}
+
+// cook torrance, the light arrives from l with radiance
+vec3 brdf(vec3 n, vec3 v, vec3 l, vec3 radiance, vec3 albedo, float metallic, float roughness) {
+  float n_dot_l = max(dot(n, l), 0.0);
+  if (n_dot_l <= 0.0) {
+    return vec3(0.0);
+  }
+  vec3 h = normalize(v + l);
+  float n_dot_v = max(dot(n, v), 1e-4);
+  vec3 f0 = mix(vec3(0.04), albedo, metallic);
+  vec3 f = fresnel_schlick(max(dot(h, v), 0.0), f0);
+  float d = distribution_ggx(max(dot(n, h), 0.0), roughness);
+  float g = geometry_schlick_ggx(n_dot_v, roughness) * geometry_schlick_ggx(n_dot_l, roughness);
+  vec3 specular = d * g * f / (4.0 * n_dot_v * n_dot_l);
+  vec3 diffuse = (1.0 - f) * (1.0 - metallic) * albedo / PI;
+  return (diffuse + specular) * radiance * n_dot_l;
+}
+
+// the same as Fog::amount, y is down
+float fog_amount(vec3 camera, vec3 position) {
+  vec3 ray = position - camera;
+  float camera_density =
+    fog_density * exp(min(-fog_height_falloff * (-camera.y - fog_height), 80.0));
+  float rise = -ray.y * fog_height_falloff;
+  float integral = abs(rise) > 1e-4 ? (1.0 - exp(-rise)) / rise : 1.0;
+  return 1.0 - exp(-camera_density * integral * length(ray));
+}
+
+vec3 apply_fog(vec3 color) {
+  return mix(color, fog_color, fog_amount(camera_position, v_position2));
+}
+
+void main() {
+    if (text_atlas != 0 && v_tex2.x >= 0 && v_tex2.y >= 0) {
+      f_color = texture(textureSrc, v_tex2);
+      if (f_color.r < 0.1) {
+        discard;
+      }
+      f_color.rgb = apply_fog(f_color.rgb);
+      if (!blended) {
+        f_color.a = 1.0;
+      }
+      return;
+    }
+
+    vec4 albedo = base_color * texture(base_color_map, v_tex2) * v_color;
+    vec4 metallic_roughness = texture(metallic_roughness_map, v_tex2);
+    float m = clamp(metallic * metallic_roughness.b, 0.0, 1.0);
+    float r = clamp(roughness * metallic_roughness.g, 0.04, 1.0);
+    vec3 n = normalize(v_normal);
+    if (has_normal_map != 0) {
+      n = perturb_normal(n, v_position2, v_tex2);
+    }
+    vec3 v = normalize(camera_position - v_position2);
+
+    vec3 color = vec3(0.0);
+    for (int i = 0; i < point_light_count; i++) {
+      vec3 to_light = plight[i].position - v_position2;
+      float dist2 = dot(to_light, to_light);
+      vec3 radiance = plight[i].color * plight[i].intensity / dist2;
+      color += brdf(n, v, normalize(to_light), radiance, albedo.rgb, m, r);
+    }
+    for (int i = 0; i < directional_light_count; i++) {
+      vec3 radiance = dlight[i].color * dlight[i].intensity;
+      color += brdf(n, v, normalize(-dlight[i].direction), radiance, albedo.rgb, m, r);
+    }
+    for (int i = 0; i < spot_light_count; i++) {
+      vec3 to_light = slight[i].position - v_position2;
+      float dist = length(to_light);
+      vec3 l = to_light / dist;
+      float outer = cos(slight[i].angle);
+      float inner = cos(slight[i].angle * (1.0 - slight[i].smoothness));
+      float cos_theta = dot(-l, normalize(slight[i].direction));
+      float cone = smoothstep(outer, max(inner, outer + 1e-4), cos_theta);
+      // windowed inverse square, reaches zero at the range
+      float window = clamp(1.0 - pow(dist / slight[i].range, 4.0), 0.0, 1.0);
+      float falloff = window * window / max(dist * dist, 1e-4);
+      vec3 radiance = slight[i].color * slight[i].intensity * cone * falloff;
+      color += brdf(n, v, l, radiance, albedo.rgb, m, r);
+    }
+    // sun_color is relative to a white lambertian surface facing the sun
+    vec3 sun_radiance = sun_color * PI * sun_visibility(v_position2);
+    color += brdf(n, v, normalize(sun_direction), sun_radiance, albedo.rgb, m, r);
+
+    color += ambient_color * albedo.rgb;
+    color += emissive * texture(emissive_map, v_tex2).rgb;
+    f_color = vec4(apply_fog(color), blended ? albedo.a : 1.0);
+}
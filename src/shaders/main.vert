@@ -0,0 +1,37 @@
+#version 450
+
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec2 tex;
+layout(location = 2) in vec2 tex_offset;
+layout(location = 3) in vec3 normal;
+layout(location = 4) in vec4 color;
+
+layout(location = 0) out vec3 v_normal;
+layout(location = 1) out vec2 v_tex;
+layout(location = 2) out vec2 v_tex_offset;
+layout(location = 3) out vec3 v_position;
+layout(location = 4) out vec4 v_color;
+
+layout(set = 0, binding = 0) uniform Data {
+      mat4 world;
+    mat4 view;
+    mat4 proj;
+    vec3 camera_position;
+} uniforms;
+
+// where the model was moved since it was uploaded, see Model::translated
+layout(push_constant) uniform Offset {
+    vec3 offset;
+} model;
+
+void main() {
+    vec3 moved = position + model.offset;
+    mat4 worldview = uniforms.view * uniforms.world;
+    // lit in the same space as v_position, before the world transform
+    v_normal = normal;
+    v_tex = tex;
+    v_tex_offset = tex_offset;
+    v_position = moved;
+    v_color = color;
+    gl_Position = uniforms.proj * worldview * vec4(moved, 1.0);
+}
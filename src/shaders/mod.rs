@@ -1,2 +1,8 @@
+pub mod debug;
+pub mod instanced;
 pub mod main;
+pub mod modes;
+pub mod post;
+pub mod shadow;
 pub mod skybox;
+pub mod water;
@@ -0,0 +1,46 @@
+// the render modes besides solid, after the main vertex shader, see render::RenderMode
+pub mod fs {
+  vulkano_shaders::shader! {
+      ty: "fragment",
+      src: "
+#version 450
+
+layout(location = 0) in vec3 v_normal;
+layout(location = 1) in vec2 v_tex2;
+layout(location = 2) in vec2 v_tex_offset2;
+layout(location = 3) in vec3 v_position2;
+layout(location = 4) in vec4 v_color;
+
+layout(location = 0) out vec4 f_color;
+
+// 0 wireframe, 1 flat shading, 2 normals as colors, 3 uv checker
+layout(constant_id = 0) const int mode = 0;
+
+const vec3 WIRE_COLOR = vec3(0.1, 1.0, 0.3);
+// y is down, the light comes from above
+const vec3 LIGHT_DIRECTION = normalize(vec3(0.3, -1.0, 0.5));
+const float CHECKER_SIZE = 8.0;
+
+void main() {
+    vec3 color;
+    if (mode == 0) {
+      color = WIRE_COLOR;
+    } else if (mode == 1) {
+      // the normal of the triangle, not the interpolated one of the vertices
+      vec3 n = normalize(cross(dFdx(v_position2), dFdy(v_position2)));
+      float light = abs(dot(n, LIGHT_DIRECTION));
+      color = v_color.rgb * (0.2 + 0.8 * light);
+    } else if (mode == 2) {
+      color = normalize(v_normal) * 0.5 + 0.5;
+    } else {
+      vec2 cell = floor(v_tex2 * CHECKER_SIZE);
+      float checker = mod(cell.x + cell.y, 2.0);
+      // tinted by the position in the unit square, to see which way the uvs run
+      color = mix(vec3(0.15), vec3(0.9), checker) * (0.5 + 0.5 * vec3(fract(v_tex2), 1.0));
+    }
+    // covers the pixel, the skybox pass reads the alpha as coverage
+    f_color = vec4(color, 1.0);
+}
+       "
+  }
+}
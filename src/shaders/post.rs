@@ -0,0 +1,128 @@
+// fullscreen passes after the hdr scene, see render::post
+pub mod vs {
+  vulkano_shaders::shader! {
+      ty: "vertex",
+      src: "
+#version 450
+
+// a MeshVertex in clip space, only x and y are used
+layout(location = 0) in vec3 position;
+
+layout(location = 0) out vec2 v_uv;
+
+void main() {
+    v_uv = position.xy * 0.5 + 0.5;
+    gl_Position = vec4(position.xy, 0.0, 1.0);
+}
+
+        "
+  }
+}
+
+// keeps what is brighter than the threshold, at half the resolution of the scene
+pub mod bright_fs {
+  vulkano_shaders::shader! {
+      ty: "fragment",
+      src: "
+#version 450
+
+layout(location = 0) in vec2 v_uv;
+
+layout(location = 0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform sampler2D scene;
+
+layout(set = 0, binding = 1) uniform Bright {
+    float threshold;
+};
+
+void main() {
+    vec3 color = texture(scene, v_uv).rgb;
+    float brightness = max(max(color.r, color.g), color.b);
+    float contribution = max(brightness - threshold, 0.0) / max(brightness, 1e-4);
+    f_color = vec4(color * contribution, 1.0);
+}
+       "
+  }
+}
+
+// one direction of a separable gaussian blur
+pub mod blur_fs {
+  vulkano_shaders::shader! {
+      ty: "fragment",
+      src: "
+#version 450
+
+layout(location = 0) in vec2 v_uv;
+
+layout(location = 0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform sampler2D source;
+
+layout(set = 0, binding = 1) uniform Blur {
+    // one texel along the blurred axis
+    vec2 direction;
+};
+
+const float WEIGHTS[5] = float[](0.227027, 0.1945946, 0.1216216, 0.054054, 0.016216);
+
+void main() {
+    vec3 color = texture(source, v_uv).rgb * WEIGHTS[0];
+    for (int i = 1; i < 5; i++) {
+      color += texture(source, v_uv + direction * float(i)).rgb * WEIGHTS[i];
+      color += texture(source, v_uv - direction * float(i)).rgb * WEIGHTS[i];
+    }
+    f_color = vec4(color, 1.0);
+}
+       "
+  }
+}
+
+// bloom, exposure, tonemapping and gamma into the final image
+pub mod tonemap_fs {
+  vulkano_shaders::shader! {
+      ty: "fragment",
+      src: "
+#version 450
+
+layout(location = 0) in vec2 v_uv;
+
+layout(location = 0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform sampler2D scene;
+layout(set = 0, binding = 1) uniform sampler2D bloom;
+
+layout(set = 0, binding = 2) uniform Post {
+    float exposure;
+    // 0.0 when there is no bloom
+    float bloom_intensity;
+    // 0 clamps, 1 is reinhard and 2 aces, see render::post::Tonemap
+    int tonemap;
+    // 1.0 when the final image encodes srgb by itself
+    float gamma;
+};
+
+// https://knarkowicz.wordpress.com/2016/01/06/aces-filmic-tone-mapping-curve/
+vec3 aces(vec3 x) {
+    return clamp((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14), 0.0, 1.0);
+}
+
+void main() {
+    vec3 color = texture(scene, v_uv).rgb;
+    // the bloom image is cleared to black when there is no bloom
+    color += texture(bloom, v_uv).rgb * bloom_intensity;
+    color *= exposure;
+    if (tonemap == 1) {
+      color = color / (1.0 + color);
+    } else if (tonemap == 2) {
+      color = aces(color);
+    }
+    color = pow(clamp(color, 0.0, 1.0), vec3(1.0 / gamma));
+    f_color = vec4(color, 1.0);
+}
+       ",
+       types_meta: {
+        #[derive(Clone, Copy, PartialEq, Debug, Default)]
+    }
+  }
+}
@@ -0,0 +1,63 @@
+// depth only pass of the sun, one per shadow cascade
+pub mod vs {
+  vulkano_shaders::shader! {
+      ty: "vertex",
+      src: "
+#version 450
+
+layout(location = 0) in vec3 position;
+
+layout(set = 0, binding = 0) uniform Data {
+    mat4 light_world;
+} uniforms;
+
+layout(push_constant) uniform Offset {
+    vec3 offset;
+} model;
+
+void main() {
+    gl_Position = uniforms.light_world * vec4(position + model.offset, 1.0);
+}
+
+        "
+  }
+}
+
+// the same with a transform per instance, see the instanced module
+pub mod instanced_vs {
+  vulkano_shaders::shader! {
+      ty: "vertex",
+      src: "
+#version 450
+
+layout(location = 0) in vec3 position;
+
+layout(location = 1) in vec4 instance_x;
+layout(location = 2) in vec4 instance_y;
+layout(location = 3) in vec4 instance_z;
+layout(location = 4) in vec4 instance_w;
+
+layout(set = 0, binding = 0) uniform Data {
+    mat4 light_world;
+} uniforms;
+
+void main() {
+    mat4 instance = mat4(instance_x, instance_y, instance_z, instance_w);
+    gl_Position = uniforms.light_world * instance * vec4(position, 1.0);
+}
+
+        "
+  }
+}
+
+pub mod fs {
+  vulkano_shaders::shader! {
+      ty: "fragment",
+      src: "
+#version 450
+
+void main() {
+}
+       "
+  }
+}
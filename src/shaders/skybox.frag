@@ -0,0 +1,99 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) in vec3 v_position;
+layout(location = 1) in vec3 v_position2;
+layout(location = 2) in vec3 v_camera_position;
+
+// alpha is how much geometry covers the pixel, opaque geometry writes 1 and msaa resolves
+// the partly covered edges in between
+layout (input_attachment_index = 0, set = 0, binding = 1) uniform subpassInput inputColor;
+// the depth of the scene subpass, MSAA is defined for the shader of a multisampled scene
+#ifdef MSAA
+layout (input_attachment_index = 1, set = 0, binding = 2) uniform subpassInputMS inputDepth;
+#else
+layout (input_attachment_index = 1, set = 0, binding = 2) uniform subpassInput inputDepth;
+#endif
+layout (set = 0, binding = 3) uniform samplerCube cubemapSampler;
+
+layout(std140, set = 0, binding = 4) uniform Sky {
+    // the sky near the horizon fades into the fog the terrain fades into
+    vec3 fog_color;
+    // the fog of the terrain at the far plane
+    float horizon_fog;
+    // points at the sun, y is down
+    vec3 sun_direction;
+    // the cubemap shows through the atmosphere at night
+    float night;
+    // what the color attachment was cleared to, transparent geometry was blended over it
+    vec3 clear_color;
+};
+
+const float PI = 3.14159265359;
+// scattering per unit of optical depth, rayleigh is stronger for blue
+const vec3 BETA_RAYLEIGH = vec3(0.11, 0.26, 0.45);
+const float BETA_MIE = 0.08;
+const float SUN_INTENSITY = 2.0;
+
+// air mass relative to looking straight up, grows quickly towards the horizon
+float optical_depth(float cos_zenith) {
+  return 1.0 / (max(cos_zenith, 0.0) + 0.15);
+}
+
+float rayleigh_phase(float mu) {
+  return 3.0 / (16.0 * PI) * (1.0 + mu * mu);
+}
+
+// henyey greenstein
+float mie_phase(float mu, float g) {
+  float g2 = g * g;
+  return (1.0 - g2) / (4.0 * PI * pow(1.0 + g2 - 2.0 * g * mu, 1.5));
+}
+
+// single scattering of sunlight, attenuated on its way to the scattering air
+vec3 atmosphere(vec3 direction) {
+  float mu = dot(direction, sun_direction);
+  vec3 beta = BETA_RAYLEIGH + BETA_MIE;
+  vec3 sunlight = SUN_INTENSITY * exp(-beta * optical_depth(-sun_direction.y));
+  vec3 scattering = BETA_RAYLEIGH * rayleigh_phase(mu) + BETA_MIE * mie_phase(mu, 0.76);
+  vec3 view_extinction = exp(-beta * optical_depth(-direction.y));
+  vec3 inscattered = sunlight * scattering / beta * (1.0 - view_extinction);
+  float disk = smoothstep(0.9995, 0.9999, mu);
+  // no light once the sun is well below the horizon
+  float day = smoothstep(-0.15, 0.05, -sun_direction.y);
+  return (inscattered * 4.0 * PI + sunlight * disk) * day;
+}
+
+// the samples of the depth attachment
+layout(constant_id = 0) const int samples = 1;
+
+// only samples nothing opaque was drawn to are still at the far plane
+float opaque_coverage() {
+#ifdef MSAA
+  float covered = 0.0;
+  for (int i = 0; i < samples; i++) {
+    covered += float(subpassLoad(inputDepth, i).r < 1.0);
+  }
+  return covered / float(samples);
+#else
+  return float(subpassLoad(inputDepth).r < 1.0);
+#endif
+}
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+    vec4 color = subpassLoad(inputColor);
+    float coverage = max(color.a, opaque_coverage());
+    if (coverage >= 1.0) {
+      outColor = vec4(color.rgb, 1.0);
+      return;
+    }
+    vec3 direction = normalize(-v_position);
+    vec3 stars = texture(cubemapSampler, direction).rgb;
+    vec3 sky = atmosphere(normalize(v_position)) + stars * night;
+    float horizon = pow(1.0 - abs(direction.y), 8.0);
+    sky = mix(sky, fog_color, horizon * horizon_fog);
+    // transparent geometry is blended with render::coverage_blending
+    outColor = vec4(color.rgb + (sky - clear_color) * (1.0 - coverage), 1.0);
+}
@@ -0,0 +1,96 @@
+pub mod vs {
+  vulkano_shaders::shader! {
+      ty: "vertex",
+      src: "
+#version 450
+
+layout(location = 0) in vec3 position;
+
+layout(location = 0) out vec3 v_position;
+
+layout(set = 0, binding = 0) uniform Data {
+    mat4 world;
+    mat4 view;
+    mat4 proj;
+    vec3 camera_position;
+    float time;
+    // points at the sun, w is unused
+    vec4 sun_direction;
+    // a is how bright the sky is
+    vec4 sun_color;
+} uniforms;
+
+layout(push_constant) uniform Offset {
+    vec3 offset;
+} model;
+
+void main() {
+    vec4 world_position = uniforms.world * vec4(position + model.offset, 1.0);
+    v_position = world_position.xyz;
+    gl_Position = uniforms.proj * uniforms.view * world_position;
+}
+
+        "
+  }
+}
+
+pub mod fs {
+  vulkano_shaders::shader! {
+      ty: "fragment",
+      src: "
+#version 450
+
+layout(location = 0) in vec3 v_position;
+
+layout(location = 0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform Data {
+    mat4 world;
+    mat4 view;
+    mat4 proj;
+    vec3 camera_position;
+    float time;
+    // points at the sun, w is unused
+    vec4 sun_direction;
+    // a is how bright the sky is
+    vec4 sun_color;
+} uniforms;
+
+const vec3 DEEP_COLOR = vec3(0.05, 0.18, 0.30);
+const vec3 SKY_COLOR = vec3(0.55, 0.70, 0.85);
+
+// a few moving sine waves, returns the derivative of the height along x and z
+vec2 waves(vec2 p, float t) {
+    vec2 d = vec2(0.0);
+    vec3 w[3] = vec3[](
+        vec3(0.8, 0.6, 0.35),
+        vec3(-0.5, 0.9, 0.55),
+        vec3(0.2, -1.0, 0.9)
+    );
+    for (int i = 0; i < 3; i++) {
+        vec2 dir = normalize(w[i].xy);
+        float k = w[i].z;
+        float c = cos(dot(dir, p) * k + t * (1.0 + float(i) * 0.7));
+        d += dir * k * c * 0.08;
+    }
+    return d;
+}
+
+void main() {
+    vec2 d = waves(v_position.xz, uniforms.time);
+    // normals point down like the terrain normals do
+    vec3 normal = normalize(vec3(d.x, -1.0, d.y));
+    vec3 eye = normalize(uniforms.camera_position - v_position);
+    float facing = abs(dot(eye, normal));
+    float fresnel = 0.02 + 0.98 * pow(1.0 - facing, 5.0);
+
+    vec3 reflection = normalize(reflect(-uniforms.sun_direction.xyz, normal));
+    vec3 specular = pow(max(dot(eye, reflection), 0.0), 60.0) * uniforms.sun_color.rgb;
+
+    vec3 sky = SKY_COLOR * uniforms.sun_color.a;
+    vec3 color = mix(DEEP_COLOR * uniforms.sun_color.a, sky, fresnel) + specular;
+    f_color = vec4(color, mix(0.6, 0.95, fresnel));
+}
+       "
+  }
+}
@@ -1,31 +1,45 @@
-use cgmath::{Point2, Point3};
+use cgmath::{Matrix4, Point2, Point3, Vector3};
 use vulkano::device::Device;
 
 use std::sync::Arc;
 
-use crate::render::Model;
+use crate::render::{Instance, InstancedModel};
 use crate::things::PrimitiveTriangle;
 use crate::things::Texts;
 
+// sign posts share a single unit triangle, the position and the text texture rect
+// are per instance
 pub struct SignPost {
-  model: Model,
+  instance: Instance,
 }
 
 impl SignPost {
-  pub fn new(device: &Arc<Device>, pos: Point3<f32>, text: String, texts: &Texts) -> Self {
+  pub fn new(pos: Point3<f32>, text: String, texts: &Texts) -> Self {
     let info = texts.info(&text);
-    let texture_size = texts.size();
-    let mesh = PrimitiveTriangle::new_tex(
-      pos,
-      Point2::new(info.min.0 as f32, info.min.1 as f32),
-      Point2::new(info.max.0 as f32, info.max.1 as f32),
-      texture_size,
-    );
-    let model = mesh.mesh.get_buffers(device);
-    SignPost { model }
+    let (w, h) = texts.size();
+    let (w, h) = (w as f32, h as f32);
+    let instance = Instance::new(Matrix4::from_translation(Vector3::new(pos.x, pos.y, pos.z)))
+      .with_tex_rect([
+        info.min.0 as f32 / w,
+        info.min.1 as f32 / h,
+        info.max.0 as f32 / w,
+        info.max.1 as f32 / h,
+      ]);
+    SignPost { instance }
   }
 
-  pub fn get_model(&self) -> Model {
-    self.model.clone()
+  pub fn model(device: &Arc<Device>) -> InstancedModel {
+    PrimitiveTriangle::new_tex(
+      Point3::new(0.0, 0.0, 0.0),
+      Point2::new(0.0, 0.0),
+      Point2::new(1.0, 1.0),
+      (1, 1),
+    )
+    .mesh
+    .get_instanced_buffers(device)
+  }
+
+  pub fn get_instance(&self) -> Instance {
+    self.instance
   }
 }
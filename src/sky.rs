@@ -1,22 +1,27 @@
-use cgmath::{Point3, Vector2, Vector3, Matrix4, One};
-use vulkano::device::Device;
+use cgmath::{Point3, Transform, Vector2, Vector3, Matrix4};
+use vulkano::device::{Device, Queue};
+use vulkano::sync::GpuFuture;
 
 use futures::executor::block_on;
 use futures::future::RemoteHandle;
 use parking_lot::RwLock;
 use std::cmp::Ordering;
+use std::path::Path;
 use std::sync::Arc;
 use profiling;
 
 use crate::actor::Actor;
 use crate::executor::Executor;
-use crate::render::Model;
+use crate::render::{Instance, InstancedModel, Model, MyMesh};
 use crate::render::Scene;
+use crate::render::{DebugLines, MAGENTA};
 use crate::shaders::main::fs;
 use crate::settings::Settings;
 use crate::things::TerrainModel;
 use crate::things::LapMesh;
-use crate::things::terrain_execute;
+use crate::things::{terrain_from_grid, terrain_grid, TerrainCache, TerrainGrid, TerrainParams};
+use crate::things::water_from_grid;
+use crate::things::{back_to_front, clouds_from_terrain};
 
 impl Sky {
   const X: f32 = 100.0;
@@ -27,6 +32,8 @@ impl Sky {
   const MZ: usize = 5;
   const DETAIL: i32 = 90;
   const SCALE: f32 = 30.0;
+  // how far below the clouds the ground is generated in cloud mode, y is down
+  const GROUND_DEPTH: f32 = 40.0;
 }
 
 fn xindex(base: f32, step: isize) -> f32 {
@@ -67,14 +74,259 @@ fn crop(x: isize, bound: usize) -> Option<usize> {
   Some(x as usize)
 }
 
+// x, z are relative to the world origin, tiles are keyed and sampled in absolute coordinates
+fn tile_key(origin: Vector2<f64>, x: f32, z: f32) -> (isize, isize) {
+  let (ax, az) = (origin.x + x as f64, origin.y + z as f64);
+  (
+    (ax / Sky::X as f64).round() as isize,
+    (az / Sky::Z as f64).round() as isize,
+  )
+}
+
+// where the tiles of the cache are, the camera and the world origin they are relative to
+#[derive(Debug, Copy, Clone)]
+struct TileGrid {
+  x: Vector2<f32>,
+  z: Vector2<f32>,
+  // last seen camera position
+  c: Vector2<f32>,
+  // absolute x, z of the world origin all the above is relative to
+  origin: Vector2<f64>,
+}
+
+impl TileGrid {
+  fn new(x: f32, z: f32) -> Self {
+    TileGrid {
+      x: Vector2::new(x, x + Sky::X),
+      z: Vector2::new(z, z + Sky::Z),
+      c: Vector2::new(0.0, 0.0),
+      origin: Vector2::new(0.0, 0.0),
+    }
+  }
+
+  // the corner of the tile cell steps away from the central one
+  fn corner(&self, cell: (isize, isize)) -> (f32, f32) {
+    (xindex(self.x.x, cell.0), zindex(self.z.x, cell.1))
+  }
+
+  fn tile(&self, cell: (isize, isize)) -> (isize, isize) {
+    let (x, z) = self.corner(cell);
+    tile_key(self.origin, x, z)
+  }
+
+  // indices in the grid. assumption is square [(xs, xe),(zs, ze)] is the central square in the grid
+  fn real_inds(&self, l: f32, _w: f32) -> (isize, isize) {
+    let gc = Vector2::new((self.x.x + self.x.y) / 2.0, (self.z.x + self.z.y) / 2.0);
+    //println!("x {:?} z {:?} gc {:?}", self.x, self.z, gc);
+    (
+      ((self.c.x - gc.x) / l) as isize,
+      ((self.c.y - gc.y) / l) as isize,
+    )
+  }
+
+  fn moved(&mut self, indices: (isize, isize)) {
+    self.x += Vector2::new(Sky::X * indices.0 as f32, Sky::X * indices.0 as f32);
+    self.z += Vector2::new(Sky::Z * indices.1 as f32, Sky::Z * indices.1 as f32);
+  }
+
+  // the same tiles and camera, relative to an origin moved by shift
+  fn origin_shifted(&mut self, shift: Vector3<f32>) {
+    self.x -= Vector2::new(shift.x, shift.x);
+    self.z -= Vector2::new(shift.z, shift.z);
+    self.c -= Vector2::new(shift.x, shift.z);
+    self.origin += Vector2::new(shift.x as f64, shift.z as f64);
+  }
+}
+
+// generates tile heights, going through the on disk cache when one is configured
+#[derive(Clone)]
+struct TerrainSource {
+  params: TerrainParams,
+  cache: Option<TerrainCache>,
+}
+
+impl TerrainSource {
+  fn new(params: TerrainParams, cache_dir: Option<&Path>) -> Self {
+    let cache = cache_dir.map(|dir| {
+      let cache = TerrainCache::new(dir, &params, Sky::DETAIL, Sky::X as i32);
+      match cache.invalidate_stale() {
+        Ok(0) => {}
+        Ok(removed) => println!("terrain cache: removed {} stale tiles", removed),
+        Err(e) => println!("terrain cache: failed to remove stale tiles {:?}", e),
+      }
+      cache
+    });
+    TerrainSource { params, cache }
+  }
+
+  // x, z is the absolute position of the tile center
+  fn grid(&self, tile: (isize, isize), x: f64, z: f64) -> TerrainGrid {
+    if let Some(grid) = self.cache.as_ref().and_then(|cache| cache.load(tile)) {
+      return grid;
+    }
+    let grid = terrain_grid(&self.params, Sky::DETAIL, Sky::X as i32, x, z);
+    if let Some(cache) = self.cache.as_ref() {
+      if let Err(e) = cache.store(tile, &grid) {
+        println!("terrain cache: failed to store tile {:?} {:?}", tile, e);
+      }
+    }
+    grid
+  }
+}
+
+// in cloud mode the sky terrain is drawn as transparent clouds over a solid ground
+#[derive(Clone)]
+struct TileSource {
+  sky: TerrainSource,
+  ground: Option<TerrainSource>,
+}
+
+impl TileSource {
+  fn new(settings: &Settings) -> Self {
+    let dir = settings.terrain_cache_dir.as_deref();
+    // the terrain with the water, the clouds keep their own sea level
+    let flooded = |params: TerrainParams| TerrainParams {
+      sealevel: settings.sea_level,
+      ..params
+    };
+    // each cache removes the tiles of other params from its directory
+    if settings.clouds_enabled {
+      let ground_dir = dir.map(|dir| dir.join("ground"));
+      TileSource {
+        sky: TerrainSource::new(TerrainParams::clouds(), dir),
+        ground: Some(TerrainSource::new(flooded(TerrainParams::ground()), ground_dir.as_deref())),
+      }
+    } else {
+      TileSource {
+        sky: TerrainSource::new(flooded(TerrainParams::default()), dir),
+        ground: None,
+      }
+    }
+  }
+
+  // what the opaque terrain and the water of a tile are generated from
+  fn solid(&self) -> &TerrainSource {
+    self.ground.as_ref().unwrap_or(&self.sky)
+  }
+
+  fn solid_offset(&self) -> Vector3<f32> {
+    match self.ground {
+      Some(_) => Vector3::new(0.0, Sky::GROUND_DEPTH, 0.0),
+      None => Vector3::new(0.0, 0.0, 0.0),
+    }
+  }
+}
+
 type ArcCacheCellInner = Arc<RwLock<CacheCellInner>>;
 
+// the device local buffers of a tile
+#[derive(Clone)]
+struct SegmentModels {
+  terrain: Model,
+  water: Option<Model>,
+  clouds: Option<Model>,
+}
+
+impl SegmentModels {
+  fn translated(&self, offset: Vector3<f32>) -> Self {
+    SegmentModels {
+      terrain: self.terrain.translated(offset),
+      water: self.water.as_ref().map(|model| model.translated(offset)),
+      clouds: self.clouds.as_ref().map(|model| model.translated(offset)),
+    }
+  }
+}
+
 struct SkySegment {
   terrain: TerrainModel,
-  model: Model,
+  // only tiles that dip below the sea level have water
+  water: Option<MyMesh>,
+  // the sky terrain drawn transparent, only in cloud mode
+  clouds: Option<MyMesh>,
+  // none until the main thread uploaded the meshes, see Sky::take_uploads
+  models: Option<SegmentModels>,
   scene: Scene,
 }
 
+impl SkySegment {
+  // where the sky tile is, the ground is only moved down from it
+  fn sky_transform(&self) -> Matrix4<f32> {
+    match self.clouds.as_ref() {
+      Some(mesh) => mesh.data.transform,
+      None => self.terrain.mesh.data.transform,
+    }
+  }
+
+  // the copies of all the meshes of the tile as one future, none once it is uploaded
+  fn upload(&mut self, queue: &Arc<Queue>) -> Option<Box<dyn GpuFuture>> {
+    if self.models.is_some() {
+      return None;
+    }
+    let (terrain, future) = self.terrain.mesh.upload_buffers(queue);
+    let mut uploads: Vec<Box<dyn GpuFuture>> = vec![];
+    let mut upload = |mesh: &MyMesh| {
+      let (model, upload) = mesh.upload_buffers(queue);
+      uploads.push(upload.boxed());
+      model
+    };
+    let water = self.water.as_ref().map(&mut upload);
+    let clouds = self.clouds.as_ref().map(&mut upload);
+    self.models = Some(SegmentModels {
+      terrain,
+      water,
+      clouds,
+    });
+    Some(
+      uploads
+        .into_iter()
+        .fold(future.boxed(), |future, upload| future.join(upload).boxed()),
+    )
+  }
+}
+
+// the terrain, water and clouds meshes of a tile. x, z is the corner of the tile relative to
+// the world origin, ax, az the same in absolute coordinates
+fn generate_segment(
+  source: &TileSource,
+  tile: (isize, isize),
+  (x, z): (f32, f32),
+  (ax, az): (f64, f64),
+) -> SkySegment {
+  let (cx, cz) = (x + Sky::X / 2.0, z + Sky::Z / 2.0);
+  let (gx, gz) = (ax + Sky::X as f64 / 2.0, az + Sky::Z as f64 / 2.0);
+  let generate = |terrain: &TerrainSource| {
+    let grid = terrain.grid(tile, gx, gz);
+    terrain_from_grid(&terrain.params, grid, Sky::SCALE, Sky::X as i32, cx, cz)
+  };
+  let solid = source.solid();
+  let mut terrain_model = generate(solid);
+  terrain_model.mesh.translate(source.solid_offset());
+  let clouds = source
+    .ground
+    .as_ref()
+    .map(|_| clouds_from_terrain(&source.sky.params, &generate(&source.sky)));
+  let water = water_from_grid(
+    &solid.params,
+    &terrain_model.grid,
+    Sky::SCALE,
+    Sky::X as i32,
+    cx,
+    cz,
+  )
+  .map(|mut mesh| {
+    mesh.translate(source.solid_offset());
+    mesh
+  });
+
+  SkySegment {
+    terrain: terrain_model,
+    water,
+    clouds,
+    models: None,
+    scene: Scene::default(),
+  }
+}
+
 #[derive(Default)]
 struct CacheCellInner {
   model: Option<SkySegment>,
@@ -86,19 +338,6 @@ struct CacheCell {
   future: Option<RemoteHandle<()>>,
 }
 
-fn get_border_vec(
-  cell_opt: Option<ArcCacheCellInner>,
-  f: fn(&SkySegment) -> Vec<f32>,
-) -> Option<Vec<f32>> {
-  if cell_opt.is_none() {
-    return None;
-  }
-  let cell = cell_opt.unwrap();
-  let read = cell.read();
-  let tm: Option<&SkySegment> = ((*read).model).as_ref();
-  Some(f(&tm.unwrap()))
-}
-
 impl CacheCell {
   fn is_queued(&self) -> bool {
     self.future.is_some()
@@ -108,14 +347,10 @@ impl CacheCell {
   fn spawn_region(
     &mut self,
     executor: &Executor,
-    device: &Arc<Device>,
-    lap_mesh: &LapMesh,
+    source: &TileSource,
+    origin: Vector2<f64>,
     x: f32,
     z: f32,
-    oleft: Option<ArcCacheCellInner>,
-    oright: Option<ArcCacheCellInner>,
-    otop: Option<ArcCacheCellInner>,
-    obottom: Option<ArcCacheCellInner>,
   ) {
     if self.future.is_some() {
       return;
@@ -127,46 +362,20 @@ impl CacheCell {
       }
     }
 
-    let weak_device = Arc::downgrade(device);
     let weak_self_inner = Arc::downgrade(&self.inner);
-    let lap_mesh: LapMesh = lap_mesh.clone();
+    let source = source.clone();
+    let (ax, az) = (origin.x + x as f64, origin.y + z as f64);
+    let tile = tile_key(origin, x, z);
     let fut = async move {
       // println!("generated ({:?},{:?})", x, z);
-      if let Some(device) = weak_device.upgrade() {
-        if let Some(self_inner) = weak_self_inner.upgrade() {
-          let mut locked = self_inner.write();
-          if locked.model.is_some() {
-            return;
-          }
-          let vleft = get_border_vec(oleft, |tm| tm.terrain.right.clone());
-          let vright = get_border_vec(oright, |tm| tm.terrain.left.clone());
-          let vtop = get_border_vec(otop, |tm| tm.terrain.bottom.clone());
-          let vbottom = get_border_vec(obottom, |tm| tm.terrain.top.clone());
-          let mut terrain_model = terrain_execute(
-            Sky::SCALE,
-            Sky::DETAIL,
-            Sky::X as i32,
-            x + Sky::X / 2.0,
-            z + Sky::Z / 2.0,
-            vleft,
-            vright,
-            vtop,
-            vbottom,
-          );
-
-          let mut mesh = lap_mesh.mesh;
-          mesh.update_transform_2(
-            Vector3::<f32>::new(0.0, -300.0, 0.0),
-            Matrix4::one(),
-            [1.0, 1.0, 1.0],
-          );
-          terrain_model.mesh.add_consume(&mut mesh);
-          let model = terrain_model.mesh.get_buffers(&device);
-          let sky_segment = SkySegment {
-            terrain: terrain_model,
-            model: model,
-            scene: Scene::default(),
-          };
+      if let Some(self_inner) = weak_self_inner.upgrade() {
+        if self_inner.read().model.is_some() {
+          return;
+        }
+        // generated without the lock, the main thread uploads it with the next frame
+        let sky_segment = generate_segment(&source, tile, (x, z), (ax, az));
+        let mut locked = self_inner.write();
+        if locked.model.is_none() {
           locked.model = Some(sky_segment);
         }
       }
@@ -191,14 +400,10 @@ impl CacheCell {
   fn create_block(
     &mut self,
     executor: &Executor,
-    device: &Arc<Device>,
-    lap_mesh: &LapMesh,
+    source: &TileSource,
+    origin: Vector2<f64>,
     x: f32,
     z: f32,
-    oleft: Option<ArcCacheCellInner>,
-    oright: Option<ArcCacheCellInner>,
-    otop: Option<ArcCacheCellInner>,
-    obottom: Option<ArcCacheCellInner>,
   ) {
     {
       let peaked = self.inner.read();
@@ -207,7 +412,7 @@ impl CacheCell {
       }
     }
     println!("blocking on sky");
-    self.spawn_region(executor, device, lap_mesh, x, z, oleft, oright, otop, obottom);
+    self.spawn_region(executor, source, origin, x, z);
     self.block();
   }
 
@@ -223,28 +428,90 @@ impl CacheCell {
     )
   }
 
+  // moves a generated tile by -shift, its buffers are only drawn with a new offset.
+  // tiles still being generated are dropped as they were placed relative to the old origin
+  #[profiling::function]
+  fn origin_shifted(&mut self, shift: Vector3<f32>) {
+    let shifted = {
+      let mut write_locked = self.inner.write();
+      if let Some(sky_segment) = write_locked.model.as_mut() {
+        sky_segment.terrain.mesh.translate(-shift);
+        if let Some(mesh) = sky_segment.water.as_mut() {
+          mesh.translate(-shift);
+        }
+        if let Some(mesh) = sky_segment.clouds.as_mut() {
+          mesh.translate(-shift);
+        }
+        sky_segment.models = sky_segment.models.as_ref().map(|m| m.translated(-shift));
+        true
+      } else {
+        false
+      }
+    };
+    if !shifted {
+      *self = CacheCell::default();
+    }
+  }
+
+  // a generated tile that was not uploaded yet
+  fn upload(&self, queue: &Arc<Queue>) -> Option<Box<dyn GpuFuture>> {
+    let mut write_locked = self.inner.write();
+    write_locked
+      .model
+      .as_mut()
+      .and_then(|sky_segment| sky_segment.upload(queue))
+  }
+
+  // tiles are only drawn once their upload was joined by a frame
+  fn models(&self) -> Option<SegmentModels> {
+    let read_locked = self.inner.read();
+    let sky_segment = read_locked.model.as_ref();
+    sky_segment.and_then(|m| m.models.clone())
+  }
+
   fn model(&self) -> Option<Model> {
+    self.models().map(|models| models.terrain)
+  }
+
+  // the lap hangs below every tile, it follows the tile through origin shifts
+  fn lap_instance(&self, offset: Vector3<f32>) -> Option<Instance> {
+    let read_locked = self.inner.read();
+    let sky_segment = read_locked.model.as_ref().filter(|m| m.models.is_some());
+    sky_segment.map(|m| Instance::new(m.sky_transform() * Matrix4::from_translation(offset)))
+  }
+
+  fn water_model(&self) -> Option<Model> {
+    self.models().and_then(|models| models.water)
+  }
+
+  // with the x, z of the tile center, to sort the transparent tiles by
+  fn clouds_model(&self) -> Option<(Vector2<f32>, Model)> {
     let read_locked = self.inner.read();
     let sky_segment = read_locked.model.as_ref();
-    sky_segment.map(|m| m.model.clone())
+    sky_segment.and_then(|m| {
+      let transform = m.sky_transform();
+      let center = Vector2::new(transform[3][0], transform[3][2]);
+      let clouds = m.models.as_ref().and_then(|models| models.clouds.clone());
+      clouds.map(|model| (center, model))
+    })
   }
 }
 
 pub struct Sky {
   settings: Settings,
-  device: Arc<Device>,
+  queue: Arc<Queue>,
   cache: Vec<CacheCell>,
-  x: Vector2<f32>,
-  z: Vector2<f32>,
-  // last seen camera position
-  c: Vector2<f32>,
+  grid: TileGrid,
   ordered_cells: Vec<(isize, isize)>,
   scene: Scene,
-  lap_mesh: LapMesh,
+  lap: InstancedModel,
+  // where the lap sits relative to the tile center
+  lap_offset: Vector3<f32>,
+  terrain: TileSource,
 }
 
 impl Sky {
-  pub fn new(settings: Settings, device: &Arc<Device>, x: f32, z: f32) -> Self {
+  pub fn new(settings: Settings, queue: &Arc<Queue>, x: f32, z: f32) -> Self {
     let mut cache: Vec<CacheCell> = vec![];
     for _i in 0..(Sky::X_ROWS * Sky::Z_ROWS) {
       cache.push(CacheCell::default());
@@ -294,29 +561,25 @@ impl Sky {
     };
 
     let lap_mesh = LapMesh::new();
+    let lap = lap_mesh.mesh.get_instanced_buffers(queue.device());
+    // the offset used to be applied before the lap transform, keep the lap in place
+    let lap_offset = lap_mesh
+      .mesh
+      .data
+      .transform
+      .transform_vector(Vector3::new(0.0, -300.0, 0.0));
+    let terrain = TileSource::new(&settings);
 
     Sky {
       settings: settings,
-      device: Arc::clone(device),
+      queue: Arc::clone(queue),
       cache,
-      x: Vector2::new(x, x + Sky::X),
-      z: Vector2::new(z, z + Sky::Z),
-      c: Vector2::new(0.0, 0.0),
+      grid: TileGrid::new(x, z),
       ordered_cells: ordered,
       scene,
-      lap_mesh,
-    }
-  }
-
-  fn get_arc(&self, cell: &(isize, isize)) -> Option<ArcCacheCellInner> {
-    let ppp = gii(cell.0, cell.1);
-    if ppp.is_none() {
-      return None;
-    }
-    if !self.cache[ppp.unwrap()].is_queued() {
-      None
-    } else {
-      Some(self.cache[tii(cell)].inner.clone())
+      lap,
+      lap_offset,
+      terrain,
     }
   }
 
@@ -329,14 +592,10 @@ impl Sky {
       profiling::scope!("00block");
       self.cache[giiu(0, 0)].create_block(
         executor,
-        &self.device,
-        &self.lap_mesh,
-        self.x.x,
-        self.z.x,
-        None,
-        None,
-        None,
-        None,
+        &self.terrain,
+        self.grid.origin,
+        self.grid.x.x,
+        self.grid.z.x,
       );
     }
 
@@ -348,26 +607,13 @@ impl Sky {
       let ordered_cells = &self.ordered_cells;
       for try_cell in ordered_cells {
         if !self.cache[tii(try_cell)].is_queued() {
-          let xx = xindex(self.x.x, try_cell.0);
-          let zz = zindex(self.z.x, try_cell.1);
-          let try_left = (try_cell.0 - 1, try_cell.1);
-          let try_right = (try_cell.0 + 1, try_cell.1);
-          let try_top = (try_cell.0, try_cell.1 - 1);
-          let try_bottom = (try_cell.0, try_cell.1 + 1);
-          let oleft = self.get_arc(&try_left);
-          let oright = self.get_arc(&try_right);
-          let otop = self.get_arc(&try_top);
-          let obottom = self.get_arc(&try_bottom);
+          let (xx, zz) = self.grid.corner(*try_cell);
           self.cache[tii(try_cell)].spawn_region(
             executor,
-            &self.device,
-            &self.lap_mesh,
+            &self.terrain,
+            self.grid.origin,
             xx,
             zz,
-            oleft,
-            oright,
-            otop,
-            obottom,
           );
         }
       }
@@ -409,12 +655,28 @@ impl Sky {
         self.cache.iter().map(|e| e.status()).collect::<Vec<_>>()
       );*/
 
-      self.x += Vector2::new(Sky::X * indices.0 as f32, Sky::X * indices.0 as f32);
-      self.z += Vector2::new(Sky::Z * indices.1 as f32, Sky::Z * indices.1 as f32);
+      self.grid.moved(indices);
       // println!("changing x {:?} z {:?}", self.x, self.z);
     }
   }
 
+  // waits for every tile that is still being generated
+  #[profiling::function]
+  pub fn finish_pending(&mut self) {
+    for cell in self.cache.iter_mut() {
+      if let Some(future) = cell.future.take() {
+        block_on(future);
+      }
+    }
+  }
+
+  // the copies of the tiles generated since the last call, the next frame has to join them
+  #[profiling::function]
+  pub fn take_uploads(&mut self) -> Vec<Box<dyn GpuFuture>> {
+    let queue = &self.queue;
+    self.cache.iter().filter_map(|cell| cell.upload(queue)).collect()
+  }
+
   pub fn get_scene(&self) -> Vec<&Scene> {
     if self.settings.sky_enabled {
       vec![&self.scene]
@@ -435,18 +697,85 @@ impl Sky {
     res
   }
 
+  pub fn get_current_laps(&self) -> (&InstancedModel, Vec<Instance>) {
+    let mut res = vec![];
+    for (i, j) in &self.ordered_cells {
+      if i.abs() + j.abs() < 3 {
+        if let Some(elem) = self.cache[giiu(*i, *j)].lap_instance(self.lap_offset) {
+          res.push(elem);
+        };
+      }
+    }
+    (&self.lap, res)
+  }
+
+  pub fn get_current_water(&self) -> Vec<Model> {
+    let mut res = vec![];
+    for (i, j) in &self.ordered_cells {
+      if i.abs() + j.abs() < 3 {
+        if let Some(elem) = self.cache[giiu(*i, *j)].water_model() {
+          res.push(elem);
+        };
+      }
+    }
+    res
+  }
+
+  // furthest first, they are blended over each other
+  pub fn get_current_clouds(&self) -> Vec<Model> {
+    let mut res = vec![];
+    for (i, j) in &self.ordered_cells {
+      if i.abs() + j.abs() < 3 {
+        if let Some(elem) = self.cache[giiu(*i, *j)].clouds_model() {
+          res.push(elem);
+        };
+      }
+    }
+    back_to_front(&mut res, self.grid.c);
+    res.into_iter().map(|(_, model)| model).collect()
+  }
+
+  // the squares of the tiles get_current draws, dimmed while a tile is still generated
+  pub fn debug_tiles(&self, lines: &mut DebugLines) {
+    for (i, j) in &self.ordered_cells {
+      if i.abs() + j.abs() < 3 {
+        let color = if self.cache[giiu(*i, *j)].model().is_some() {
+          MAGENTA
+        } else {
+          [0.3, 0.0, 0.3, 1.0]
+        };
+        let (x, z) = self.grid.corner((*i, *j));
+        let min = Point3::new(x, 0.0, z);
+        lines.grid(min, Vector2::new(Sky::X, Sky::Z), (1, 1), color);
+      }
+    }
+  }
+
   pub fn camera_entered(&mut self, pos: &Point3<f32>) {
-    self.c = Vector2::new(pos.x, pos.z);
+    self.grid.c = Vector2::new(pos.x, pos.z);
   }
 
-  // indices in the grid. assumption is square [(xs, xe),(zs, ze)] is the central square in the grid
-  fn real_inds(&self, l: f32, _w: f32) -> (isize, isize) {
-    let gc = Vector2::new((self.x.x + self.x.y) / 2.0, (self.z.x + self.z.y) / 2.0);
-    //println!("x {:?} z {:?} gc {:?}", self.x, self.z, gc);
-    (
-      ((self.c.x - gc.x) / l) as isize,
-      ((self.c.y - gc.y) / l) as isize,
-    )
+  #[profiling::function]
+  pub fn origin_shifted(&mut self, shift: Vector3<f32>) {
+    self.grid.origin_shifted(shift);
+    for cell in self.cache.iter_mut() {
+      cell.origin_shifted(shift);
+    }
+    let point_lights = self
+      .scene
+      .point_lights
+      .iter()
+      .map(|light| {
+        let mut light = **light;
+        light.position = (Vector3::from(light.position) - shift).into();
+        Arc::new(light)
+      })
+      .collect();
+    self.scene.point_lights = point_lights;
+  }
+
+  fn real_inds(&self, l: f32, w: f32) -> (isize, isize) {
+    self.grid.real_inds(l, w)
   }
 }
 
@@ -456,4 +785,38 @@ impl Actor for Sky {
   }
 }
 
-mod tests {}
+#[cfg(test)]
+mod test {
+  use cgmath::{Vector2, Vector3};
+
+  use crate::sky::{giiu, Sky, TileGrid};
+
+  #[test]
+  pub fn test_tiles_across_origin_shift() {
+    let mut grid = TileGrid::new(0.0, 0.0);
+    grid.c = Vector2::new(2350.0, -1680.0);
+    // as Sky::tick follows the camera
+    let indices = grid.real_inds(Sky::X, Sky::Z);
+    grid.moved(indices);
+    assert_eq!(grid.real_inds(Sky::X, Sky::Z), (0, 0));
+    assert_eq!(grid.tile((0, 0)), (23, -17));
+
+    let cells: Vec<(isize, isize)> = (-4..=4)
+      .flat_map(|i| (-4..=4).map(move |j| (i, j)))
+      .collect();
+    let before: Vec<_> = cells.iter().map(|cell| grid.tile(*cell)).collect();
+    grid.origin_shifted(Vector3::new(2000.0, 0.0, -2000.0));
+    // the cache is not moved, the same cells hold the same tiles
+    assert_eq!(grid.real_inds(Sky::X, Sky::Z), (0, 0));
+    let after: Vec<_> = cells.iter().map(|cell| grid.tile(*cell)).collect();
+    assert_eq!(before, after);
+    assert_eq!(grid.tile((1, -2)), (24, -19));
+    assert_eq!(giiu(1, -2), giiu(1, 0) - 2 * Sky::X_ROWS);
+
+    // the camera is still over the central tile
+    let (x, z) = grid.corner((0, 0));
+    assert!(x <= grid.c.x && grid.c.x < x + Sky::X);
+    assert!(z <= grid.c.y && grid.c.y < z + Sky::Z);
+    assert_eq!((x, z), (300.0, 300.0));
+  }
+}
@@ -0,0 +1,37 @@
+use bevy_ecs::event::{EventReader, EventWriter};
+use bevy_ecs::system::Local;
+use winit::event::VirtualKeyCode;
+
+use crate::input::{CaptureEvent, GameEvent, InputEvent, MyKeyStatus, MyKeyboardInput};
+
+// keys held down, winit repeats the pressed events while a key is held
+#[derive(Default)]
+pub struct CaptureKeys {
+  screenshot: bool,
+  sequence: bool,
+}
+
+pub fn capture_reacts_to_keyboard(
+  mut reader: EventReader<InputEvent>,
+  mut held: Local<CaptureKeys>,
+  mut writer: EventWriter<GameEvent>,
+) {
+  for event in reader.iter() {
+    if let InputEvent::KeyBoard(MyKeyboardInput::Key {
+      key_code: Some(key_code),
+      status,
+    }) = event
+    {
+      let pressed = matches!(status, MyKeyStatus::Pressed);
+      let (was_held, capture) = match key_code {
+        VirtualKeyCode::F12 => (&mut held.screenshot, CaptureEvent::Screenshot),
+        VirtualKeyCode::F11 => (&mut held.sequence, CaptureEvent::Sequence),
+        _ => continue,
+      };
+      if pressed && !*was_held {
+        writer.send(GameEvent::Capture(capture));
+      }
+      *was_held = pressed;
+    }
+  }
+}
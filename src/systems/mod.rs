@@ -14,10 +14,18 @@ use cgmath::Point2;
 use winit::event::VirtualKeyCode;
 
 mod camera;
+mod capture;
 mod game_systems;
+mod origin;
+mod time_of_day;
+mod view;
 
 pub use camera::*;
+pub use capture::*;
 pub use game_systems::*;
+pub use origin::*;
+pub use time_of_day::*;
+pub use view::*;
 
 // This system moves each entity with a Position and Velocity component
 pub fn movement(mut query: Query<(&mut Position, &Velocity)>) {
@@ -0,0 +1,89 @@
+use bevy_ecs::event::EventWriter;
+use bevy_ecs::system::{Query, ResMut};
+use cgmath::{Point3, Vector3};
+
+use crate::components::{CameraId, Position, WorldOrigin};
+use crate::input::{GameEvent, OriginShiftedEvent};
+
+// has to be a multiple of the sky tile size so tiles stay on the same grid after a shift
+pub const REBASE_STEP: f32 = 1000.0;
+pub const REBASE_DISTANCE: f32 = 2000.0;
+
+pub fn origin_shift(local: &Point3<f32>) -> Option<Vector3<f32>> {
+  if local.x.abs() < REBASE_DISTANCE && local.z.abs() < REBASE_DISTANCE {
+    return None;
+  }
+  Some(Vector3::new(
+    (local.x / REBASE_STEP).round() * REBASE_STEP,
+    0.0,
+    (local.z / REBASE_STEP).round() * REBASE_STEP,
+  ))
+}
+
+pub fn rebase_origin(
+  mut origin: ResMut<WorldOrigin>,
+  mut writer: EventWriter<GameEvent>,
+  mut query: Query<(&CameraId, &mut Position)>,
+) {
+  for (_camera, mut position) in query.iter_mut() {
+    if let Some(shift) = origin_shift(&position.point3) {
+      position.point3 -= shift;
+      origin.shift(shift);
+      writer.send(GameEvent::OriginShifted(OriginShiftedEvent { shift }));
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::components::WorldOrigin;
+  use crate::systems::origin::{origin_shift, REBASE_DISTANCE, REBASE_STEP};
+  use cgmath::{Point3, Vector3};
+
+  const TILE: f64 = 100.0;
+
+  fn fly(velocity: Vector3<f32>, distance: f64) {
+    let mut origin = WorldOrigin::default();
+    let mut local = Point3::new(0.0f32, 10.0, 0.0);
+    let steps = (distance / velocity.x.abs().max(velocity.z.abs()) as f64) as u64;
+    for step in 1..=steps {
+      local += velocity;
+      if let Some(shift) = origin_shift(&local) {
+        local -= shift;
+        origin.shift(shift);
+      }
+      assert!(local.x.abs() <= REBASE_DISTANCE && local.z.abs() <= REBASE_DISTANCE);
+
+      let expected_x = step as f64 * velocity.x as f64;
+      let expected_z = step as f64 * velocity.z as f64;
+      let absolute = origin.absolute(local);
+      assert_eq!(absolute.x, expected_x);
+      assert_eq!(absolute.z, expected_z);
+      assert_eq!(
+        ((absolute.x / TILE).floor(), (absolute.z / TILE).floor()),
+        ((expected_x / TILE).floor(), (expected_z / TILE).floor())
+      );
+    }
+    assert_eq!(origin.offset.x % REBASE_STEP as f64, 0.0);
+    assert_eq!(origin.offset.z % REBASE_STEP as f64, 0.0);
+  }
+
+  #[test]
+  pub fn test_fly_far_keeps_tile_indexing() {
+    fly(Vector3::new(37.5, 0.0, 37.5), 1.0e7);
+  }
+
+  #[test]
+  pub fn test_fly_far_backwards_keeps_tile_indexing() {
+    fly(Vector3::new(-62.25, 0.0, 12.5), 1.0e7);
+  }
+
+  #[test]
+  pub fn test_no_shift_near_origin() {
+    assert_eq!(origin_shift(&Point3::new(1999.0, 0.0, -1999.0)), None);
+    assert_eq!(
+      origin_shift(&Point3::new(2000.0, 5.0, -1499.0)),
+      Some(Vector3::new(2000.0, 0.0, -1000.0))
+    );
+  }
+}
@@ -0,0 +1,72 @@
+use bevy_ecs::event::EventReader;
+use bevy_ecs::system::{Local, ResMut};
+use winit::event::VirtualKeyCode;
+
+use std::time::Instant;
+
+use crate::components::TimeOfDay;
+use crate::input::{InputEvent, MyKeyStatus, MyKeyboardInput};
+
+// hours per real second while a scrub key is held
+const SCRUB_SPEED: f32 = 4.0;
+
+#[derive(Default)]
+pub struct ScrubKeys {
+  forward: bool,
+  backward: bool,
+  last_tick: Option<Instant>,
+}
+
+// the day goes on by itself, holding T scrubs forward and R backward
+pub fn time_of_day_advances(
+  mut reader: EventReader<InputEvent>,
+  mut keys: Local<ScrubKeys>,
+  mut time_of_day: ResMut<TimeOfDay>,
+) {
+  for event in reader.iter() {
+    if let InputEvent::KeyBoard(MyKeyboardInput::Key {
+      key_code: Some(key_code),
+      status,
+    }) = event
+    {
+      let pressed = matches!(status, MyKeyStatus::Pressed);
+      match key_code {
+        VirtualKeyCode::T => keys.forward = pressed,
+        VirtualKeyCode::R => keys.backward = pressed,
+        _ => {}
+      }
+    }
+  }
+  let now = Instant::now();
+  let elapsed = keys
+    .last_tick
+    .map_or(0.0, |last| (now - last).as_secs_f32());
+  keys.last_tick = Some(now);
+  if time_of_day.frozen {
+    return;
+  }
+  let scrub = match (keys.forward, keys.backward) {
+    (true, false) => SCRUB_SPEED,
+    (false, true) => -SCRUB_SPEED,
+    _ => 0.0,
+  };
+  let hours_per_second = 24.0 / time_of_day.day_length + scrub;
+  time_of_day.advance(elapsed * hours_per_second);
+}
+
+#[cfg(test)]
+mod test {
+  use crate::components::TimeOfDay;
+
+  #[test]
+  pub fn test_time_of_day_wraps() {
+    let mut time_of_day = TimeOfDay::default();
+    time_of_day.hours = 23.5;
+    time_of_day.advance(1.0);
+    assert!((time_of_day.hours - 0.5).abs() < 1e-5);
+    assert_eq!(time_of_day.to_string(), "00:30");
+    time_of_day.advance(-1.0);
+    assert!((time_of_day.hours - 23.5).abs() < 1e-5);
+    assert_eq!(time_of_day.to_string(), "23:30");
+  }
+}
@@ -0,0 +1,45 @@
+use bevy_ecs::event::{EventReader, EventWriter};
+use bevy_ecs::system::Local;
+use winit::event::VirtualKeyCode;
+
+use crate::input::{GameEvent, InputEvent, MyKeyStatus, MyKeyboardInput, ViewEvent};
+
+// keys held down, winit repeats the pressed events while a key is held
+#[derive(Default)]
+pub struct ViewKeys {
+  next_skybox: bool,
+  normals: bool,
+  aabbs: bool,
+  sky_grid: bool,
+  axes: bool,
+  render_mode: bool,
+}
+
+pub fn view_reacts_to_keyboard(
+  mut reader: EventReader<InputEvent>,
+  mut held: Local<ViewKeys>,
+  mut writer: EventWriter<GameEvent>,
+) {
+  for event in reader.iter() {
+    if let InputEvent::KeyBoard(MyKeyboardInput::Key {
+      key_code: Some(key_code),
+      status,
+    }) = event
+    {
+      let pressed = matches!(status, MyKeyStatus::Pressed);
+      let (was_held, view) = match key_code {
+        VirtualKeyCode::F4 => (&mut held.render_mode, ViewEvent::NextRenderMode),
+        VirtualKeyCode::F5 => (&mut held.normals, ViewEvent::ToggleNormals),
+        VirtualKeyCode::F6 => (&mut held.aabbs, ViewEvent::ToggleAabbs),
+        VirtualKeyCode::F7 => (&mut held.sky_grid, ViewEvent::ToggleSkyGrid),
+        VirtualKeyCode::F8 => (&mut held.axes, ViewEvent::ToggleAxes),
+        VirtualKeyCode::F9 => (&mut held.next_skybox, ViewEvent::NextSkybox),
+        _ => continue,
+      };
+      if pressed && !*was_held {
+        writer.send(GameEvent::View(view));
+      }
+      *was_held = pressed;
+    }
+  }
+}
@@ -0,0 +1,63 @@
+use cgmath::Vector4;
+
+// heights are the raw terrain heights, before the height scale is applied
+const BEACH_HEIGHT: f32 = 0.006;
+const SNOW_HEIGHT: f32 = 0.085;
+// slope is 1 - |normal.y|, 0 is flat and 1 is a vertical wall
+const ROCK_SLOPE: f32 = 0.35;
+const SNOW_MAX_SLOPE: f32 = 0.5;
+const DRY_MOISTURE: f32 = 0.3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Biome {
+  Water,
+  Sand,
+  Grass,
+  Rock,
+  Snow,
+}
+
+impl Biome {
+  pub fn classify(sealevel: f32, height: f32, slope: f32, moisture: f32) -> Biome {
+    if height <= sealevel {
+      Biome::Water
+    } else if height > SNOW_HEIGHT && slope < SNOW_MAX_SLOPE {
+      Biome::Snow
+    } else if slope > ROCK_SLOPE {
+      Biome::Rock
+    } else if height < sealevel + BEACH_HEIGHT || moisture < DRY_MOISTURE {
+      Biome::Sand
+    } else {
+      Biome::Grass
+    }
+  }
+
+  pub fn color(self) -> Vector4<f32> {
+    match self {
+      Biome::Water => Vector4::new(0.10, 0.25, 0.55, 1.0),
+      Biome::Sand => Vector4::new(0.76, 0.70, 0.50, 1.0),
+      Biome::Grass => Vector4::new(0.30, 0.55, 0.20, 1.0),
+      Biome::Rock => Vector4::new(0.45, 0.42, 0.40, 1.0),
+      Biome::Snow => Vector4::new(0.95, 0.95, 0.97, 1.0),
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::things::biome::Biome;
+
+  const SEALEVEL: f32 = 0.0;
+
+  #[test]
+  pub fn test_classify() {
+    assert_eq!(Biome::classify(SEALEVEL, -0.01, 0.0, 0.5), Biome::Water);
+    assert_eq!(Biome::classify(SEALEVEL, SEALEVEL, 0.9, 0.5), Biome::Water);
+    assert_eq!(Biome::classify(SEALEVEL, 0.002, 0.0, 0.5), Biome::Sand);
+    assert_eq!(Biome::classify(SEALEVEL, 0.04, 0.0, 0.1), Biome::Sand);
+    assert_eq!(Biome::classify(SEALEVEL, 0.04, 0.1, 0.6), Biome::Grass);
+    assert_eq!(Biome::classify(SEALEVEL, 0.04, 0.6, 0.6), Biome::Rock);
+    assert_eq!(Biome::classify(SEALEVEL, 0.1, 0.1, 0.6), Biome::Snow);
+    assert_eq!(Biome::classify(SEALEVEL, 0.1, 0.8, 0.6), Biome::Rock);
+  }
+}
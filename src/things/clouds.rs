@@ -0,0 +1,86 @@
+use cgmath::{InnerSpace, Vector2, Vector4};
+use profiling;
+
+use crate::render::MyMesh;
+use crate::things::{TerrainModel, TerrainParams};
+
+// raw heights above the sea level over which a cloud fades in, before the height scale
+const CLOUD_SOFTNESS: f32 = 0.03;
+const CLOUD_COLOR: [f32; 3] = [0.95, 0.96, 1.0];
+
+// 0 where the noise is clamped flat at the sea level, rising smoothly to 1
+pub fn cloud_alpha(sealevel: f32, height: f32) -> f32 {
+  let t = ((height - sealevel) / CLOUD_SOFTNESS).max(0.0).min(1.0);
+  t * t * (3.0 - 2.0 * t)
+}
+
+// the sky terrain recolored as clouds, the vertices are in the same order as the grid heights
+#[profiling::function]
+pub fn clouds_from_terrain(params: &TerrainParams, terrain: &TerrainModel) -> MyMesh {
+  let mut mesh = terrain.mesh.clone();
+  mesh.data.color = terrain
+    .grid
+    .heights
+    .iter()
+    .map(|height| {
+      let alpha = cloud_alpha(params.sealevel, *height);
+      Vector4::new(CLOUD_COLOR[0], CLOUD_COLOR[1], CLOUD_COLOR[2], alpha)
+    })
+    .collect();
+  mesh
+}
+
+// transparent tiles are blended furthest first, items are keyed by their x, z center
+pub fn back_to_front<T>(items: &mut [(Vector2<f32>, T)], camera: Vector2<f32>) {
+  items.sort_by(|(a, _), (b, _)| {
+    let (da, db) = ((a - camera).magnitude2(), (b - camera).magnitude2());
+    db.partial_cmp(&da).unwrap()
+  });
+}
+
+#[cfg(test)]
+mod test {
+  use cgmath::Vector2;
+
+  use crate::things::clouds::{back_to_front, cloud_alpha, clouds_from_terrain};
+  use crate::things::{terrain_execute, TerrainParams};
+
+  const SEALEVEL: f32 = 0.01;
+
+  #[test]
+  pub fn test_cloud_alpha() {
+    assert_eq!(cloud_alpha(SEALEVEL, SEALEVEL), 0.0);
+    assert_eq!(cloud_alpha(SEALEVEL, SEALEVEL - 0.1), 0.0);
+    assert_eq!(cloud_alpha(SEALEVEL, SEALEVEL + 1.0), 1.0);
+    let (low, high) = (
+      cloud_alpha(SEALEVEL, SEALEVEL + 0.01),
+      cloud_alpha(SEALEVEL, SEALEVEL + 0.02),
+    );
+    assert!(0.0 < low && low < high && high < 1.0);
+  }
+
+  #[test]
+  pub fn test_clouds_fade_out_at_sea_level() {
+    let params = TerrainParams::clouds();
+    let terrain = terrain_execute(&params, 30.0, 20, 100, 50.0, 50.0);
+    let clouds = clouds_from_terrain(&params, &terrain);
+    assert_eq!(clouds.data.color.len(), clouds.data.vertex.len());
+    assert_eq!(clouds.data.vertex, terrain.mesh.data.vertex);
+    for (color, height) in clouds.data.color.iter().zip(terrain.grid.heights.iter()) {
+      assert_eq!(color.w, cloud_alpha(params.sealevel, *height));
+    }
+  }
+
+  #[test]
+  pub fn test_back_to_front() {
+    let mut items = vec![
+      (Vector2::new(0.0, 0.0), 0),
+      (Vector2::new(300.0, 0.0), 1),
+      (Vector2::new(0.0, -100.0), 2),
+      (Vector2::new(-200.0, 100.0), 3),
+    ];
+    back_to_front(&mut items, Vector2::new(10.0, 0.0));
+    let order: Vec<i32> = items.iter().map(|(_, i)| *i).collect();
+    assert_eq!(order, vec![1, 3, 2, 0]);
+  }
+}
@@ -36,14 +36,14 @@ const HASH: [usize; 512] = [
   0x91, 0xFF, 0xEA, 0x54, 0xFA, 0x66, 0x1A, 0x45, 0x39, 0xCF, 0x75, 0xA4, 0x88, 0xFB, 0x5D,
 ];
 
-fn lerp(t: f32, a: f32, b: f32) -> f32 {
+fn lerp(t: f64, a: f64, b: f64) -> f64 {
   a + t * (b - a)
 }
 
-fn grad(hash_val: usize, x: f32, y: f32, z: f32) -> f32 {
+fn grad(hash_val: usize, x: f64, y: f64, z: f64) -> f64 {
   let hh = hash_val & 15; /* CONVERT LO 4 BITS OF HASH CODE */
-  let uu: f32 = if hh < 8 { x } else { y }; /* INTO 12 GRADIENT DIRECTIONS. */
-  let vv: f32 = if hh < 4 {
+  let uu: f64 = if hh < 8 { x } else { y }; /* INTO 12 GRADIENT DIRECTIONS. */
+  let vv: f64 = if hh < 4 {
     y
   } else if hh == 12 || hh == 14 {
     x
@@ -53,19 +53,19 @@ fn grad(hash_val: usize, x: f32, y: f32, z: f32) -> f32 {
   (if (hh & 1) == 0 { uu } else { -uu }) + (if (hh & 2) == 0 { vv } else { -vv })
 }
 
-fn npfade(t: f32) -> f32 {
+fn npfade(t: f64) -> f64 {
   t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
 }
 
 /* instead of adding another permutation array, just use hash table defined above */
-fn new_perlin(xi: f32, yi: f32, zi: f32) -> f32 {
+fn new_perlin(xi: f64, yi: f64, zi: f64) -> f64 {
   let (mut xrel, mut yrel, mut zrel) = (xi, yi, zi);
-  let mut uu: f32 = xrel.floor();
-  let mut vv: f32 = yrel.floor();
-  let mut ww: f32 = zrel.floor();
-  let xx = ((uu as i32) & 255) as usize;
-  let yy = ((vv as i32) & 255) as usize;
-  let zz = ((ww as i32) & 255) as usize; /* FIND UNIT CUBE THAT CONTAINS POINT */
+  let mut uu: f64 = xrel.floor();
+  let mut vv: f64 = yrel.floor();
+  let mut ww: f64 = zrel.floor();
+  let xx = ((uu as i64) & 255) as usize;
+  let yy = ((vv as i64) & 255) as usize;
+  let zz = ((ww as i64) & 255) as usize; /* FIND UNIT CUBE THAT CONTAINS POINT */
   xrel -= uu; /* FIND RELATIVE X,Y,Z */
   yrel -= vv; /* OF POINT IN CUBE. */
   zrel -= ww;
@@ -109,19 +109,19 @@ fn new_perlin(xi: f32, yi: f32, zi: f32) -> f32 {
   )
 }
 
-fn noisefunc(x: f32, y: f32, z: f32) -> f32 {
+pub fn noisefunc(x: f64, y: f64, z: f64) -> f64 {
   new_perlin(x, y, z)
 }
 
 pub fn hetero_terrain_new_perlin(
-  xi: f32,
-  yi: f32,
-  zi: f32,
-  hh: f32,
-  lacunarity: f32,
-  octaves: f32,
-  offset: f32,
-) -> f32 {
+  xi: f64,
+  yi: f64,
+  zi: f64,
+  hh: f64,
+  lacunarity: f64,
+  octaves: f64,
+  offset: f64,
+) -> f64 {
   let pw_hl = lacunarity.powf(-hh);
   let (mut x, mut y, mut z) = (xi, yi, zi);
 
@@ -138,8 +138,8 @@ pub fn hetero_terrain_new_perlin(
     y *= lacunarity;
     z *= lacunarity;
   }
-  let rmd: f32 = octaves - octaves.floor();
-  if rmd <= f32::EPSILON {
+  let rmd: f64 = octaves - octaves.floor();
+  if rmd <= f64::EPSILON {
     let increment = (noisefunc(x, y, z) + offset) * pwr * value;
     value += rmd * increment;
   }
@@ -2,7 +2,7 @@ use vulkano::device::Device;
 
 use cgmath::{Matrix4, Rad, Vector3};
 
-use crate::render::from_gltf;
+use crate::render::from_gltf_merged;
 use crate::render::Model;
 use crate::render::MyMesh;
 
@@ -21,7 +21,7 @@ pub struct Lap {
 
 impl LapMesh {
   pub fn new() -> Self {
-    let mut mesh = from_gltf(Path::new("models/lep.glb"), false);
+    let mut mesh = from_gltf_merged(Path::new("models/lep.glb"), false);
     mesh.reset_transform();
     mesh.update_transform_2(
       Vector3::<f32>::new(0.0, 0.0, 0.0),
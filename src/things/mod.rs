@@ -1,12 +1,20 @@
+mod biome;
+mod clouds;
 mod counting_avg;
 mod hetero_terrain;
 mod lap;
 mod primitives;
+mod terrain_cache;
 mod terrain_generation;
 mod texts;
+mod water;
 
+pub use self::biome::*;
+pub use self::clouds::*;
 pub use self::counting_avg::*;
 pub use self::lap::*;
 pub use self::primitives::*;
+pub use self::terrain_cache::*;
 pub use self::terrain_generation::*;
 pub use self::texts::*;
+pub use self::water::*;
@@ -0,0 +1,259 @@
+use std::convert::TryInto;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::things::{TerrainGrid, TerrainParams};
+
+// bump when the file layout or the way heights are generated changes
+const VERSION: u32 = 3;
+const MAGIC: &[u8; 4] = b"GGTC";
+
+#[derive(Debug, Clone)]
+pub struct TerrainCache {
+  dir: PathBuf,
+  seed: i32,
+  params_hash: u64,
+  sub_division: i32,
+}
+
+impl TerrainCache {
+  pub fn new(dir: &Path, params: &TerrainParams, sub_division: i32, mesh_size: i32) -> Self {
+    let mut bytes = VERSION.to_le_bytes().to_vec();
+    bytes.extend(params.to_le_bytes());
+    bytes.extend_from_slice(&sub_division.to_le_bytes());
+    bytes.extend_from_slice(&mesh_size.to_le_bytes());
+    TerrainCache {
+      dir: dir.to_path_buf(),
+      seed: params.seed,
+      params_hash: fnv1a(&bytes),
+      sub_division,
+    }
+  }
+
+  fn prefix(&self) -> String {
+    format!("tile_{}_{:016x}_", self.seed, self.params_hash)
+  }
+
+  fn path(&self, tile: (isize, isize)) -> PathBuf {
+    self
+      .dir
+      .join(format!("{}{}_{}.bin", self.prefix(), tile.0, tile.1))
+  }
+
+  pub fn load(&self, tile: (isize, isize)) -> Option<TerrainGrid> {
+    let bytes = fs::read(self.path(tile)).ok()?;
+    let grid = self.decode(&bytes);
+    if grid.is_none() {
+      println!("terrain cache: ignoring broken tile {:?}", tile);
+    }
+    grid
+  }
+
+  pub fn store(&self, tile: (isize, isize), grid: &TerrainGrid) -> io::Result<()> {
+    fs::create_dir_all(&self.dir)?;
+    // write to a temporary file first so a crash never leaves a half written tile behind
+    let path = self.path(tile);
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, self.encode(grid))?;
+    fs::rename(tmp, path)
+  }
+
+  // removes tiles written by other versions, seeds or params
+  pub fn invalidate_stale(&self) -> io::Result<usize> {
+    let entries = match fs::read_dir(&self.dir) {
+      Ok(entries) => entries,
+      Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+      Err(e) => return Err(e),
+    };
+    let prefix = self.prefix();
+    let mut removed = 0;
+    for entry in entries {
+      let entry = entry?;
+      let name = entry.file_name();
+      let name = name.to_string_lossy();
+      if name.starts_with("tile_") && !name.starts_with(&prefix) {
+        fs::remove_file(entry.path())?;
+        removed += 1;
+      }
+    }
+    Ok(removed)
+  }
+
+  fn encode(&self, grid: &TerrainGrid) -> Vec<u8> {
+    let mut out = Vec::with_capacity(32 + 4 * (2 * grid.heights.len() + 4 * grid.left.len()));
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&VERSION.to_le_bytes());
+    out.extend_from_slice(&self.params_hash.to_le_bytes());
+    out.extend_from_slice(&grid.sub_division.to_le_bytes());
+    for values in &[
+      &grid.heights,
+      &grid.moisture,
+      &grid.left,
+      &grid.right,
+      &grid.top,
+      &grid.bottom,
+    ] {
+      out.extend_from_slice(&(values.len() as u32).to_le_bytes());
+      for value in values.iter() {
+        out.extend_from_slice(&value.to_le_bytes());
+      }
+    }
+    out
+  }
+
+  fn decode(&self, bytes: &[u8]) -> Option<TerrainGrid> {
+    let mut reader = Reader { bytes, pos: 0 };
+    if reader.take(4)? != MAGIC
+      || reader.u32()? != VERSION
+      || reader.u64()? != self.params_hash
+    {
+      return None;
+    }
+    let sub_division = reader.u32()? as i32;
+    if sub_division != self.sub_division {
+      return None;
+    }
+    let heights = reader.f32s()?;
+    let moisture = reader.f32s()?;
+    let left = reader.f32s()?;
+    let right = reader.f32s()?;
+    let top = reader.f32s()?;
+    let bottom = reader.f32s()?;
+    let side = sub_division as usize;
+    if heights.len() != side * side
+      || moisture.len() != side * side
+      || [&left, &right, &top, &bottom].iter().any(|v| v.len() != side)
+      || reader.pos != bytes.len()
+    {
+      return None;
+    }
+    Some(TerrainGrid {
+      sub_division,
+      heights,
+      moisture,
+      left,
+      right,
+      top,
+      bottom,
+    })
+  }
+}
+
+struct Reader<'a> {
+  bytes: &'a [u8],
+  pos: usize,
+}
+
+impl<'a> Reader<'a> {
+  fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+    let end = self.pos.checked_add(n)?;
+    let res = self.bytes.get(self.pos..end)?;
+    self.pos = end;
+    Some(res)
+  }
+
+  fn u32(&mut self) -> Option<u32> {
+    Some(u32::from_le_bytes(self.take(4)?.try_into().ok()?))
+  }
+
+  fn u64(&mut self) -> Option<u64> {
+    Some(u64::from_le_bytes(self.take(8)?.try_into().ok()?))
+  }
+
+  fn f32s(&mut self) -> Option<Vec<f32>> {
+    let len = self.u32()? as usize;
+    let raw = self.take(len.checked_mul(4)?)?;
+    Some(
+      raw
+        .chunks_exact(4)
+        .map(|c| f32::from_le_bytes(c.try_into().unwrap()))
+        .collect(),
+    )
+  }
+}
+
+// 64 bit fnv-1a, unlike DefaultHasher it does not change between rust releases, so a toolchain
+// upgrade keeps the tiles on disk
+fn fnv1a(bytes: &[u8]) -> u64 {
+  bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+    (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+  })
+}
+
+#[cfg(test)]
+mod test {
+  use crate::things::terrain_cache::{fnv1a, TerrainCache};
+  use crate::things::{terrain_grid, TerrainParams};
+
+  use std::fs;
+  use std::path::PathBuf;
+
+  const SIZE: i32 = 100;
+  const DETAIL: i32 = 10;
+
+  fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("ggga_{}_{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    dir
+  }
+
+  #[test]
+  pub fn test_write_read() {
+    let dir = temp_dir("write_read");
+    let params = TerrainParams::default();
+    let cache = TerrainCache::new(&dir, &params, DETAIL, SIZE);
+    let grid = terrain_grid(&params, DETAIL, SIZE, 50.0, -50.0);
+
+    assert_eq!(cache.load((0, -1)), None);
+    cache.store((0, -1), &grid).unwrap();
+    assert_eq!(cache.load((0, -1)), Some(grid));
+    assert_eq!(cache.load((1, -1)), None);
+    fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  pub fn test_fnv1a() {
+    // the reference values of the fnv test suite
+    assert_eq!(fnv1a(b""), 0xcbf29ce484222325);
+    assert_eq!(fnv1a(b"a"), 0xaf63dc4c8601ec8c);
+    assert_eq!(fnv1a(b"foobar"), 0x85944171f73967e8);
+  }
+
+  #[test]
+  pub fn test_params_change_invalidates() {
+    let dir = temp_dir("invalidate");
+    let params = TerrainParams::default();
+    let cache = TerrainCache::new(&dir, &params, DETAIL, SIZE);
+    let grid = terrain_grid(&params, DETAIL, SIZE, 50.0, 50.0);
+    cache.store((0, 0), &grid).unwrap();
+
+    let changed = TerrainParams {
+      sealevel: -0.5,
+      ..TerrainParams::default()
+    };
+    let changed_cache = TerrainCache::new(&dir, &changed, DETAIL, SIZE);
+    assert_eq!(changed_cache.load((0, 0)), None);
+    let other_detail = TerrainCache::new(&dir, &params, DETAIL + 1, SIZE);
+    assert_eq!(other_detail.load((0, 0)), None);
+
+    assert_eq!(changed_cache.invalidate_stale().unwrap(), 1);
+    assert_eq!(cache.load((0, 0)), None);
+    fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  pub fn test_truncated_tile_is_ignored() {
+    let dir = temp_dir("truncated");
+    let params = TerrainParams::default();
+    let cache = TerrainCache::new(&dir, &params, DETAIL, SIZE);
+    let grid = terrain_grid(&params, DETAIL, SIZE, 50.0, 50.0);
+    cache.store((0, 0), &grid).unwrap();
+
+    let path = cache.path((0, 0));
+    let bytes = fs::read(&path).unwrap();
+    fs::write(&path, &bytes[..bytes.len() - 3]).unwrap();
+    assert_eq!(cache.load((0, 0)), None);
+    fs::remove_dir_all(&dir).unwrap();
+  }
+}
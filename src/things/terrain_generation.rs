@@ -1,20 +1,42 @@
 // translation of https://github.com/sftd/blender-addons/blob/master/add_mesh_ant_landscape.py
 // into rust
 
-use cgmath::{Matrix4, One, Point2, Point3, Vector3};
+use cgmath::{InnerSpace, Matrix4, One, Point2, Point3, Vector3};
 use genmesh::{MapToVertices, Neighbors, Polygon, Quad, Triangle, Triangulate, Vertices};
 use mint::Vector3 as MintVector3;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
 use rand_distr::{Distribution, UnitSphere};
 use profiling;
-use crate::render::MyMesh;
-use crate::things::hetero_terrain::hetero_terrain_new_perlin;
+
+use crate::render::{InterestingMeshData, MyMesh};
+use crate::things::biome::Biome;
+use crate::things::hetero_terrain::{hetero_terrain_new_perlin, noisefunc};
 use crate::utils::{Face, Vertex};
 
+// the noise origin has to be a pure function of the seed, otherwise
+// two tiles sampling the same world coordinate would not agree
+fn noise_origin(rseed: i32) -> (f64, f64, f64) {
+  if rseed == 0 {
+    (0.0, 0.0, 0.0)
+  } else {
+    let mut rng = StdRng::seed_from_u64(rseed as u64);
+    let v: [f32; 3] = UnitSphere.sample(&mut rng);
+    (
+      (0.5 - v[0] as f64) * 1000.0,
+      (0.5 - v[1] as f64) * 1000.0,
+      (0.5 - v[2] as f64) * 1000.0,
+    )
+  }
+}
+
+// x, y, z are absolute world coordinates, they are kept in f64 so the noise
+// stays precise far away from the origin
 fn landscape_gen(
-  x: f32,
-  y: f32,
-  z: f32,
-  rseed: i32,
+  x: f64,
+  y: f64,
+  z: f64,
+  origin: (f64, f64, f64),
   nsize: f32,
   depth: f32,
   dimension: f32,
@@ -132,27 +154,19 @@ fn landscape_gen(
 
       return value
   */
-  let (origin_x, origin_y, origin_z) = if rseed == 0 {
-    (0.0, 0.0, 0.0)
-  } else {
-    let v: [f32; 3] = UnitSphere.sample(&mut rand::thread_rng());
-    (
-      (0.5 - v[0]) * 1000.0,
-      (0.5 - v[1]) * 1000.0,
-      (0.5 - v[2]) * 1000.0,
-    )
-  };
+  let (origin_x, origin_y, origin_z) = origin;
 
+  let nsize = nsize as f64;
   let ncoords = (
     x / nsize + origin_x,
     y / nsize + origin_y,
     z / nsize + origin_z,
   );
-  let mut value = hetero_terrain_new_perlin(
-    ncoords.0, ncoords.1, ncoords.2, dimension, /*-H*/
-    lacunarity, depth, /* octaves */
-    offset,
-  ) * 0.25;
+  let mut value = (hetero_terrain_new_perlin(
+    ncoords.0, ncoords.1, ncoords.2, dimension as f64, /*-H*/
+    lacunarity as f64, depth as f64, /* octaves */
+    offset as f64,
+  ) * 0.25) as f32;
 
   value = value * height + heightoffset;
   if value < sealevel {
@@ -164,6 +178,18 @@ fn landscape_gen(
   value
 }
 
+// moisture varies slower than the height, it only decides between dry and green land
+const MOISTURE_SIZE: f64 = 150.0;
+
+fn moisture_gen(x: f64, y: f64, origin: (f64, f64, f64)) -> f32 {
+  let value = noisefunc(
+    x / MOISTURE_SIZE + origin.1,
+    y / MOISTURE_SIZE + origin.2,
+    origin.0,
+  );
+  (0.5 + 0.5 * value).max(0.0).min(1.0) as f32
+}
+
 fn create_faces(out_faces: &mut Vec<Face>, vert_idx_1: &[u32], vert_idx_2: &[u32]) {
   /*
   # A very simple "bridge" tool.
@@ -293,21 +319,163 @@ fn create_faces(out_faces: &mut Vec<Face>, vert_idx_1: &[u32], vert_idx_2: &[u32
   }
 }
 
-fn grid_gen(
+#[derive(Debug, Clone, PartialEq)]
+pub struct TerrainParams {
+  pub seed: i32,
+  pub nsize: f32,
+  pub depth: f32,
+  pub dimension: f32,
+  pub lacunarity: f32,
+  pub offset: f32,
+  pub height: f32,
+  pub heightoffset: f32,
+  pub sealevel: f32,
+  pub platlevel: f32,
+}
+
+impl Default for TerrainParams {
+  fn default() -> TerrainParams {
+    TerrainParams {
+      seed: 1,
+      nsize: 0.33,
+      depth: 8.0,
+      dimension: 0.95,
+      lacunarity: 2.20,
+      offset: 0.50,
+      height: 0.23,
+      heightoffset: 0.0,
+      sealevel: -1.0,
+      platlevel: 1.0,
+    }
+  }
+}
+
+impl TerrainParams {
+  // the sky terrain of cloud mode, the valleys clamped at the sea level are the gaps between clouds
+  pub fn clouds() -> TerrainParams {
+    TerrainParams {
+      sealevel: 0.01,
+      ..TerrainParams::default()
+    }
+  }
+
+  // solid land under the clouds, flatter and with its own noise so it does not mirror them
+  pub fn ground() -> TerrainParams {
+    TerrainParams {
+      seed: 2,
+      nsize: 0.5,
+      height: 0.15,
+      // a little above the lowest valleys, so some of them end up under water
+      sealevel: 0.01,
+      ..TerrainParams::default()
+    }
+  }
+}
+
+impl TerrainParams {
+  // every param in little endian, the same on every platform and toolchain
+  pub fn to_le_bytes(&self) -> Vec<u8> {
+    let mut bytes = self.seed.to_le_bytes().to_vec();
+    for value in &[
+      self.nsize,
+      self.depth,
+      self.dimension,
+      self.lacunarity,
+      self.offset,
+      self.height,
+      self.heightoffset,
+      self.sealevel,
+      self.platlevel,
+    ] {
+      bytes.extend_from_slice(&value.to_bits().to_le_bytes());
+    }
+    bytes
+  }
+}
+
+// heights of a sub_division x sub_division grid, row_x major, before the height scale
+#[derive(Debug, Clone, PartialEq)]
+pub struct TerrainGrid {
+  pub sub_division: i32,
+  pub heights: Vec<f32>,
+  pub moisture: Vec<f32>,
+  pub left: Vec<f32>,
+  pub right: Vec<f32>,
+  pub top: Vec<f32>,
+  pub bottom: Vec<f32>,
+}
+
+// x, y is the absolute world position of the grid center, the noise is sampled
+// in world space so neighbouring grids agree on their shared borders
+#[profiling::function]
+pub fn terrain_grid(
+  params: &TerrainParams,
   sub_division: i32,
   mesh_size: i32,
-  oleft: Option<Vec<f32>>,
-  oright: Option<Vec<f32>>,
-  otop: Option<Vec<f32>>,
-  obottom: Option<Vec<f32>>,
-) -> (
-  Vec<Vertex>,
-  Vec<Face>,
-  Vec<f32>,
-  Vec<f32>,
-  Vec<f32>,
-  Vec<f32>,
-) {
+  x: f64,
+  y: f64,
+) -> TerrainGrid {
+  let start = -(mesh_size as f32) / 2.0;
+  let origin = noise_origin(params.seed);
+  let mut heights: Vec<f32> = vec![];
+  let mut moisture: Vec<f32> = vec![];
+  let mut left: Vec<f32> = vec![];
+  let mut right: Vec<f32> = vec![];
+  let mut top: Vec<f32> = vec![];
+  let mut bottom: Vec<f32> = vec![];
+  for row_x in 0..sub_division {
+    let lx = grid_coord(start, row_x, sub_division, mesh_size);
+    for row_y in 0..sub_division {
+      let ly = grid_coord(start, row_y, sub_division, mesh_size);
+      /*
+
+          fn landscape_gen(x: f32, y:f32, z:f32,mesh_size: i32,
+      rseed: i32, nsize: f32, nbasis: f32, depth: f32, dimension: f32, lacunarity: f32,
+      offset: f32, invert: f32, height:f32, heightoffset:f32, sealevel: f32, platlevel: f32) -> f32 {
+      */
+      let z = landscape_gen(
+        x + lx as f64,
+        y + ly as f64,
+        0.0,
+        origin,
+        params.nsize,
+        params.depth,
+        params.dimension,
+        params.lacunarity,
+        params.offset,
+        params.height,
+        params.heightoffset,
+        params.sealevel,
+        params.platlevel,
+      );
+      if row_x == 0 {
+        left.push(z);
+      }
+      if row_x == sub_division - 1 {
+        right.push(z);
+      }
+      if row_y == 0 {
+        top.push(z);
+      }
+      if row_y == sub_division - 1 {
+        bottom.push(z);
+      }
+      heights.push(z);
+      moisture.push(moisture_gen(x + lx as f64, y + ly as f64, origin));
+    }
+  }
+  TerrainGrid {
+    sub_division,
+    heights,
+    moisture,
+    left,
+    right,
+    top,
+    bottom,
+  }
+}
+
+fn grid_gen(grid: &TerrainGrid, mesh_size: i32) -> (Vec<Vertex>, Vec<Face>) {
   /*
   verts = []
       faces = []
@@ -336,77 +504,21 @@ fn grid_gen(
   */
   let mut verts: Vec<Vertex> = vec![];
   let mut faces: Vec<Face> = vec![];
-  let delta = (mesh_size as f32) / ((sub_division - 1) as f32);
-  let start = -(mesh_size / 2);
+  let sub_division = grid.sub_division;
+  let start = -(mesh_size as f32) / 2.0;
   let mut edgeloop_prev: Vec<u32> = vec![];
-  let mut left: Vec<f32> = vec![];
-  let mut right: Vec<f32> = vec![];
-  let mut top: Vec<f32> = vec![];
-  let mut bottom: Vec<f32> = vec![];
   for row_x in 0..sub_division {
     let mut edgeloop_cur: Vec<u32> = vec![];
-    let x = (start as f32) + (row_x as f32) * delta;
+    let lx = grid_coord(start, row_x, sub_division, mesh_size);
     for row_y in 0..sub_division {
-      let y = (start as f32) + (row_y as f32) * delta;
-      /*
-
-          fn landscape_gen(x: f32, y:f32, z:f32,mesh_size: i32,
-      rseed: i32, nsize: f32, nbasis: f32, depth: f32, dimension: f32, lacunarity: f32,
-      offset: f32, invert: f32, height:f32, heightoffset:f32, sealevel: f32, platlevel: f32) -> f32 {
-      */
-      let nsize = 0.33;
-      let depth = 8.0;
-      let dimension = 0.95;
-      let lacunarity = 2.20;
-      let offset = 0.50;
-      let height = 0.23;
-      let heightoffset = 0.0;
-      let sealevel = -1.0;
-      let platlevel = 1.0;
-      let mut z = landscape_gen(
-        x,
-        y,
-        0.0,
-        1,
-        nsize,
-        depth,
-        dimension,
-        lacunarity,
-        offset,
-        height,
-        heightoffset,
-        sealevel,
-        platlevel,
-      );
-      if row_x == 0 {
-        if let Some(o) = oleft.as_ref().unwrap_or(&vec![]).get(row_y as usize) {
-          z = *o;
-        }
-        left.push(z);
-      }
-      if row_x == sub_division - 1 {
-        if let Some(o) = oright.as_ref().unwrap_or(&vec![]).get(row_y as usize) {
-          z = *o;
-        }
-        right.push(z);
-      }
-      if row_y == 0 {
-        if let Some(o) = otop.as_ref().unwrap_or(&vec![]).get(row_x as usize) {
-          z = *o;
-        }
-        top.push(z);
-      }
-      if row_y == sub_division - 1 {
-        if let Some(o) = obottom.as_ref().unwrap_or(&vec![]).get(row_x as usize) {
-          z = *o;
-        }
-        bottom.push(z);
-      }
+      let ly = grid_coord(start, row_y, sub_division, mesh_size);
+      let z = grid.heights[(row_x * sub_division + row_y) as usize];
       edgeloop_cur.push(verts.len() as u32);
       verts.push(Vertex {
-        position: (x, y, z),
+        position: (lx, ly, z),
         tex: (-1.0, -1.0),
         tex_offset: (0, 0),
+        color: (1.0, 1.0, 1.0, 1.0),
       });
     }
     if !edgeloop_prev.is_empty() {
@@ -414,32 +526,43 @@ fn grid_gen(
     }
     edgeloop_prev = edgeloop_cur;
   }
-  (verts, faces, left, right, top, bottom)
+  (verts, faces)
+}
+
+// the last row lands exactly on start + mesh_size, which is the first row of the next grid
+fn grid_coord(start: f32, row: i32, sub_division: i32, mesh_size: i32) -> f32 {
+  start + ((row * mesh_size) as f32) / ((sub_division - 1) as f32)
 }
 
 #[derive(Clone)]
 pub struct TerrainModel {
   pub mesh: MyMesh,
-  pub left: Vec<f32>,
-  pub right: Vec<f32>,
-  pub top: Vec<f32>,
-  pub bottom: Vec<f32>,
+  pub grid: TerrainGrid,
 }
 
 #[profiling::function]
 pub fn terrain_execute(
+  params: &TerrainParams,
   scale: f32,
   sub_division: i32,
   mesh_size: i32,
   x: f32,
   z: f32,
-  oleft: Option<Vec<f32>>,
-  oright: Option<Vec<f32>>,
-  otop: Option<Vec<f32>>,
-  obottom: Option<Vec<f32>>,
 ) -> TerrainModel {
-  let (verts, faces, left, right, top, bottom) =
-    grid_gen(sub_division, mesh_size, oleft, oright, otop, obottom);
+  let grid = terrain_grid(params, sub_division, mesh_size, x as f64, z as f64);
+  terrain_from_grid(params, grid, scale, mesh_size, x, z)
+}
+
+#[profiling::function]
+pub fn terrain_from_grid(
+  params: &TerrainParams,
+  grid: TerrainGrid,
+  scale: f32,
+  mesh_size: i32,
+  x: f32,
+  z: f32,
+) -> TerrainModel {
+  let (verts, faces) = grid_gen(&grid, mesh_size);
   let vertex: Vec<Point3<f32>> = verts
     .iter()
     .map(|v| Point3::new(v.position.0, v.position.2 * scale, v.position.1))
@@ -470,20 +593,68 @@ pub fn terrain_execute(
     .map(|_i| Point2::new(-1.0, -1.0))
     .collect();
   let tex_offset = (0..vertex.len()).map(|_i| Point2::new(0, 0)).collect();
+  let color = verts
+    .iter()
+    .zip(normals.iter())
+    .zip(grid.moisture.iter())
+    .map(|((v, n), moisture)| {
+      let slope = 1.0 - Vector3::new(n.x, n.y, n.z).normalize().y.abs();
+      Biome::classify(params.sealevel, v.position.2, slope, *moisture).color()
+    })
+    .collect();
   let transform = <Matrix4<f32> as One>::one();
 
-  let mut mesh = MyMesh::new(vertex, tex, tex_offset, normals, index, transform, false);
+  let mut mesh = MyMesh::new_interesting(
+    vertex,
+    tex,
+    tex_offset,
+    color,
+    normals,
+    index,
+    transform,
+    false,
+    InterestingMeshData::default(),
+  );
   mesh.update_transform_2(
     Vector3::new(x, 0.0, z),
     Matrix4::<f32>::one(),
     //Matrix4::from_angle_x(Rad(std::f32::consts::FRAC_PI_2)),
     [1.0, 1.0, 1.0],
   );
-  TerrainModel {
-    mesh: mesh,
-    left,
-    right,
-    top,
-    bottom,
+  TerrainModel { mesh: mesh, grid }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::things::terrain_generation::{terrain_execute, TerrainGrid, TerrainParams};
+
+  const SIZE: i32 = 100;
+  const DETAIL: i32 = 20;
+
+  fn tile(x: f32, z: f32) -> TerrainGrid {
+    terrain_execute(&TerrainParams::default(), 30.0, DETAIL, SIZE, x, z).grid
+  }
+
+  #[test]
+  pub fn test_horizontal_neighbours_share_border() {
+    let right_first = tile(150.0, 50.0);
+    let left_second = tile(50.0, 50.0);
+    assert_eq!(left_second.right, right_first.left);
+
+    let left_first = tile(50.0, 50.0);
+    let right_second = tile(150.0, 50.0);
+    assert_eq!(left_first.right, right_second.left);
+    assert_eq!(left_first.right, left_second.right);
+  }
+
+  #[test]
+  pub fn test_vertical_and_diagonal_neighbours_share_border() {
+    let bottom = tile(-50.0, 50.0);
+    let top = tile(-50.0, -50.0);
+    assert_eq!(top.bottom, bottom.top);
+
+    let diagonal = tile(50.0, 50.0);
+    assert_eq!(top.bottom.last(), diagonal.top.first());
+    assert_eq!(bottom.right.first(), diagonal.left.first());
   }
 }
@@ -0,0 +1,167 @@
+use cgmath::{Matrix4, One, Point2, Point3, Vector3};
+use profiling;
+
+use crate::render::MyMesh;
+use crate::things::{TerrainGrid, TerrainParams};
+
+// keeps the water surface just above the flat floors the terrain is clamped to
+const WATER_LIFT: f32 = 0.05;
+
+// cells (row_x, row_y) of the grid with at least one corner at or below the sea level,
+// the terrain clamps its heights to the sea level so those corners are exactly on it
+pub fn water_cells(grid: &TerrainGrid, sealevel: f32) -> Vec<(i32, i32)> {
+  let sub_division = grid.sub_division;
+  let height = |row_x: i32, row_y: i32| grid.heights[(row_x * sub_division + row_y) as usize];
+  let mut cells = vec![];
+  for row_x in 0..(sub_division - 1) {
+    for row_y in 0..(sub_division - 1) {
+      let submerged = [(0, 0), (1, 0), (0, 1), (1, 1)]
+        .iter()
+        .any(|(dx, dy)| height(row_x + dx, row_y + dy) <= sealevel);
+      if submerged {
+        cells.push((row_x, row_y));
+      }
+    }
+  }
+  cells
+}
+
+// one quad per submerged cell, in the same local coordinates as the terrain mesh
+pub fn water_gen(
+  grid: &TerrainGrid,
+  sealevel: f32,
+  scale: f32,
+  mesh_size: i32,
+) -> (Vec<Point3<f32>>, Vec<u32>) {
+  let sub_division = grid.sub_division;
+  let start = -(mesh_size as f32) / 2.0;
+  let coord = |row: i32| start + ((row * mesh_size) as f32) / ((sub_division - 1) as f32);
+  let y = sealevel * scale + WATER_LIFT;
+  let mut vertex = vec![];
+  let mut index = vec![];
+  for (row_x, row_y) in water_cells(grid, sealevel) {
+    let base = vertex.len() as u32;
+    let (x0, x1) = (coord(row_x), coord(row_x + 1));
+    let (z0, z1) = (coord(row_y), coord(row_y + 1));
+    vertex.push(Point3::new(x0, y, z0));
+    vertex.push(Point3::new(x1, y, z0));
+    vertex.push(Point3::new(x1, y, z1));
+    vertex.push(Point3::new(x0, y, z1));
+    index.extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+  }
+  (vertex, index)
+}
+
+#[profiling::function]
+pub fn water_from_grid(
+  params: &TerrainParams,
+  grid: &TerrainGrid,
+  scale: f32,
+  mesh_size: i32,
+  x: f32,
+  z: f32,
+) -> Option<MyMesh> {
+  let (vertex, index) = water_gen(grid, params.sealevel, scale, mesh_size);
+  if index.is_empty() {
+    return None;
+  }
+  // same inverted convention as the terrain normals
+  let normals = vec![Point3::new(0.0, -1.0, 0.0); vertex.len()];
+  let tex = vec![Point2::new(-1.0, -1.0); vertex.len()];
+  let tex_offset = vec![Point2::new(0, 0); vertex.len()];
+  let mut mesh = MyMesh::new(
+    vertex,
+    tex,
+    tex_offset,
+    normals,
+    index,
+    Matrix4::one(),
+    false,
+  );
+  mesh.update_transform_2(
+    Vector3::new(x, 0.0, z),
+    Matrix4::<f32>::one(),
+    [1.0, 1.0, 1.0],
+  );
+  Some(mesh)
+}
+
+#[cfg(test)]
+mod test {
+  use crate::things::water::{water_cells, water_gen};
+  use crate::things::{terrain_grid, TerrainGrid, TerrainParams};
+
+  const SEALEVEL: f32 = 0.0;
+
+  fn grid(heights: Vec<f32>) -> TerrainGrid {
+    let sub_division = (heights.len() as f64).sqrt() as i32;
+    TerrainGrid {
+      sub_division,
+      moisture: vec![0.5; heights.len()],
+      heights,
+      left: vec![],
+      right: vec![],
+      top: vec![],
+      bottom: vec![],
+    }
+  }
+
+  #[test]
+  pub fn test_quads_only_for_submerged_cells() {
+    // a single point at the sea level touches the four cells around it
+    #[rustfmt::skip]
+    let heights = vec![
+      0.1, 0.1, 0.1, 0.1,
+      0.1, 0.1, 0.1, 0.1,
+      0.1, 0.1, SEALEVEL, 0.1,
+      0.1, 0.1, 0.1, 0.1,
+    ];
+    let grid = grid(heights);
+    assert_eq!(
+      water_cells(&grid, SEALEVEL),
+      vec![(1, 1), (1, 2), (2, 1), (2, 2)]
+    );
+    let (vertex, index) = water_gen(&grid, SEALEVEL, 30.0, 30);
+    assert_eq!(vertex.len(), 4 * 4);
+    assert_eq!(index.len(), 4 * 6);
+    // the first quad covers the cell (1, 1), which spans 10 units in each direction
+    let xs: Vec<f32> = vertex[0..4].iter().map(|v| v.x).collect();
+    let zs: Vec<f32> = vertex[0..4].iter().map(|v| v.z).collect();
+    assert_eq!(xs, vec![-5.0, 5.0, 5.0, -5.0]);
+    assert_eq!(zs, vec![-5.0, -5.0, 5.0, 5.0]);
+  }
+
+  #[test]
+  pub fn test_no_quads_above_sea_level() {
+    let grid = grid(vec![0.1; 16]);
+    assert!(water_cells(&grid, SEALEVEL).is_empty());
+    let (vertex, index) = water_gen(&grid, SEALEVEL, 30.0, 30);
+    assert!(vertex.is_empty());
+    assert!(index.is_empty());
+  }
+
+  #[test]
+  pub fn test_generated_terrain() {
+    let params = TerrainParams {
+      sealevel: 0.01,
+      ..TerrainParams::default()
+    };
+    let sealevel = params.sealevel;
+    let sub_division = 30;
+    let grid = terrain_grid(&params, sub_division, 100, 50.0, 50.0);
+    let cells = water_cells(&grid, sealevel);
+    // this tile has a lake once the sea is a little above the lowest valleys
+    assert!(!cells.is_empty());
+    let (_, index) = water_gen(&grid, sealevel, 30.0, 100);
+    assert_eq!(index.len(), cells.len() * 6);
+    for row_x in 0..(sub_division - 1) {
+      for row_y in 0..(sub_division - 1) {
+        let lowest = [(0, 0), (1, 0), (0, 1), (1, 1)]
+          .iter()
+          .map(|(dx, dy)| grid.heights[((row_x + dx) * sub_division + row_y + dy) as usize])
+          .fold(f32::MAX, f32::min);
+        assert_eq!(cells.contains(&(row_x, row_y)), lowest <= sealevel);
+      }
+    }
+  }
+}
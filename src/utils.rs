@@ -3,16 +3,51 @@ pub struct Vertex {
   pub position: (f32, f32, f32),
   pub tex: (f32, f32),
   pub tex_offset: (i32, i32),
+  pub color: (f32, f32, f32, f32),
 }
 
-vulkano::impl_vertex!(Vertex, position, tex, tex_offset);
+vulkano::impl_vertex!(Vertex, position, tex, tex_offset, color);
 
+// vertex and normal interleaved, what models are drawn from
 #[derive(Default, Copy, Clone, Debug)]
-pub struct Normal {
+pub struct MeshVertex {
+  pub position: (f32, f32, f32),
+  pub tex: (f32, f32),
+  pub tex_offset: (i32, i32),
   pub normal: (f32, f32, f32),
+  pub color: (f32, f32, f32, f32),
+}
+
+vulkano::impl_vertex!(MeshVertex, position, tex, tex_offset, normal, color);
+
+// a colored point of a debug line, see render::DebugLines
+#[derive(Default, Copy, Clone, Debug, PartialEq)]
+pub struct LineVertex {
+  pub position: (f32, f32, f32),
+  pub color: (f32, f32, f32, f32),
+}
+
+vulkano::impl_vertex!(LineVertex, position, color);
+
+// per instance data, the columns of the instance transform and the rect of the
+// texture the unit tex coordinates of the mesh are mapped into
+#[derive(Default, Copy, Clone, Debug, PartialEq)]
+pub struct Instance {
+  pub instance_x: (f32, f32, f32, f32),
+  pub instance_y: (f32, f32, f32, f32),
+  pub instance_z: (f32, f32, f32, f32),
+  pub instance_w: (f32, f32, f32, f32),
+  pub instance_tex_rect: (f32, f32, f32, f32),
 }
 
-vulkano::impl_vertex!(Normal, normal);
+vulkano::impl_vertex!(
+  Instance,
+  instance_x,
+  instance_y,
+  instance_z,
+  instance_w,
+  instance_tex_rect
+);
 
 use genmesh::Polygon;
 pub type Face = Polygon<u32>;